@@ -7,20 +7,41 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use gstopentok::common::Credentials;
+use gstopentok::common::{Credentials, IpcMessage, IpcPeers, OpenTokUri};
+use ipc_channel::ipc::{self, IpcReceiver, IpcSender};
 
-#[derive(Debug)]
-pub enum Direction {
-    Source,
-    Sink,
-}
+pub use gstopentok::common::RemoteDirection as Direction;
 
 #[derive(Debug)]
 pub struct Settings {
-    pub credentials: Credentials,
-    pub stream_id: Option<String>,
-    pub ipc_server: String,
+    /// Location of the wrapped element, credentials included.
+    pub uri: OpenTokUri,
     pub direction: Direction,
+    /// Extra properties of the wrapped element, as `(name, value)` strings.
+    pub properties: Vec<(String, String)>,
+    /// Messages to the main process.
+    pub ipc_sender: IpcSender<IpcMessage>,
+    /// Messages from the main process.
+    pub ipc_receiver: IpcReceiver<IpcMessage>,
+}
+
+/// Connects to the one shot server of the main process and hands over the
+/// channels used from then on in both directions.
+fn connect_to_parent(ipc_server: &str) -> Option<(IpcSender<IpcMessage>, IpcReceiver<IpcMessage>)> {
+    let (ipc_sender, parent_receiver) = ipc::channel().ok()?;
+    let (parent_sender, ipc_receiver) = ipc::channel().ok()?;
+    let oneshot_sender: IpcSender<IpcPeers> = match IpcSender::connect(ipc_server.into()) {
+        Ok(sender) => sender,
+        Err(err) => {
+            eprintln!(
+                "===> Cannot connect to IPC server {}: {:?}",
+                ipc_server, err
+            );
+            return None;
+        }
+    };
+    oneshot_sender.send((parent_sender, parent_receiver)).ok()?;
+    Some((ipc_sender, ipc_receiver))
 }
 
 pub async fn parse_cli() -> Option<Settings> {
@@ -30,16 +51,75 @@ pub async fn parse_cli() -> Option<Settings> {
     let mut app = clap_serde::yaml_to_app(&yaml).ok()?;
     let matches = app.clone().get_matches();
 
+    let ipc_server = match matches.value_of("ipc_server") {
+        Some(name) => name,
+        None => {
+            eprintln!("===> No ipc_server!");
+            app.print_help().unwrap();
+            return None;
+        }
+    };
+
+    // The elements hand the credentials over IPC, so that they don't leak
+    // through the process list. Passing them as arguments is only meant for
+    // standalone use.
+    let standalone = [
+        "location",
+        "api_key",
+        "session_id",
+        "token",
+        "room_uri",
+        "credentials_file",
+    ]
+    .iter()
+    .any(|arg| matches.is_present(arg));
+
+    let (ipc_sender, ipc_receiver) = connect_to_parent(ipc_server)?;
+
+    if !standalone {
+        let settings = match ipc_receiver.recv() {
+            Ok(IpcMessage::Settings(settings)) => settings,
+            Ok(message) => {
+                eprintln!("===> Unexpected IPC message {:?}", message);
+                return None;
+            }
+            Err(err) => {
+                eprintln!("===> No settings received: {:?}", err);
+                return None;
+            }
+        };
+
+        return Some(Settings {
+            uri: settings.uri,
+            direction: settings.direction,
+            properties: settings.properties,
+            ipc_sender,
+            ipc_receiver,
+        });
+    }
+
+    let mut base = OpenTokUri::default();
     let mut credentials = Credentials::default();
+    if let Some(location) = matches.value_of("location") {
+        base = match location.parse::<OpenTokUri>() {
+            Ok(uri) => uri,
+            Err(err) => {
+                eprintln!("{}", err);
+                app.print_help().unwrap();
+                return None;
+            }
+        };
+        base.remote = false;
+        credentials = Credentials::from(&base);
+    }
     if let Some(api_key) = matches.value_of("api_key") {
         credentials.set_api_key(api_key.into()).unwrap();
-
     }
     if let Some(session_id) = matches.value_of("session_id") {
         credentials.set_session_id(session_id.into()).unwrap();
     }
     if let Some(token) = matches.value_of("token") {
-        credentials.set_token(token.into()).unwrap();
+        credentials.set_token(token).unwrap();
     }
     if let Some(room_uri) = matches.value_of("room_uri") {
         if let Err(err) = credentials.set_room_uri(room_uri.into()) {
@@ -49,30 +129,28 @@ pub async fn parse_cli() -> Option<Settings> {
         }
     }
 
-    if !credentials.is_complete()
-    {
+    if let Some(path) = matches.value_of("credentials_file") {
+        credentials.set_credentials_file(Some(path.into())).unwrap();
+    }
+
+    if let Err(err) = credentials.resolve() {
+        eprintln!("could not resolve credentials: {:?}", err);
+        app.print_help().unwrap();
+        return None;
+    }
+
+    if !credentials.is_complete() {
         eprintln!("===> Incomplete credentials!");
         app.print_help().unwrap();
         return None;
     }
 
-    if let Err(err) = async_std::task::block_on(
-        credentials.load(std::time::Duration::from_secs(5))
-    ) {
+    if let Err(err) = async_std::task::block_on(credentials.load()) {
         eprintln!("could not load credentials: {:?}", err);
         app.print_help().unwrap();
         return None;
     }
 
-    let ipc_server = match matches.value_of("ipc_server") {
-        Some(name) => name.into(),
-        None => {
-            eprintln!("===> No ipc_server!");
-            app.print_help().unwrap();
-            return None;
-        }
-    };
-
     let direction = match matches.value_of("direction") {
         Some(value) => {
             if value == "src" {
@@ -88,12 +166,23 @@ pub async fn parse_cli() -> Option<Settings> {
         }
     };
 
-    let stream_id = matches.value_of("stream_id").map(|s| s.into());
+    if let Some(stream_id) = matches.value_of("stream_id") {
+        base.stream_id = Some(stream_id.into());
+    }
+    let uri = match credentials.to_uri(&base) {
+        Some(uri) => uri,
+        None => {
+            eprintln!("===> No session ID!");
+            app.print_help().unwrap();
+            return None;
+        }
+    };
 
     Some(Settings {
-        credentials,
-        stream_id,
-        ipc_server,
+        uri,
         direction,
+        properties: vec![],
+        ipc_sender,
+        ipc_receiver,
     })
 }
@@ -46,28 +46,38 @@ fn create_pipeline(settings: cli::Settings) -> Result<(gst::Pipeline, Arc<dyn Ip
 
     let pipeline = gst::Pipeline::new(None);
 
-    let credentials = &settings.credentials;
-    let location = format!(
-        "opentok://{}?key={}&token={}",
-        &credentials.session_id().unwrap(), &credentials.api_key().unwrap(), &credentials.token().unwrap()
-    );
-
-    let uri_type = match settings.direction {
-        cli::Direction::Source => gst::URIType::Src,
-        cli::Direction::Sink => gst::URIType::Sink,
+    let factory = match settings.direction {
+        cli::Direction::Source => "opentoksrc",
+        cli::Direction::Sink => "opentoksink",
     };
 
-    let element = gst::Element::make_from_uri(uri_type, &location, Some("opentok-element"))?;
-
-    if let Some(ref stream_id) = settings.stream_id {
-        element.set_property("stream-id", stream_id);
+    // Not gst::Element::make_from_uri(), GStreamer logs the URIs it creates
+    // elements from, token included.
+    let element = gst::ElementFactory::make(factory, Some("opentok-element"))?;
+    element
+        .dynamic_cast_ref::<gst::URIHandler>()
+        .unwrap()
+        .set_uri(&settings.uri.to_uri())?;
+    for (name, value) in &settings.properties {
+        element.try_set_property_from_str(name, value)?;
     }
 
     pipeline.add(&element)?;
 
-    let messenger: Arc<dyn IpcMessenger> = match settings.direction {
-        cli::Direction::Source => Arc::new(source::Source::new(&pipeline, &element, &settings)),
-        cli::Direction::Sink => Arc::new(sink::Sink::new(&pipeline, &element, &settings)),
+    let cli::Settings {
+        direction,
+        ipc_sender,
+        ipc_receiver,
+        ..
+    } = settings;
+    let messenger: Arc<dyn IpcMessenger> = match direction {
+        cli::Direction::Source => Arc::new(source::Source::new(&pipeline, &element, ipc_sender)),
+        cli::Direction::Sink => Arc::new(sink::Sink::new(
+            &pipeline,
+            &element,
+            ipc_sender,
+            ipc_receiver,
+        )),
     };
 
     Ok((pipeline, messenger))
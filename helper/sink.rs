@@ -9,7 +9,7 @@
 
 use glib::clone;
 use gst::prelude::*;
-use ipc_channel::ipc::{self, IpcSender};
+use ipc_channel::ipc::{IpcReceiver, IpcSender};
 use log::debug;
 use std::path::Path;
 use std::str::FromStr;
@@ -20,8 +20,6 @@ use std::thread;
 use crate::{IpcMessenger, Stream};
 use gstopentok::common::{Error, IpcMessage, StreamMessage, StreamMessageData};
 
-use crate::cli;
-
 pub struct Sink {
     ipc_sender: Arc<Mutex<IpcSender<IpcMessage>>>,
 }
@@ -143,7 +141,8 @@ impl Sink {
     pub fn new(
         pipeline: &gst::Pipeline,
         opentoksink: &gst::Element,
-        settings: &cli::Settings,
+        child_to_parent_ipc_sender: IpcSender<IpcMessage>,
+        parent_to_child_ipc_receiver: IpcReceiver<IpcMessage>,
     ) -> Self {
         let (audio_thread_sender, audio_thread_receiver) = mpsc::channel();
         let (video_thread_sender, video_thread_receiver) = mpsc::channel();
@@ -151,23 +150,11 @@ impl Sink {
         let audio_thread_sender = Arc::new(Mutex::new(audio_thread_sender));
         let video_thread_sender = Arc::new(Mutex::new(video_thread_sender));
 
-        let ipc_server_name = settings.ipc_server.clone();
-
-        let (child_to_parent_ipc_sender, child_to_parent_ipc_receiver) = ipc::channel().unwrap();
-
         let pipeline_weak = pipeline.downgrade();
         // Control thread
         thread::spawn(move || {
             debug!("Control thread running");
 
-            let (parent_to_child_ipc_sender, parent_to_child_ipc_receiver) =
-                ipc::channel().unwrap();
-            if let Ok(oneshot_sender) = IpcSender::connect(ipc_server_name) {
-                oneshot_sender
-                    .send((parent_to_child_ipc_sender, child_to_parent_ipc_receiver))
-                    .unwrap();
-            }
-
             let pipeline = pipeline_weak.upgrade().unwrap();
             loop {
                 match parent_to_child_ipc_receiver.try_recv() {
@@ -15,7 +15,7 @@ use log::debug;
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
-use crate::{cli, IpcMessenger};
+use crate::IpcMessenger;
 use gstopentok::common::{IpcMessage, StreamMessage, StreamMessageData};
 
 pub struct Source {
@@ -93,18 +93,8 @@ impl Source {
     pub fn new(
         pipeline: &gst::Pipeline,
         opentoksrc: &gst::Element,
-        settings: &cli::Settings,
+        ipc_sender: IpcSender<IpcMessage>,
     ) -> Self {
-        let server_name = settings.ipc_server.clone();
-
-        // Create ipc channel to communicate with the main process, connect
-        // to the main process one shot server and send the ipc receiver
-        // where the messages from the child process will be sent.
-        let (ipc_sender, ipc_receiver) = ipc::channel().unwrap();
-        if let Ok(oneshot_sender) = IpcSender::connect(server_name) {
-            oneshot_sender.send(ipc_receiver).unwrap();
-        }
-
         let ipc_sender = Arc::new(Mutex::new(ipc_sender));
 
         opentoksrc.connect_pad_added(clone!(
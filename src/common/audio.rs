@@ -0,0 +1,112 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Format of the shared audio device.
+//!
+//! The SDK captures the published audio, and renders the mix of the
+//! subscribed streams, through a single audio device per process. The
+//! `audio-rate` and `audio-channels` properties of `opentoksink` configure
+//! its capture side, and the ones of `opentoksrc` its render side. The SDK
+//! only reads them when the device starts, so they can only be changed
+//! until the elements leave READY.
+
+use glib::ToValue;
+use gst::prelude::*;
+use once_cell::sync::Lazy;
+
+pub const DEFAULT_RATE: u32 = 44100;
+pub const DEFAULT_CHANNELS: u32 = 1;
+/// The SDK handles mono and stereo audio only.
+pub const MAX_CHANNELS: u32 = 2;
+
+/// Format of the audio exchanged with the shared audio device, as
+/// interleaved S16 samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioSettings {
+    pub rate: u32,
+    pub channels: u32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            rate: DEFAULT_RATE,
+            channels: DEFAULT_CHANNELS,
+        }
+    }
+}
+
+/// Caps of the audio samples exchanged with the audio device.
+pub fn audio_device_caps(settings: &AudioSettings) -> gst::Caps {
+    gst::Caps::builder("audio/x-raw")
+        .field("format", gst_audio::AUDIO_FORMAT_S16.to_str())
+        .field("layout", "interleaved")
+        .field("rate", settings.rate as i32)
+        .field("channels", settings.channels as i32)
+        .build()
+}
+
+/// Properties configuring the [`AudioSettings`], shared by the elements.
+pub fn audio_properties() -> &'static [glib::ParamSpec] {
+    static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+        vec![
+            glib::ParamSpecUInt::new(
+                "audio-rate",
+                "Audio rate",
+                "Sample rate of the audio device, in Hz",
+                1,
+                i32::MAX as u32,
+                DEFAULT_RATE,
+                glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_READY,
+            ),
+            glib::ParamSpecUInt::new(
+                "audio-channels",
+                "Audio channels",
+                "Number of channels of the audio device",
+                1,
+                MAX_CHANNELS,
+                DEFAULT_CHANNELS,
+                glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_READY,
+            ),
+        ]
+    });
+
+    PROPERTIES.as_ref()
+}
+
+/// Whether `name` is one of the [`audio_properties`], unlike the
+/// `audio-fallback` publisher property.
+pub fn is_audio_property(name: &str) -> bool {
+    audio_properties().iter().any(|pspec| pspec.name() == name)
+}
+
+/// Whether the [`audio_properties`] of `element` can still be changed, the
+/// audio device starting as the element leaves READY.
+pub fn audio_settings_mutable(element: &gst::Element) -> bool {
+    element.current_state() <= gst::State::Ready && element.pending_state() <= gst::State::Ready
+}
+
+/// Sets one of the [`audio_properties`].
+pub fn set_audio_property(settings: &mut AudioSettings, name: &str, value: &glib::Value) {
+    let value = value.get::<u32>().expect("type checked upstream");
+    match name {
+        "audio-rate" => settings.rate = value,
+        "audio-channels" => settings.channels = value,
+        _ => unimplemented!(),
+    }
+}
+
+/// Gets one of the [`audio_properties`].
+pub fn audio_property(settings: &AudioSettings, name: &str) -> glib::Value {
+    match name {
+        "audio-rate" => settings.rate.to_value(),
+        "audio-channels" => settings.channels.to_value(),
+        _ => unimplemented!(),
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Session backend abstraction.
+//!
+//! The elements never talk to the OpenTok SDK directly. They go through a
+//! [`Backend`] instead, which is either the SDK itself or the in-process
+//! [`MockBackend`](super::mock::MockBackend), so that the pipeline logic can
+//! run without the proprietary library and a live Vonage account.
+
+use super::audio::AudioSettings;
+use super::mock::MockBackend;
+use super::publisher::PublisherSettings;
+use super::sdk::OpenTokBackend;
+use super::Error;
+
+use gst_video::VideoFormat;
+use once_cell::sync::Lazy;
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+/// Environment variable used to select the backend when none was set
+/// programmatically. Only `opentok` (the default) and `mock` are known.
+pub const BACKEND_ENV_VAR: &str = "GST_OPENTOK_BACKEND";
+
+/// Description of a stream available in a session.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamInfo {
+    /// Unique stream identifier.
+    pub id: String,
+    /// Identifier of the connection that published the stream.
+    pub connection_id: String,
+    /// Name given to the stream by its publisher.
+    pub name: String,
+    /// Whether the stream currently carries audio.
+    pub has_audio: bool,
+    /// Whether the stream currently carries video.
+    pub has_video: bool,
+}
+
+/// Description of a client connected to a session.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnectionInfo {
+    /// Unique connection identifier.
+    pub id: String,
+    /// Connection data of the token the client connected with.
+    pub data: String,
+    /// Time the client connected, in milliseconds since the Unix epoch.
+    pub creation_time: i64,
+}
+
+/// Change of a stream notified by [`SessionEvent::StreamPropertyChanged`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamChange {
+    HasAudio(bool),
+    HasVideo(bool),
+    VideoDimensions {
+        width: u32,
+        height: u32,
+    },
+    /// `camera`, `screen` or `custom`.
+    VideoType(&'static str),
+}
+
+/// A message of the session signaling channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Signal {
+    /// Type of the signal, empty if none was given.
+    pub signal_type: String,
+    pub data: String,
+    /// Identifier of the connection that sent the signal.
+    pub connection_id: String,
+}
+
+/// A raw video frame, tightly packed.
+#[derive(Clone, Debug)]
+pub struct VideoFrameData {
+    pub format: VideoFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// Capture time of the frame, on a timeline of its own, if known.
+    pub timestamp: Option<gst::ClockTime>,
+}
+
+/// A chunk of interleaved S16 audio samples.
+#[derive(Clone, Debug)]
+pub struct AudioSamples {
+    pub data: Vec<i16>,
+    pub rate: u32,
+    pub channels: u32,
+}
+
+/// Settings of the video capturer of a publisher.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturerSettings {
+    pub format: VideoFormat,
+    pub width: u32,
+    pub height: u32,
+    pub fps: gst::Fraction,
+}
+
+impl Default for CapturerSettings {
+    fn default() -> Self {
+        Self {
+            format: VideoFormat::I420,
+            width: 1280,
+            height: 720,
+            fps: gst::Fraction::new(30, 1),
+        }
+    }
+}
+
+/// Settings of a subscription to a remote stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriberSettings {
+    pub subscribe_to_audio: bool,
+    pub subscribe_to_video: bool,
+    /// Video resolution to ask for, as width and height, when the stream is
+    /// available in several layers.
+    pub preferred_resolution: Option<(u32, u32)>,
+}
+
+impl Default for SubscriberSettings {
+    fn default() -> Self {
+        Self {
+            subscribe_to_audio: true,
+            subscribe_to_video: true,
+            preferred_resolution: None,
+        }
+    }
+}
+
+/// Session level notifications.
+#[derive(Clone, Debug)]
+pub enum SessionEvent {
+    Connected,
+    Disconnected,
+    StreamReceived(StreamInfo),
+    StreamDropped(StreamInfo),
+    /// The connection was lost and is being restored. `attempt` is 0 when
+    /// the SDK reconnects by itself, the attempts of the shared session are
+    /// numbered from 1 to `max_attempts`.
+    Reconnecting {
+        attempt: u32,
+        max_attempts: u32,
+    },
+    /// The connection was restored.
+    Reconnected,
+    /// The session refused the token, usually because it expired.
+    AuthenticationFailed(String),
+    /// The connection to the session could not be established, or was lost.
+    /// The backend disconnects afterwards, if it was connected.
+    ConnectionLost(String),
+    /// Any other error, the connection being unaffected.
+    Error(String),
+    /// Another client joined the session.
+    ConnectionCreated(ConnectionInfo),
+    ConnectionDropped(ConnectionInfo),
+    /// The stream, already updated, changed as described.
+    StreamPropertyChanged(StreamInfo, StreamChange),
+    ArchiveStarted {
+        id: String,
+        name: String,
+    },
+    ArchiveStopped {
+        id: String,
+    },
+    /// A signal was sent to the whole session or to this connection.
+    SignalReceived(Signal),
+}
+
+/// Publisher level notifications.
+#[derive(Clone, Debug)]
+pub enum PublisherEvent {
+    StreamCreated(StreamInfo),
+    StreamDestroyed(StreamInfo),
+    Error(String),
+}
+
+/// Subscriber level notifications.
+#[derive(Clone, Debug)]
+pub enum SubscriberEvent {
+    VideoFrame(VideoFrameData),
+    VideoEnabled,
+    VideoDisabled,
+    AudioEnabled,
+    AudioDisabled,
+    /// The subscriber started receiving the stream.
+    Connected,
+    /// The stream is temporarily interrupted.
+    Disconnected,
+    Reconnected,
+    /// The quality of the stream is so low that its video may be disabled.
+    VideoDisableWarning,
+    VideoDisableWarningLifted,
+    Error(String),
+}
+
+pub type SessionEventHandler = Arc<dyn Fn(SessionEvent) + Send + Sync>;
+pub type PublisherEventHandler = Arc<dyn Fn(PublisherEvent) + Send + Sync>;
+pub type SubscriberEventHandler = Arc<dyn Fn(SubscriberEvent) + Send + Sync>;
+pub type AudioSamplesHandler = Arc<dyn Fn(AudioSamples) + Send + Sync>;
+
+/// Entry point of a backend implementation.
+pub trait Backend: Send + Sync {
+    /// Short name of the backend, for logging purposes.
+    fn name(&self) -> &'static str;
+
+    /// Creates a new, not yet connected, session.
+    fn create_session(
+        &self,
+        api_key: &str,
+        session_id: &str,
+        handler: SessionEventHandler,
+    ) -> Result<Arc<dyn SessionBackend>, Error>;
+
+    /// Fails for the publisher `settings` the backend cannot apply.
+    fn check_publisher_settings(&self, settings: &PublisherSettings) -> Result<(), Error>;
+
+    /// Creates a publisher whose video is fed through
+    /// [`PublisherBackend::provide_video_frame`]. The stream is named `name`,
+    /// and the audio and video are toggled by the caller, regardless of
+    /// `settings`.
+    fn create_publisher(
+        &self,
+        name: &str,
+        settings: &PublisherSettings,
+        capturer: &CapturerSettings,
+        handler: PublisherEventHandler,
+    ) -> Result<Arc<dyn PublisherBackend>, Error>;
+
+    /// Pushes audio to the shared audio capture device.
+    fn push_audio_samples(&self, samples: &[i16]);
+
+    /// Sets the format of the audio pushed to the shared audio capture
+    /// device. The SDK only reads it when the device starts capturing.
+    fn set_audio_capture_settings(&self, settings: &AudioSettings);
+
+    /// Sets the format of the audio rendered by the shared audio device,
+    /// and given to the audio samples handler. The SDK only reads it when
+    /// the device starts rendering.
+    fn set_audio_render_settings(&self, settings: &AudioSettings);
+
+    /// Sets the callback receiving the mix of all the subscribed audio
+    /// streams, as rendered by the shared audio device.
+    fn set_audio_samples_handler(&self, handler: Option<AudioSamplesHandler>);
+}
+
+pub trait SessionBackend: Send + Sync {
+    fn connect(&self, token: &str) -> Result<(), Error>;
+
+    fn disconnect(&self) -> Result<(), Error>;
+
+    fn publish(&self, publisher: &Arc<dyn PublisherBackend>) -> Result<(), Error>;
+
+    /// Subscribes to a stream previously notified through
+    /// [`SessionEvent::StreamReceived`].
+    fn subscribe(
+        &self,
+        stream_id: &str,
+        settings: &SubscriberSettings,
+        handler: SubscriberEventHandler,
+    ) -> Result<Arc<dyn SubscriberBackend>, Error>;
+
+    /// Sends a signal to all the connections of the session, this one
+    /// included, or to `connection_id` only.
+    fn send_signal(
+        &self,
+        signal_type: &str,
+        data: &str,
+        connection_id: Option<&str>,
+    ) -> Result<(), Error>;
+}
+
+pub trait PublisherBackend: Send + Sync {
+    fn provide_video_frame(&self, frame: &VideoFrameData) -> Result<(), Error>;
+
+    fn toggle_audio(&self, enabled: bool) -> Result<(), Error>;
+
+    fn toggle_video(&self, enabled: bool) -> Result<(), Error>;
+
+    /// Applies the settings that can change while publishing, listed in
+    /// [`RUNTIME_PROPERTIES`](super::publisher::RUNTIME_PROPERTIES).
+    fn update_settings(&self, settings: &PublisherSettings) -> Result<(), Error>;
+
+    /// Applies new settings to the video capturer, keeping the stream
+    /// published. Fails when the publisher has to be created again for them
+    /// to apply.
+    fn reconfigure_capturer(&self, capturer: &CapturerSettings) -> Result<(), Error>;
+
+    fn unpublish(&self) -> Result<(), Error>;
+
+    /// Allows session implementations to get back their concrete publisher
+    /// type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Subscription to a remote stream, which ends when dropped.
+pub trait SubscriberBackend: Send + Sync {
+    /// Latest known state of the subscribed stream.
+    fn stream(&self) -> StreamInfo;
+}
+
+static BACKEND: Lazy<Mutex<Option<Arc<dyn Backend>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Returns the backend used by the elements, creating it on first use.
+pub fn backend() -> Arc<dyn Backend> {
+    let mut backend = BACKEND.lock().unwrap();
+    if let Some(ref backend) = *backend {
+        return backend.clone();
+    }
+
+    let new_backend: Arc<dyn Backend> = match std::env::var(BACKEND_ENV_VAR).as_deref() {
+        Ok("mock") => Arc::new(MockBackend::new()),
+        _ => Arc::new(OpenTokBackend::new()),
+    };
+    *backend = Some(new_backend.clone());
+    new_backend
+}
+
+/// Replaces the backend used by elements created from now on.
+pub fn set_backend(new_backend: Arc<dyn Backend>) {
+    *BACKEND.lock().unwrap() = Some(new_backend);
+}
@@ -0,0 +1,231 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Element messages describing the activity of the session.
+//!
+//! The elements post an element message for each session, publisher and
+//! subscriber event, so that applications can follow what happens in the
+//! room. Errors are posted as error messages instead, and video frames are
+//! not reported. The structures are:
+//!
+//! * `opentok/connected`, `opentok/disconnected` and `opentok/reconnected`,
+//!   without fields.
+//! * `opentok/reconnecting`: `attempt` and `max-attempts` (`u32`), see
+//!   [`super::reconnect`].
+//! * `opentok/connection-created` and `opentok/connection-dropped`:
+//!   `connection-id` and `data` (strings), `creation-time` (`i64`,
+//!   milliseconds since the Unix epoch).
+//! * `opentok/stream-received`, `opentok/stream-dropped` (session),
+//!   `opentok/stream-created` and `opentok/stream-destroyed` (publisher):
+//!   the stream fields, `stream-id`, `connection-id` and `name` (strings),
+//!   `has-audio` and `has-video` (booleans).
+//! * `opentok/stream-property-changed`: the stream fields, and `property`,
+//!   one of `has-audio`, `has-video`, `video-dimensions` (along with the
+//!   `width` and `height` `u32` fields) or `video-type` (along with the
+//!   `video-type` string field, `camera`, `screen` or `custom`).
+//!   `opentoksink` also posts it for its own streams, when their audio or
+//!   video is toggled.
+//! * `opentok/archive-started`: `archive-id` and `name` (strings).
+//! * `opentok/archive-stopped`: `archive-id` (string).
+//! * `opentok/signal-received`: `type`, `data` and `connection-id`
+//!   (strings), see [`super::signaling`].
+//! * `opentok/subscriber-connected`, `opentok/subscriber-disconnected`,
+//!   `opentok/subscriber-reconnected`, `opentok/audio-enabled`,
+//!   `opentok/audio-disabled`, `opentok/video-enabled`,
+//!   `opentok/video-disabled`, `opentok/video-disable-warning` and
+//!   `opentok/video-disable-warning-lifted`: `stream-id` (string).
+//! * `opentok/subscriber-error`: `stream-id` and `message` (strings).
+
+use super::backend::{
+    ConnectionInfo, PublisherEvent, PublisherEventHandler, SessionEvent, SessionEventHandler,
+    StreamChange, StreamInfo, SubscriberEvent, SubscriberEventHandler,
+};
+
+use gst::prelude::*;
+use std::sync::Arc;
+
+fn post(element: &gst::Element, structure: gst::Structure) {
+    let _ = element.post_message(
+        gst::message::Element::builder(structure)
+            .src(element)
+            .build(),
+    );
+}
+
+fn stream_structure(name: &str, stream: &StreamInfo) -> gst::structure::Builder {
+    gst::Structure::builder(name)
+        .field("stream-id", &stream.id)
+        .field("connection-id", &stream.connection_id)
+        .field("name", &stream.name)
+        .field("has-audio", stream.has_audio)
+        .field("has-video", stream.has_video)
+}
+
+fn connection_structure(name: &str, connection: &ConnectionInfo) -> gst::Structure {
+    gst::Structure::builder(name)
+        .field("connection-id", &connection.id)
+        .field("data", &connection.data)
+        .field("creation-time", connection.creation_time)
+        .build()
+}
+
+/// Posts the element message describing a session event, if any.
+pub fn post_session_message(element: &gst::Element, event: &SessionEvent) {
+    let structure = match *event {
+        SessionEvent::Connected => gst::Structure::new_empty("opentok/connected"),
+        SessionEvent::Disconnected => gst::Structure::new_empty("opentok/disconnected"),
+        SessionEvent::Reconnecting {
+            attempt,
+            max_attempts,
+        } => gst::Structure::builder("opentok/reconnecting")
+            .field("attempt", attempt)
+            .field("max-attempts", max_attempts)
+            .build(),
+        SessionEvent::Reconnected => gst::Structure::new_empty("opentok/reconnected"),
+        SessionEvent::StreamReceived(ref stream) => {
+            stream_structure("opentok/stream-received", stream).build()
+        }
+        SessionEvent::StreamDropped(ref stream) => {
+            stream_structure("opentok/stream-dropped", stream).build()
+        }
+        SessionEvent::ConnectionCreated(ref connection) => {
+            connection_structure("opentok/connection-created", connection)
+        }
+        SessionEvent::ConnectionDropped(ref connection) => {
+            connection_structure("opentok/connection-dropped", connection)
+        }
+        SessionEvent::StreamPropertyChanged(ref stream, ref change) => {
+            let builder = stream_structure("opentok/stream-property-changed", stream);
+            match *change {
+                StreamChange::HasAudio(_) => builder.field("property", "has-audio"),
+                StreamChange::HasVideo(_) => builder.field("property", "has-video"),
+                StreamChange::VideoDimensions { width, height } => builder
+                    .field("property", "video-dimensions")
+                    .field("width", width)
+                    .field("height", height),
+                StreamChange::VideoType(video_type) => builder
+                    .field("property", "video-type")
+                    .field("video-type", video_type),
+            }
+            .build()
+        }
+        SessionEvent::ArchiveStarted { ref id, ref name } => {
+            gst::Structure::builder("opentok/archive-started")
+                .field("archive-id", id)
+                .field("name", name)
+                .build()
+        }
+        SessionEvent::ArchiveStopped { ref id } => {
+            gst::Structure::builder("opentok/archive-stopped")
+                .field("archive-id", id)
+                .build()
+        }
+        SessionEvent::SignalReceived(ref signal) => {
+            gst::Structure::builder("opentok/signal-received")
+                .field("type", &signal.signal_type)
+                .field("data", &signal.data)
+                .field("connection-id", &signal.connection_id)
+                .build()
+        }
+        SessionEvent::AuthenticationFailed(_)
+        | SessionEvent::ConnectionLost(_)
+        | SessionEvent::Error(_) => return,
+    };
+    post(element, structure);
+}
+
+/// Posts the element message describing a publisher event, if any.
+pub fn post_publisher_message(element: &gst::Element, event: &PublisherEvent) {
+    let structure = match *event {
+        PublisherEvent::StreamCreated(ref stream) => {
+            stream_structure("opentok/stream-created", stream).build()
+        }
+        PublisherEvent::StreamDestroyed(ref stream) => {
+            stream_structure("opentok/stream-destroyed", stream).build()
+        }
+        PublisherEvent::Error(_) => return,
+    };
+    post(element, structure);
+}
+
+/// Posts the element message describing an event of the subscriber to
+/// `stream_id`, if any.
+pub fn post_subscriber_message(element: &gst::Element, stream_id: &str, event: &SubscriberEvent) {
+    let name = match *event {
+        SubscriberEvent::Connected => "opentok/subscriber-connected",
+        SubscriberEvent::Disconnected => "opentok/subscriber-disconnected",
+        SubscriberEvent::Reconnected => "opentok/subscriber-reconnected",
+        SubscriberEvent::AudioEnabled => "opentok/audio-enabled",
+        SubscriberEvent::AudioDisabled => "opentok/audio-disabled",
+        SubscriberEvent::VideoEnabled => "opentok/video-enabled",
+        SubscriberEvent::VideoDisabled => "opentok/video-disabled",
+        SubscriberEvent::VideoDisableWarning => "opentok/video-disable-warning",
+        SubscriberEvent::VideoDisableWarningLifted => "opentok/video-disable-warning-lifted",
+        SubscriberEvent::Error(ref message) => {
+            let structure = gst::Structure::builder("opentok/subscriber-error")
+                .field("stream-id", stream_id)
+                .field("message", message)
+                .build();
+            return post(element, structure);
+        }
+        SubscriberEvent::VideoFrame(_) => return,
+    };
+    post(
+        element,
+        gst::Structure::builder(name)
+            .field("stream-id", stream_id)
+            .build(),
+    );
+}
+
+/// Wraps `handler`, so that each session event is posted by `element`
+/// before being handled.
+pub fn with_session_messages(
+    element: &gst::Element,
+    handler: SessionEventHandler,
+) -> SessionEventHandler {
+    let element = element.downgrade();
+    Arc::new(move |event| {
+        if let Some(element) = element.upgrade() {
+            post_session_message(&element, &event);
+        }
+        handler(event)
+    })
+}
+
+/// Like [`with_session_messages`], for publisher events.
+pub fn with_publisher_messages(
+    element: &gst::Element,
+    handler: PublisherEventHandler,
+) -> PublisherEventHandler {
+    let element = element.downgrade();
+    Arc::new(move |event| {
+        if let Some(element) = element.upgrade() {
+            post_publisher_message(&element, &event);
+        }
+        handler(event)
+    })
+}
+
+/// Like [`with_session_messages`], for the events of the subscriber to
+/// `stream_id`.
+pub fn with_subscriber_messages(
+    element: &gst::Element,
+    stream_id: &str,
+    handler: SubscriberEventHandler,
+) -> SubscriberEventHandler {
+    let element = element.downgrade();
+    let stream_id = stream_id.to_string();
+    Arc::new(move |event| {
+        if let Some(element) = element.upgrade() {
+            post_subscriber_message(&element, &stream_id, &event);
+        }
+        handler(event)
+    })
+}
@@ -0,0 +1,854 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! In-process [`Backend`] implementation.
+//!
+//! Sessions sharing a session ID join the same fake room. Streams published
+//! in a room are announced to every other connection of that room, and the
+//! video frames and audio samples provided by publishers are looped back to
+//! the local subscribers. No network access is involved.
+
+use super::audio::AudioSettings;
+use super::backend::{
+    AudioSamples, AudioSamplesHandler, Backend, CapturerSettings, ConnectionInfo, PublisherBackend,
+    PublisherEvent, PublisherEventHandler, SessionBackend, SessionEvent, SessionEventHandler,
+    Signal, StreamChange, StreamInfo, SubscriberBackend, SubscriberEvent, SubscriberEventHandler,
+    SubscriberSettings, VideoFrameData,
+};
+use super::publisher::PublisherSettings;
+use super::token::token_is_expired;
+use super::{Error, CAT};
+
+use gst::gst_debug;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Runs the event handlers sequentially on a dedicated thread, like the SDK
+/// does with its callbacks, so that handlers never run on the caller's stack.
+struct Dispatcher {
+    sender: Mutex<mpsc::Sender<Task>>,
+}
+
+impl Dispatcher {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        thread::spawn(move || {
+            for task in receiver {
+                task();
+            }
+        });
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn dispatch<F: FnOnce() + Send + 'static>(&self, task: F) {
+        let _ = self.sender.lock().unwrap().send(Box::new(task));
+    }
+}
+
+struct Connection {
+    id: String,
+    info: ConnectionInfo,
+    /// Token the connection was made with.
+    token: String,
+    handler: SessionEventHandler,
+    connected: Arc<AtomicBool>,
+}
+
+/// Session ID and stream ID of a publisher, once published.
+type Published = Arc<Mutex<Option<(String, String)>>>;
+
+struct PublishedStream {
+    info: StreamInfo,
+    publisher_handler: PublisherEventHandler,
+    publisher_published: Published,
+    publisher_settings: Arc<Mutex<(PublisherSettings, CapturerSettings)>>,
+    subscribers: Vec<(u64, SubscriberSettings, SubscriberEventHandler)>,
+}
+
+#[derive(Default)]
+struct Room {
+    connections: Vec<Connection>,
+    streams: HashMap<String, PublishedStream>,
+}
+
+impl Room {
+    fn notify_others(&self, dispatcher: &Dispatcher, connection_id: &str, event: SessionEvent) {
+        for connection in self.connections.iter().filter(|c| c.id != connection_id) {
+            let handler = connection.handler.clone();
+            let event = event.clone();
+            dispatcher.dispatch(move || handler(event));
+        }
+    }
+
+    fn notify_all(&self, dispatcher: &Dispatcher, event: SessionEvent) {
+        for connection in self.connections.iter() {
+            let handler = connection.handler.clone();
+            let event = event.clone();
+            dispatcher.dispatch(move || handler(event));
+        }
+    }
+
+    fn notify_subscribers(&self, dispatcher: &Dispatcher, stream_id: &str, event: SubscriberEvent) {
+        if let Some(stream) = self.streams.get(stream_id) {
+            for (_, settings, handler) in stream.subscribers.iter() {
+                let video_event = matches!(
+                    event,
+                    SubscriberEvent::VideoFrame(_)
+                        | SubscriberEvent::VideoEnabled
+                        | SubscriberEvent::VideoDisabled
+                );
+                if video_event && !settings.subscribe_to_video {
+                    continue;
+                }
+                let handler = handler.clone();
+                let event = event.clone();
+                dispatcher.dispatch(move || handler(event));
+            }
+        }
+    }
+
+    fn drop_stream(&mut self, dispatcher: &Dispatcher, stream_id: &str) {
+        if let Some(stream) = self.streams.remove(stream_id) {
+            let info = stream.info.clone();
+            self.notify_others(
+                dispatcher,
+                &info.connection_id,
+                SessionEvent::StreamDropped(info.clone()),
+            );
+            let handler = stream.publisher_handler;
+            dispatcher.dispatch(move || handler(PublisherEvent::StreamDestroyed(info)));
+        }
+    }
+}
+
+struct Shared {
+    /// Rooms indexed by session ID.
+    rooms: Mutex<HashMap<String, Room>>,
+    dispatcher: Dispatcher,
+    audio_handler: Mutex<Option<AudioSamplesHandler>>,
+    /// Formats of the loopback audio device.
+    audio_capture: Mutex<AudioSettings>,
+    audio_render: Mutex<AudioSettings>,
+    next_subscriber_id: AtomicU64,
+    /// Number of upcoming connections to refuse, by session ID.
+    refusals: Mutex<HashMap<String, usize>>,
+}
+
+impl Shared {
+    fn take_refusal(&self, session_id: &str) -> bool {
+        match self.refusals.lock().unwrap().get_mut(session_id) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Backend emulating OpenTok sessions within the current process.
+pub struct MockBackend {
+    shared: Arc<Shared>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                rooms: Default::default(),
+                dispatcher: Dispatcher::new(),
+                audio_handler: Default::default(),
+                audio_capture: Default::default(),
+                audio_render: Default::default(),
+                next_subscriber_id: AtomicU64::new(0),
+                refusals: Default::default(),
+            }),
+        }
+    }
+
+    /// Streams currently published in the room identified by `session_id`.
+    pub fn streams(&self, session_id: &str) -> Vec<StreamInfo> {
+        self.shared
+            .rooms
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|room| room.streams.values().map(|s| s.info.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Settings of the publisher of the stream `stream_id`, and of its video
+    /// capturer.
+    pub fn publisher_settings(
+        &self,
+        session_id: &str,
+        stream_id: &str,
+    ) -> Option<(PublisherSettings, CapturerSettings)> {
+        self.shared
+            .rooms
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .and_then(|room| room.streams.get(stream_id))
+            .map(|stream| stream.publisher_settings.lock().unwrap().clone())
+    }
+
+    /// Number of connections currently joined to the room identified by
+    /// `session_id`.
+    pub fn connection_count(&self, session_id: &str) -> usize {
+        self.shared
+            .rooms
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|room| room.connections.len())
+            .unwrap_or(0)
+    }
+
+    /// Tokens the connections currently joined to the room identified by
+    /// `session_id` were made with.
+    pub fn connection_tokens(&self, session_id: &str) -> Vec<String> {
+        self.shared
+            .rooms
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|room| room.connections.iter().map(|c| c.token.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Emulates a network failure: all the connections to the room
+    /// identified by `session_id` are lost and disconnected, and their
+    /// streams are gone.
+    pub fn drop_connections(&self, session_id: &str) {
+        let room = match self.shared.rooms.lock().unwrap().remove(session_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        let dispatcher = &self.shared.dispatcher;
+        for (_, stream) in room.streams {
+            stream.publisher_published.lock().unwrap().take();
+            let handler = stream.publisher_handler;
+            let info = stream.info;
+            dispatcher.dispatch(move || handler(PublisherEvent::StreamDestroyed(info)));
+        }
+        for connection in room.connections {
+            gst_debug!(CAT, "Mock connection {} lost", connection.id);
+            connection.connected.store(false, Ordering::SeqCst);
+            let handler = connection.handler;
+            // Like OpenTok, which disconnects after reporting the error.
+            dispatcher.dispatch(move || {
+                handler(SessionEvent::ConnectionLost("Connection dropped".into()));
+                handler(SessionEvent::Disconnected);
+            });
+        }
+    }
+
+    /// Emulates an error leaving the connections to the room identified by
+    /// `session_id` unaffected, such as a refused signal.
+    pub fn session_error(&self, session_id: &str, error: &str) {
+        if let Some(room) = self.shared.rooms.lock().unwrap().get(session_id) {
+            room.notify_all(&self.shared.dispatcher, SessionEvent::Error(error.into()));
+        }
+    }
+
+    /// Starts archiving the room identified by `session_id`, returning the
+    /// archive ID.
+    pub fn start_archive(&self, session_id: &str, name: &str) -> String {
+        let id = Uuid::new_v4().to_string();
+        if let Some(room) = self.shared.rooms.lock().unwrap().get(session_id) {
+            room.notify_all(
+                &self.shared.dispatcher,
+                SessionEvent::ArchiveStarted {
+                    id: id.clone(),
+                    name: name.into(),
+                },
+            );
+        }
+        id
+    }
+
+    pub fn stop_archive(&self, session_id: &str, archive_id: &str) {
+        if let Some(room) = self.shared.rooms.lock().unwrap().get(session_id) {
+            room.notify_all(
+                &self.shared.dispatcher,
+                SessionEvent::ArchiveStopped {
+                    id: archive_id.into(),
+                },
+            );
+        }
+    }
+
+    /// Makes the next `count` connections to the room identified by
+    /// `session_id` fail with an error.
+    pub fn refuse_connections(&self, session_id: &str, count: usize) {
+        self.shared
+            .refusals
+            .lock()
+            .unwrap()
+            .insert(session_id.into(), count);
+    }
+}
+
+impl Backend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn create_session(
+        &self,
+        api_key: &str,
+        session_id: &str,
+        handler: SessionEventHandler,
+    ) -> Result<Arc<dyn SessionBackend>, Error> {
+        if api_key.is_empty() || session_id.is_empty() {
+            return Err(Error::Init(
+                "Failed to create OpenTok session: empty API key or session ID".into(),
+            ));
+        }
+
+        Ok(Arc::new(MockSession {
+            shared: self.shared.clone(),
+            session_id: session_id.into(),
+            connection_id: Uuid::new_v4().to_string(),
+            handler,
+            connected: Default::default(),
+        }))
+    }
+
+    fn check_publisher_settings(&self, _settings: &PublisherSettings) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn create_publisher(
+        &self,
+        name: &str,
+        settings: &PublisherSettings,
+        capturer: &CapturerSettings,
+        handler: PublisherEventHandler,
+    ) -> Result<Arc<dyn PublisherBackend>, Error> {
+        Ok(Arc::new(MockPublisher {
+            shared: self.shared.clone(),
+            name: name.into(),
+            settings: Arc::new(Mutex::new((settings.clone(), capturer.clone()))),
+            handler,
+            audio: AtomicBool::new(true),
+            video: AtomicBool::new(true),
+            published: Default::default(),
+        }))
+    }
+
+    fn push_audio_samples(&self, samples: &[i16]) {
+        if let Some(ref handler) = *self.shared.audio_handler.lock().unwrap() {
+            let handler = handler.clone();
+            let capture = *self.shared.audio_capture.lock().unwrap();
+            let render = *self.shared.audio_render.lock().unwrap();
+            let samples = AudioSamples {
+                data: convert_audio(samples, &capture, &render),
+                rate: render.rate,
+                channels: render.channels,
+            };
+            self.shared.dispatcher.dispatch(move || handler(samples));
+        }
+    }
+
+    fn set_audio_capture_settings(&self, settings: &AudioSettings) {
+        *self.shared.audio_capture.lock().unwrap() = *settings;
+    }
+
+    fn set_audio_render_settings(&self, settings: &AudioSettings) {
+        *self.shared.audio_render.lock().unwrap() = *settings;
+    }
+
+    fn set_audio_samples_handler(&self, handler: Option<AudioSamplesHandler>) {
+        *self.shared.audio_handler.lock().unwrap() = handler;
+    }
+}
+
+/// Converts interleaved samples from the `from` format to the `to` one, by
+/// picking the nearest samples, which is good enough for a loopback device.
+fn convert_audio(samples: &[i16], from: &AudioSettings, to: &AudioSettings) -> Vec<i16> {
+    if from == to {
+        return samples.to_vec();
+    }
+    let frames = samples.len() / from.channels as usize;
+    let converted_frames = frames * to.rate as usize / from.rate as usize;
+    let mut converted = Vec::with_capacity(converted_frames * to.channels as usize);
+    for frame in 0..converted_frames {
+        let source = frame * from.rate as usize / to.rate as usize;
+        for channel in 0..to.channels.min(from.channels) as usize {
+            converted.push(samples[source * from.channels as usize + channel]);
+        }
+        // Missing channels repeat the last one.
+        for _ in from.channels..to.channels {
+            converted.push(*converted.last().unwrap());
+        }
+    }
+    converted
+}
+
+struct MockSession {
+    shared: Arc<Shared>,
+    session_id: String,
+    connection_id: String,
+    handler: SessionEventHandler,
+    connected: Arc<AtomicBool>,
+}
+
+impl SessionBackend for MockSession {
+    fn connect(&self, token: &str) -> Result<(), Error> {
+        if token.is_empty() {
+            return Err(Error::Init("Connection error: empty token".into()));
+        }
+        if token_is_expired(token) {
+            // Like OpenTok, report this asynchronously.
+            let handler = self.handler.clone();
+            self.shared.dispatcher.dispatch(move || {
+                handler(SessionEvent::AuthenticationFailed("Token expired".into()))
+            });
+            return Ok(());
+        }
+        if self.connected.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if self.shared.take_refusal(&self.session_id) {
+            let handler = self.handler.clone();
+            self.shared.dispatcher.dispatch(move || {
+                handler(SessionEvent::ConnectionLost("Connection refused".into()))
+            });
+            return Ok(());
+        }
+        self.connected.store(true, Ordering::SeqCst);
+
+        gst_debug!(
+            CAT,
+            "Mock connection {} joining {}",
+            self.connection_id,
+            self.session_id
+        );
+
+        let info = ConnectionInfo {
+            id: self.connection_id.clone(),
+            data: String::new(),
+            creation_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis() as i64),
+        };
+        let mut rooms = self.shared.rooms.lock().unwrap();
+        let room = rooms.entry(self.session_id.clone()).or_default();
+        let dispatcher = &self.shared.dispatcher;
+        room.notify_all(dispatcher, SessionEvent::ConnectionCreated(info.clone()));
+        room.connections.push(Connection {
+            id: self.connection_id.clone(),
+            info,
+            token: token.into(),
+            handler: self.handler.clone(),
+            connected: self.connected.clone(),
+        });
+
+        let handler = self.handler.clone();
+        dispatcher.dispatch(move || handler(SessionEvent::Connected));
+        for connection in room
+            .connections
+            .iter()
+            .filter(|c| c.id != self.connection_id)
+        {
+            let handler = self.handler.clone();
+            let info = connection.info.clone();
+            dispatcher.dispatch(move || handler(SessionEvent::ConnectionCreated(info)));
+        }
+        for stream in room.streams.values() {
+            if stream.info.connection_id == self.connection_id {
+                continue;
+            }
+            let handler = self.handler.clone();
+            let info = stream.info.clone();
+            dispatcher.dispatch(move || handler(SessionEvent::StreamReceived(info)));
+        }
+
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), Error> {
+        if !self.connected.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        gst_debug!(
+            CAT,
+            "Mock connection {} leaving {}",
+            self.connection_id,
+            self.session_id
+        );
+
+        let mut rooms = self.shared.rooms.lock().unwrap();
+        if let Some(room) = rooms.get_mut(&self.session_id) {
+            let own_streams: Vec<String> = room
+                .streams
+                .values()
+                .filter(|s| s.info.connection_id == self.connection_id)
+                .map(|s| s.info.id.clone())
+                .collect();
+            for stream_id in own_streams {
+                room.drop_stream(&self.shared.dispatcher, &stream_id);
+            }
+            if let Some(index) = room
+                .connections
+                .iter()
+                .position(|c| c.id == self.connection_id)
+            {
+                let connection = room.connections.remove(index);
+                room.notify_all(
+                    &self.shared.dispatcher,
+                    SessionEvent::ConnectionDropped(connection.info),
+                );
+            }
+            if room.connections.is_empty() {
+                rooms.remove(&self.session_id);
+            }
+        }
+
+        let handler = self.handler.clone();
+        self.shared
+            .dispatcher
+            .dispatch(move || handler(SessionEvent::Disconnected));
+
+        Ok(())
+    }
+
+    fn publish(&self, publisher: &Arc<dyn PublisherBackend>) -> Result<(), Error> {
+        let publisher = publisher
+            .as_any()
+            .downcast_ref::<MockPublisher>()
+            .ok_or_else(|| Error::Backend("Not a mock publisher".into()))?;
+
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(Error::InvalidState("Cannot publish before connecting"));
+        }
+
+        let mut published = publisher.published.lock().unwrap();
+        if published.is_some() {
+            return Err(Error::InvalidState("Publisher already publishing"));
+        }
+
+        let info = StreamInfo {
+            id: Uuid::new_v4().to_string(),
+            connection_id: self.connection_id.clone(),
+            name: publisher.name.clone(),
+            has_audio: publisher.audio.load(Ordering::SeqCst),
+            has_video: publisher.video.load(Ordering::SeqCst),
+        };
+
+        let mut rooms = self.shared.rooms.lock().unwrap();
+        let room = rooms.entry(self.session_id.clone()).or_default();
+        room.streams.insert(
+            info.id.clone(),
+            PublishedStream {
+                info: info.clone(),
+                publisher_handler: publisher.handler.clone(),
+                publisher_published: publisher.published.clone(),
+                publisher_settings: publisher.settings.clone(),
+                subscribers: vec![],
+            },
+        );
+        *published = Some((self.session_id.clone(), info.id.clone()));
+
+        let dispatcher = &self.shared.dispatcher;
+        let handler = publisher.handler.clone();
+        let created = info.clone();
+        dispatcher.dispatch(move || handler(PublisherEvent::StreamCreated(created)));
+        room.notify_others(
+            dispatcher,
+            &self.connection_id,
+            SessionEvent::StreamReceived(info),
+        );
+
+        Ok(())
+    }
+
+    fn subscribe(
+        &self,
+        stream_id: &str,
+        settings: &SubscriberSettings,
+        handler: SubscriberEventHandler,
+    ) -> Result<Arc<dyn SubscriberBackend>, Error> {
+        let mut rooms = self.shared.rooms.lock().unwrap();
+        let stream = rooms
+            .get_mut(&self.session_id)
+            .and_then(|room| room.streams.get_mut(stream_id))
+            .ok_or_else(|| Error::SubscriberStreamSetupFailed(stream_id.into()))?;
+
+        let id = self
+            .shared
+            .next_subscriber_id
+            .fetch_add(1, Ordering::SeqCst);
+        stream
+            .subscribers
+            .push((id, settings.clone(), handler.clone()));
+        self.shared
+            .dispatcher
+            .dispatch(move || handler(SubscriberEvent::Connected));
+
+        Ok(Arc::new(MockSubscriber {
+            shared: self.shared.clone(),
+            session_id: self.session_id.clone(),
+            stream_id: stream_id.into(),
+            id,
+        }))
+    }
+
+    fn send_signal(
+        &self,
+        signal_type: &str,
+        data: &str,
+        connection_id: Option<&str>,
+    ) -> Result<(), Error> {
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(Error::InvalidState("Cannot signal before connecting"));
+        }
+
+        let event = SessionEvent::SignalReceived(Signal {
+            signal_type: signal_type.into(),
+            data: data.into(),
+            connection_id: self.connection_id.clone(),
+        });
+        let rooms = self.shared.rooms.lock().unwrap();
+        let room = rooms
+            .get(&self.session_id)
+            .ok_or(Error::InvalidState("Cannot signal before connecting"))?;
+        match connection_id {
+            Some(connection_id) => {
+                let connection = room
+                    .connections
+                    .iter()
+                    .find(|c| c.id == connection_id)
+                    .ok_or_else(|| {
+                        Error::Backend(format!("Unknown connection {}", connection_id))
+                    })?;
+                let handler = connection.handler.clone();
+                self.shared.dispatcher.dispatch(move || handler(event));
+            }
+            None => room.notify_all(&self.shared.dispatcher, event),
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MockSession {
+    fn drop(&mut self) {
+        let _ = self.disconnect();
+    }
+}
+
+struct MockPublisher {
+    shared: Arc<Shared>,
+    name: String,
+    settings: Arc<Mutex<(PublisherSettings, CapturerSettings)>>,
+    handler: PublisherEventHandler,
+    audio: AtomicBool,
+    video: AtomicBool,
+    published: Published,
+}
+
+impl MockPublisher {
+    fn toggle(&self, enabled: bool, video: bool) {
+        let flag = if video { &self.video } else { &self.audio };
+        if flag.swap(enabled, Ordering::SeqCst) == enabled {
+            return;
+        }
+
+        if let Some((ref session_id, ref stream_id)) = *self.published.lock().unwrap() {
+            let mut rooms = self.shared.rooms.lock().unwrap();
+            if let Some(room) = rooms.get_mut(session_id) {
+                if let Some(stream) = room.streams.get_mut(stream_id) {
+                    let change = if video {
+                        stream.info.has_video = enabled;
+                        StreamChange::HasVideo(enabled)
+                    } else {
+                        stream.info.has_audio = enabled;
+                        StreamChange::HasAudio(enabled)
+                    };
+                    let info = stream.info.clone();
+                    let connection_id = info.connection_id.clone();
+                    room.notify_others(
+                        &self.shared.dispatcher,
+                        &connection_id,
+                        SessionEvent::StreamPropertyChanged(info, change),
+                    );
+                }
+                let event = match (video, enabled) {
+                    (true, true) => SubscriberEvent::VideoEnabled,
+                    (true, false) => SubscriberEvent::VideoDisabled,
+                    (false, true) => SubscriberEvent::AudioEnabled,
+                    (false, false) => SubscriberEvent::AudioDisabled,
+                };
+                room.notify_subscribers(&self.shared.dispatcher, stream_id, event);
+            }
+        }
+    }
+}
+
+impl PublisherBackend for MockPublisher {
+    fn provide_video_frame(&self, frame: &VideoFrameData) -> Result<(), Error> {
+        if !self.video.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some((ref session_id, ref stream_id)) = *self.published.lock().unwrap() {
+            let rooms = self.shared.rooms.lock().unwrap();
+            if let Some(room) = rooms.get(session_id) {
+                room.notify_subscribers(
+                    &self.shared.dispatcher,
+                    stream_id,
+                    SubscriberEvent::VideoFrame(frame.clone()),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn toggle_audio(&self, enabled: bool) -> Result<(), Error> {
+        self.toggle(enabled, false);
+        Ok(())
+    }
+
+    fn toggle_video(&self, enabled: bool) -> Result<(), Error> {
+        self.toggle(enabled, true);
+        Ok(())
+    }
+
+    fn update_settings(&self, settings: &PublisherSettings) -> Result<(), Error> {
+        let video_type = {
+            let current = &mut self.settings.lock().unwrap().0;
+            let video_type = current.video_type;
+            current.video_type = settings.video_type;
+            current.content_hint = settings.content_hint;
+            current.max_video_bitrate = settings.max_video_bitrate;
+            current.audio_fallback = settings.audio_fallback;
+            video_type
+        };
+        if video_type == settings.video_type {
+            return Ok(());
+        }
+
+        if let Some((ref session_id, ref stream_id)) = *self.published.lock().unwrap() {
+            let rooms = self.shared.rooms.lock().unwrap();
+            if let Some(room) = rooms.get(session_id) {
+                if let Some(stream) = room.streams.get(stream_id) {
+                    let info = stream.info.clone();
+                    let connection_id = info.connection_id.clone();
+                    room.notify_others(
+                        &self.shared.dispatcher,
+                        &connection_id,
+                        SessionEvent::StreamPropertyChanged(
+                            info,
+                            StreamChange::VideoType(settings.video_type.as_str()),
+                        ),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn reconfigure_capturer(&self, capturer: &CapturerSettings) -> Result<(), Error> {
+        let resized = {
+            let current = &mut self.settings.lock().unwrap().1;
+            let resized = (current.width, current.height) != (capturer.width, capturer.height);
+            *current = capturer.clone();
+            resized
+        };
+        if !resized {
+            return Ok(());
+        }
+
+        if let Some((ref session_id, ref stream_id)) = *self.published.lock().unwrap() {
+            let rooms = self.shared.rooms.lock().unwrap();
+            if let Some(room) = rooms.get(session_id) {
+                if let Some(stream) = room.streams.get(stream_id) {
+                    let info = stream.info.clone();
+                    let connection_id = info.connection_id.clone();
+                    room.notify_others(
+                        &self.shared.dispatcher,
+                        &connection_id,
+                        SessionEvent::StreamPropertyChanged(
+                            info,
+                            StreamChange::VideoDimensions {
+                                width: capturer.width,
+                                height: capturer.height,
+                            },
+                        ),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unpublish(&self) -> Result<(), Error> {
+        if let Some((session_id, stream_id)) = self.published.lock().unwrap().take() {
+            let mut rooms = self.shared.rooms.lock().unwrap();
+            if let Some(room) = rooms.get_mut(&session_id) {
+                room.drop_stream(&self.shared.dispatcher, &stream_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+struct MockSubscriber {
+    shared: Arc<Shared>,
+    session_id: String,
+    stream_id: String,
+    id: u64,
+}
+
+impl SubscriberBackend for MockSubscriber {
+    fn stream(&self) -> StreamInfo {
+        self.shared
+            .rooms
+            .lock()
+            .unwrap()
+            .get(&self.session_id)
+            .and_then(|room| room.streams.get(&self.stream_id))
+            .map(|stream| stream.info.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockSubscriber {
+    fn drop(&mut self) {
+        let mut rooms = self.shared.rooms.lock().unwrap();
+        if let Some(stream) = rooms
+            .get_mut(&self.session_id)
+            .and_then(|room| room.streams.get_mut(&self.stream_id))
+        {
+            stream.subscribers.retain(|(id, _, _)| *id != self.id);
+        }
+    }
+}
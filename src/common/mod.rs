@@ -0,0 +1,584 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use anyhow::{anyhow, ensure};
+use gst::{gst_debug, gst_error, gst_warning};
+use gst_video::VideoFormat;
+use ipc_channel::ipc::{IpcReceiver, IpcSender};
+use once_cell::sync::Lazy;
+use opentok::log;
+use opentok::video_frame::FrameFormat;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use thiserror::Error;
+use url::Url;
+
+pub mod audio;
+pub mod backend;
+pub mod messages;
+pub mod mock;
+pub mod publisher;
+pub mod reconnect;
+pub mod room;
+mod sdk;
+pub mod secret;
+pub mod session;
+pub mod signaling;
+pub mod startup;
+pub mod state;
+pub mod timestamps;
+pub mod token;
+pub mod uri;
+
+pub use reconnect::ReconnectPolicy;
+pub use room::RoomInfoProvider;
+pub use secret::Secret;
+pub use session::{SessionHandle, SharedSession};
+pub use state::SessionState;
+use token::{generate_token, Role, TokenOptions};
+pub use uri::OpenTokUri;
+
+static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
+    gst::DebugCategory::new(
+        "opentok-common",
+        gst::DebugColorFlags::empty(),
+        Some("OpenTok common"),
+    )
+});
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot add element {0}")]
+    AddElement(&'static str),
+    #[error("Cannot get element pad {0}")]
+    ElementPad(&'static str),
+    #[error("OpenTok initialization error {0}")]
+    Init(String),
+    #[error("Invalid state: {0}")]
+    InvalidState(&'static str),
+    #[error("Cannot link elements: {0}")]
+    LinkElements(&'static str),
+    #[error("Missing element {0}. Check your GStreamer installation")]
+    MissingElement(&'static str),
+    #[error("Cannot find gst-opentok-helper binary in $PATH.")]
+    MissingOpenTokRemoteBinary,
+    #[error("Failed to launch OpenTokRemote container process")]
+    OpenTokRemoteLaunchFailed,
+    #[error("Cannot activate pad {0}")]
+    PadActivation(&'static str),
+    #[error("Cannot create pad {0} {1}")]
+    PadConstruction(&'static str, String),
+    #[error("Failed to set stream {0} on OpenTok subscriber")]
+    SubscriberStreamSetupFailed(String),
+    #[error("Not subscribing to {0}. We only care about {1}")]
+    InvalidStream(String, String),
+    #[error("OpenTok backend error: {0}")]
+    Backend(String),
+    #[error("Cannot generate token: {0}")]
+    TokenGeneration(String),
+    #[error("Invalid OpenTok URI: {0}")]
+    InvalidUri(String),
+    #[error("Cannot get room info: {0}")]
+    RoomInfo(String),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum IpcMessage {
+    Error(String),
+    PublishedStream(String),
+    Settings(RemoteSettings),
+    Stream(StreamMessage),
+    Terminate(),
+}
+
+/// Channels handed over by gst-opentok-helper when it connects to the one
+/// shot server of its parent: the parent to child sender and the child to
+/// parent receiver.
+pub type IpcPeers = (IpcSender<IpcMessage>, IpcReceiver<IpcMessage>);
+
+/// Whether gst-opentok-helper wraps a source or a sink.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum RemoteDirection {
+    Source,
+    Sink,
+}
+
+/// Everything gst-opentok-helper needs to set up its element. The parent
+/// sends it right after the IPC handshake, so that the credentials never
+/// show up on the helper command line.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RemoteSettings {
+    pub direction: RemoteDirection,
+    /// Location of the wrapped element, `opentok://` scheme.
+    pub uri: OpenTokUri,
+    /// Extra properties of the wrapped element, as `(name, value)` strings.
+    pub properties: Vec<(String, String)>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum StreamMessage {
+    Audio(StreamMessageData),
+    Video(StreamMessageData),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum StreamMessageData {
+    ShmSocketPathAdded(String, String, String),
+    ShmSocketPathRemoved(String, IpcSender<()>),
+    CapsChanged(String, String),
+}
+
+/// Environment variables used as a fallback for the credentials that were
+/// neither set explicitly nor found in the credentials file.
+pub const API_KEY_ENV_VAR: &str = "OPENTOK_API_KEY";
+pub const SESSION_ID_ENV_VAR: &str = "OPENTOK_SESSION_ID";
+pub const TOKEN_ENV_VAR: &str = "OPENTOK_TOKEN";
+
+/// Sets `field` to `value` unless it already holds a non empty string.
+fn fill<T: Default + From<String> + PartialEq>(field: &mut Option<T>, value: Option<String>) {
+    if field.as_ref().map_or(true, |field| *field == T::default()) {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            *field = Some(value.into());
+        }
+    }
+}
+
+/// OpenTok session credentials.
+#[derive(Clone, Debug, Default)]
+pub struct Credentials {
+    /// OpenTok API Key.
+    api_key: Option<String>,
+    /// OpenTok Session identifier.
+    /// You can think of a session as a “room” where clients can
+    /// interact with one another in real-time — associated with
+    /// this unique session ID.
+    session_id: Option<String>,
+    /// OpenTok Session token.
+    /// A token is a unique authentication “key” that allows a
+    /// client to join a session.
+    token: Option<Secret>,
+    /// OpenTok API secret, used to generate a token when none was given.
+    api_secret: Option<Secret>,
+    /// Settings of the generated tokens.
+    token_options: TokenOptions,
+    /// Whether `token` was generated from `api_secret`, in which case it is
+    /// generated again on each `load()`.
+    token_generated: bool,
+    /// Stream ID to subscribe to, if any.
+    /// Only useful for subscribers.
+    stream_id: Option<String>,
+
+    /// Uri of the room, conflicts with other fields
+    room_uri: Option<Url>,
+    /// How to get the other fields from `room_uri`.
+    room_info: RoomInfoProvider,
+    /// YAML or JSON file holding `apiKey`, `sessionId`, `token` and
+    /// optionally `streamId`.
+    credentials_file: Option<PathBuf>,
+}
+
+impl Credentials {
+    pub fn is_complete(&self) -> bool {
+        self.room_uri.is_some()
+            || (self.api_key.is_some()
+                && self.session_id.is_some()
+                && (self.token.is_some() || self.api_secret.is_some()))
+    }
+
+    pub fn room_uri(&self) -> Option<&Url> {
+        self.room_uri.as_ref()
+    }
+
+    pub fn stream_id(&self) -> Option<&String> {
+        self.stream_id.as_ref()
+    }
+
+    pub fn set_api_key(&mut self, key: String) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.room_uri.is_none(),
+            anyhow!("Can't set api_key when room_uri is set")
+        );
+        self.api_key = Some(key);
+        Ok(())
+    }
+
+    pub fn set_session_id(&mut self, id: String) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.room_uri.is_none(),
+            anyhow!("Can't set session_id when room_uri is set")
+        );
+        self.session_id = Some(id);
+
+        Ok(())
+    }
+
+    pub fn set_token(&mut self, token: impl Into<Secret>) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.room_uri.is_none(),
+            anyhow!("Can't set token when room_uri is set")
+        );
+        self.token = Some(token.into());
+        self.token_generated = false;
+        Ok(())
+    }
+
+    pub fn set_credentials_file(&mut self, path: Option<PathBuf>) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.room_uri.is_none(),
+            anyhow!("Can't set credentials_file when room_uri is set")
+        );
+        self.credentials_file = path;
+        Ok(())
+    }
+
+    pub fn credentials_file(&self) -> Option<&Path> {
+        self.credentials_file.as_deref()
+    }
+
+    /// Fills the missing fields from the credentials file, then from the
+    /// `OPENTOK_*` environment variables. Explicitly set fields always win.
+    pub fn resolve(&mut self) -> Result<(), anyhow::Error> {
+        if self.room_uri.is_some() {
+            return Ok(());
+        }
+
+        if let Some(path) = self.credentials_file.clone() {
+            let contents = std::fs::read_to_string(&path).map_err(|err| {
+                anyhow!("Can't read credentials file {}: {}", path.display(), err)
+            })?;
+            let (api_key, session_id, token, stream_id) =
+                if path.extension().map_or(false, |ext| ext == "json") {
+                    let json = json::parse(&contents).map_err(|err| anyhow!(err))?;
+                    let field = |key: &str| json[key].as_str().map(String::from);
+                    (
+                        field("apiKey"),
+                        field("sessionId"),
+                        field("token"),
+                        field("streamId"),
+                    )
+                } else {
+                    let docs = yaml_rust::YamlLoader::load_from_str(&contents)
+                        .map_err(|err| anyhow!(err))?;
+                    let yaml = docs
+                        .get(0)
+                        .ok_or_else(|| anyhow!("Empty credentials file"))?;
+                    let field = |key: &str| yaml[key].as_str().map(String::from);
+                    (
+                        field("apiKey"),
+                        field("sessionId"),
+                        field("token"),
+                        field("streamId"),
+                    )
+                };
+            gst_debug!(CAT, "Loaded credentials file {}", path.display());
+            fill(&mut self.api_key, api_key);
+            fill(&mut self.session_id, session_id);
+            fill(&mut self.token, token);
+            fill(&mut self.stream_id, stream_id);
+        }
+
+        fill(&mut self.api_key, std::env::var(API_KEY_ENV_VAR).ok());
+        fill(&mut self.session_id, std::env::var(SESSION_ID_ENV_VAR).ok());
+        if self.api_secret.is_none() {
+            fill(&mut self.token, std::env::var(TOKEN_ENV_VAR).ok());
+        }
+
+        Ok(())
+    }
+
+    pub fn set_api_secret(&mut self, secret: impl Into<Secret>) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.room_uri.is_none(),
+            anyhow!("Can't set api_secret when room_uri is set")
+        );
+        self.api_secret = Some(secret.into());
+        Ok(())
+    }
+
+    pub fn set_role(&mut self, role: &str) -> Result<(), anyhow::Error> {
+        self.token_options.role = role.parse::<Role>()?;
+        Ok(())
+    }
+
+    /// Sets the absolute expiry time of generated tokens, in seconds since the
+    /// Unix epoch. 0 means the default lifetime.
+    pub fn set_expire_time(&mut self, expire_time: u64) {
+        self.token_options.expire_time = if expire_time == 0 {
+            None
+        } else {
+            Some(expire_time)
+        };
+    }
+
+    pub fn set_connection_data(&mut self, data: Option<String>) {
+        self.token_options.connection_data = data;
+    }
+
+    pub fn role(&self) -> Role {
+        self.token_options.role
+    }
+
+    pub fn expire_time(&self) -> u64 {
+        self.token_options.expire_time.unwrap_or_default()
+    }
+
+    pub fn connection_data(&self) -> Option<&String> {
+        self.token_options.connection_data.as_ref()
+    }
+
+    /// Generates a token from the API key and secret, unless a token was
+    /// explicitly provided.
+    pub fn generate_token(&mut self) -> Result<(), Error> {
+        let has_token = self.token.as_ref().map_or(false, |token| !token.is_empty());
+        if has_token && !self.token_generated {
+            return Ok(());
+        }
+        if let (Some(api_key), Some(api_secret), Some(session_id)) =
+            (&self.api_key, &self.api_secret, &self.session_id)
+        {
+            let token = Secret::new(generate_token(
+                api_key,
+                api_secret.expose(),
+                session_id,
+                &self.token_options,
+            )?);
+            gst_debug!(CAT, "Generated {} token {}", self.token_options.role, token);
+            self.token = Some(token);
+            self.token_generated = true;
+        }
+        Ok(())
+    }
+
+    pub fn set_stream_id(&mut self, stream_id: String) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.room_uri.is_none(),
+            anyhow!("Can't set stream_id when room_uri is set")
+        );
+        self.stream_id = Some(stream_id);
+        Ok(())
+    }
+
+    pub fn set_room_uri(&mut self, uri: String) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.api_key.is_none() || self.session_id.is_none() || self.token.is_none(),
+            anyhow!("Can't set `room_uri` when any other field is set")
+        );
+
+        self.room_uri = Some(Url::parse(&uri).map_err(|err| {
+            glib::BoolError::new(
+                format!("Malformed url {:?}", err),
+                file!(),
+                "set_location",
+                line!(),
+            )
+        })?);
+
+        Ok(())
+    }
+
+    /// How the credentials are fetched from `room_uri`.
+    pub fn room_info(&self) -> &RoomInfoProvider {
+        &self.room_info
+    }
+
+    pub fn room_info_mut(&mut self) -> &mut RoomInfoProvider {
+        &mut self.room_info
+    }
+
+    pub fn set_room_info(&mut self, room_info: RoomInfoProvider) {
+        self.room_info = room_info;
+    }
+
+    pub async fn load(&mut self) -> Result<(), anyhow::Error> {
+        gst_debug!(CAT, "Loading!!");
+        self.resolve()?;
+        let room_uri = match self.room_uri {
+            Some(ref room_uri) => room_uri,
+            None => return self.generate_token().map_err(|err| anyhow!(err)),
+        };
+
+        let info = self.room_info.fetch(room_uri).await?;
+        self.api_key = Some(info.api_key);
+        self.session_id = Some(info.session_id);
+        self.token = Some(info.token);
+        if info.stream_id.is_some() {
+            self.stream_id = info.stream_id;
+        }
+        gst_debug!(CAT, "Loaded {:?}", self);
+
+        Ok(())
+    }
+
+    pub fn api_key(&self) -> Option<&String> {
+        self.api_key.as_ref()
+    }
+
+    pub fn session_id(&self) -> Option<&String> {
+        self.session_id.as_ref()
+    }
+
+    pub fn token(&self) -> Option<&Secret> {
+        self.token.as_ref()
+    }
+
+    /// Expiry time of the token, in seconds since the Unix epoch, if it could
+    /// be decoded.
+    pub fn token_expire_time(&self) -> Option<u64> {
+        self.token
+            .as_ref()
+            .and_then(|token| token::token_expire_time(token.expose()))
+    }
+
+    /// Location of the session, with the other fields taken from `base`.
+    /// None until the session ID is known.
+    pub fn to_uri(&self, base: &OpenTokUri) -> Option<OpenTokUri> {
+        Some(OpenTokUri {
+            session_id: self.session_id.clone().filter(|id| !id.is_empty())?,
+            api_key: self.api_key.clone(),
+            token: self.token.clone(),
+            stream_id: base.stream_id.clone().or_else(|| self.stream_id.clone()),
+            ..base.clone()
+        })
+    }
+}
+
+/// Extract credentials from urls of this form:
+/// opentok://<session id>/<stream_id>?key=<key>&token=<token>
+impl From<&OpenTokUri> for Credentials {
+    fn from(uri: &OpenTokUri) -> Credentials {
+        Credentials {
+            api_key: uri.api_key.clone(),
+            session_id: Some(uri.session_id.clone()),
+            token: uri.token.clone(),
+            stream_id: uri.stream_id.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Formats of the video frames of the SDK, and their GStreamer counterparts.
+///
+/// The SDK names the RGB formats after libyuv, whose names give the order of
+/// the components in a little endian word: `ARGB32` frames are stored as B,
+/// G, R, A bytes. MJPEG frames are `image/jpeg` buffers, described as encoded
+/// video. The compressed frames of the SDK have no known codec, and are not
+/// mapped.
+const VIDEO_FORMATS: &[(FrameFormat, VideoFormat)] = &[
+    (FrameFormat::Yuv420P, VideoFormat::I420),
+    (FrameFormat::Nv12, VideoFormat::Nv12),
+    (FrameFormat::Nv21, VideoFormat::Nv21),
+    (FrameFormat::Uyvy, VideoFormat::Uyvy),
+    (FrameFormat::Yuy2, VideoFormat::Yuy2),
+    (FrameFormat::Rgb24, VideoFormat::Bgr),
+    (FrameFormat::Argb32, VideoFormat::Bgra),
+    (FrameFormat::Bgra32, VideoFormat::Argb),
+    (FrameFormat::Abgr32, VideoFormat::Rgba),
+    (FrameFormat::Rgba32, VideoFormat::Abgr),
+    (FrameFormat::Mjpeg, VideoFormat::Encoded),
+];
+
+/// GStreamer format of the SDK frames in `format`, if supported.
+pub fn gst_from_otc_format(format: FrameFormat) -> Option<VideoFormat> {
+    VIDEO_FORMATS
+        .iter()
+        .find(|(otc_format, _)| {
+            std::mem::discriminant(otc_format) == std::mem::discriminant(&format)
+        })
+        .map(|(_, gst_format)| *gst_format)
+}
+
+/// SDK format of the GStreamer frames in `format`, if supported.
+pub fn otc_format_from_gst_format(format: VideoFormat) -> Option<FrameFormat> {
+    VIDEO_FORMATS
+        .iter()
+        .find(|(_, gst_format)| *gst_format == format)
+        .map(|(otc_format, _)| *otc_format)
+}
+
+/// Video caps of the received frames, in any of the supported formats, or
+/// of the published ones, which are raw video only.
+fn video_caps(received: bool) -> gst::Caps {
+    let builder = |name: &str| {
+        gst::Caps::builder(name)
+            .field("width", gst::IntRange::<i32>::new(1, i32::MAX))
+            .field("height", gst::IntRange::<i32>::new(1, i32::MAX))
+            .field(
+                "framerate",
+                gst::FractionRange::new(gst::Fraction::new(0, 1), gst::Fraction::new(i32::MAX, 1)),
+            )
+    };
+    let raw_formats = VIDEO_FORMATS
+        .iter()
+        .filter(|(_, format)| *format != VideoFormat::Encoded)
+        .map(|(_, format)| format.to_str());
+
+    let mut caps = builder("video/x-raw")
+        .field("format", gst::List::new(raw_formats))
+        .build();
+    if received {
+        caps.merge(builder("image/jpeg").build());
+    }
+    caps
+}
+
+/// Caps of the published video and audio. Any raw audio is accepted, and
+/// converted to the format of the audio device, so the audio caps do not
+/// depend on the [`audio_properties`](audio::audio_properties).
+pub fn caps() -> (gst::Caps, gst::Caps) {
+    let audio_formats = gst_audio::AUDIO_FORMATS_ALL
+        .iter()
+        .map(|format| format.to_str());
+    let audio_caps = gst::Caps::builder("audio/x-raw")
+        .field("format", gst::List::new(audio_formats))
+        .field("layout", "interleaved")
+        .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
+        .field("channels", gst::IntRange::<i32>::new(1, i32::MAX))
+        .build();
+    (video_caps(false), audio_caps)
+}
+
+/// Caps of the received video and audio. The audio pads only produce the
+/// format of the audio device, as configured by
+/// [`audio_properties`](audio::audio_properties). Pad templates belong to
+/// the element class, shared by all the elements, so the audio caps cover
+/// any configuration; the caps of the audio pads themselves are the
+/// configured ones, as set on their `appsrc` before they are exposed.
+pub fn received_caps() -> (gst::Caps, gst::Caps) {
+    let audio_caps = gst::Caps::builder("audio/x-raw")
+        .field("format", gst_audio::AUDIO_FORMAT_S16.to_str())
+        .field("layout", "interleaved")
+        .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
+        .field(
+            "channels",
+            gst::IntRange::<i32>::new(1, audio::MAX_CHANNELS as i32),
+        )
+        .build();
+    (video_caps(true), audio_caps)
+}
+
+pub fn pipe_opentok_to_gst_log(category: gst::DebugCategory) {
+    log::logger_callback(Box::new(move |msg| {
+        if msg.contains("ERROR") {
+            if msg.contains("Could not check wether there is a proxy") {
+                return;
+            }
+            gst_error!(category, "{}", msg);
+        } else if msg.contains("WARN") {
+            gst_warning!(category, "{}", msg);
+        } else {
+            gst_debug!(category, "{}", msg);
+        }
+    }))
+}
+
+static INIT: Once = Once::new();
+pub fn init() {
+    INIT.call_once(|| opentok::init().unwrap());
+}
@@ -0,0 +1,256 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Configuration of the publishers of `opentoksink`.
+//!
+//! The same properties exist on the element, where they apply to all its
+//! publishers, and on its sink pads, where they only apply to the publisher
+//! of the pad and take precedence over the element ones. The name and the
+//! preferred frame rate and resolution are used when the publisher is
+//! created. The other settings can also be changed while publishing: the
+//! audio and video are toggled right away, and the [`RUNTIME_PROPERTIES`]
+//! are applied through
+//! [`PublisherBackend::update_settings`](super::backend::PublisherBackend::update_settings).
+
+use gst::prelude::*;
+use once_cell::sync::Lazy;
+
+/// Source of the published video, as seen by the other clients.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstOpenTokVideoType")]
+pub enum VideoType {
+    #[enum_value(name = "Camera", nick = "camera")]
+    Camera,
+    #[enum_value(name = "Screen sharing", nick = "screen")]
+    Screen,
+}
+
+impl Default for VideoType {
+    fn default() -> Self {
+        VideoType::Camera
+    }
+}
+
+impl VideoType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoType::Camera => "camera",
+            VideoType::Screen => "screen",
+        }
+    }
+}
+
+/// Hint of the kind of video content, driving the encoder trade-offs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstOpenTokVideoContentHint")]
+pub enum VideoContentHint {
+    #[enum_value(name = "None", nick = "none")]
+    None,
+    #[enum_value(name = "Motion: favor smoothness", nick = "motion")]
+    Motion,
+    #[enum_value(name = "Detail: favor sharpness", nick = "detail")]
+    Detail,
+    #[enum_value(name = "Text: favor legibility", nick = "text")]
+    Text,
+}
+
+impl Default for VideoContentHint {
+    fn default() -> Self {
+        VideoContentHint::None
+    }
+}
+
+/// Settings of a publisher and of its stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublisherSettings {
+    /// Name of the stream, defaults to the `name` parameter of the location.
+    pub name: Option<String>,
+    pub video_type: VideoType,
+    pub content_hint: VideoContentHint,
+    /// Maximum video bitrate, in bits per second, 0 for no limit.
+    pub max_video_bitrate: u32,
+    /// Frame rate of the video capturer, 0/1 to use the one of the caps.
+    pub preferred_framerate: gst::Fraction,
+    /// Resolution of the video capturer, 0 to use the one of the caps.
+    pub preferred_width: u32,
+    pub preferred_height: u32,
+    /// Whether the stream may drop its video when the bandwidth is too low.
+    pub audio_fallback: bool,
+    /// Whether the audio and video are published, provided the publisher
+    /// has a pad for them.
+    pub publish_audio: bool,
+    pub publish_video: bool,
+}
+
+impl Default for PublisherSettings {
+    fn default() -> Self {
+        Self {
+            name: None,
+            video_type: VideoType::default(),
+            content_hint: VideoContentHint::default(),
+            max_video_bitrate: 0,
+            preferred_framerate: gst::Fraction::new(0, 1),
+            preferred_width: 0,
+            preferred_height: 0,
+            audio_fallback: true,
+            publish_audio: true,
+            publish_video: true,
+        }
+    }
+}
+
+/// Publisher properties applied to live publishers through
+/// [`PublisherBackend::update_settings`](super::backend::PublisherBackend::update_settings).
+pub const RUNTIME_PROPERTIES: &[&str] = &[
+    "video-type",
+    "video-content-hint",
+    "max-video-bitrate",
+    "audio-fallback",
+];
+
+/// Properties configuring the [`PublisherSettings`], shared by
+/// `opentoksink` and its pads.
+pub fn publisher_properties() -> &'static [glib::ParamSpec] {
+    static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+        let defaults = PublisherSettings::default();
+        vec![
+            glib::ParamSpecString::new(
+                "publisher-name",
+                "Publisher name",
+                "Name of the published stream, defaults to the name parameter of the location",
+                None,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecEnum::new(
+                "video-type",
+                "Video type",
+                "Source of the published video",
+                VideoType::static_type(),
+                defaults.video_type as i32,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecEnum::new(
+                "video-content-hint",
+                "Video content hint",
+                "Kind of content of the published video",
+                VideoContentHint::static_type(),
+                defaults.content_hint as i32,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "max-video-bitrate",
+                "Max video bitrate",
+                "Maximum bitrate of the published video, in bits per second (0 = unlimited)",
+                0,
+                u32::MAX,
+                defaults.max_video_bitrate,
+                glib::ParamFlags::READWRITE,
+            ),
+            gst::ParamSpecFraction::new(
+                "preferred-framerate",
+                "Preferred framerate",
+                "Frame rate of the published video (0/1 = framerate of the caps)",
+                gst::Fraction::new(0, 1),
+                gst::Fraction::new(i32::MAX, 1),
+                defaults.preferred_framerate,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "preferred-width",
+                "Preferred width",
+                "Width of the published video (0 = width of the caps)",
+                0,
+                u32::MAX,
+                defaults.preferred_width,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "preferred-height",
+                "Preferred height",
+                "Height of the published video (0 = height of the caps)",
+                0,
+                u32::MAX,
+                defaults.preferred_height,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecBoolean::new(
+                "audio-fallback",
+                "Audio fallback",
+                "Whether the video of the stream is dropped when the bandwidth is too low",
+                defaults.audio_fallback,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecBoolean::new(
+                "publish-audio",
+                "Publish audio",
+                "Whether the audio is published, if there is an audio pad",
+                defaults.publish_audio,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecBoolean::new(
+                "publish-video",
+                "Publish video",
+                "Whether the video is published, if there is a video pad",
+                defaults.publish_video,
+                glib::ParamFlags::READWRITE,
+            ),
+        ]
+    });
+
+    PROPERTIES.as_ref()
+}
+
+/// Whether `name` is one of the [`publisher_properties`].
+pub fn is_publisher_property(name: &str) -> bool {
+    publisher_properties()
+        .iter()
+        .any(|pspec| pspec.name() == name)
+}
+
+/// Sets one of the [`publisher_properties`].
+pub fn set_publisher_property(settings: &mut PublisherSettings, name: &str, value: &glib::Value) {
+    match name {
+        "publisher-name" => settings.name = value.get().expect("type checked upstream"),
+        "video-type" => settings.video_type = value.get().expect("type checked upstream"),
+        "video-content-hint" => settings.content_hint = value.get().expect("type checked upstream"),
+        "max-video-bitrate" => {
+            settings.max_video_bitrate = value.get().expect("type checked upstream")
+        }
+        "preferred-framerate" => {
+            settings.preferred_framerate = value.get().expect("type checked upstream")
+        }
+        "preferred-width" => settings.preferred_width = value.get().expect("type checked upstream"),
+        "preferred-height" => {
+            settings.preferred_height = value.get().expect("type checked upstream")
+        }
+        "audio-fallback" => settings.audio_fallback = value.get().expect("type checked upstream"),
+        "publish-audio" => settings.publish_audio = value.get().expect("type checked upstream"),
+        "publish-video" => settings.publish_video = value.get().expect("type checked upstream"),
+        _ => unimplemented!(),
+    }
+}
+
+/// Gets one of the [`publisher_properties`].
+pub fn publisher_property(settings: &PublisherSettings, name: &str) -> glib::Value {
+    match name {
+        "publisher-name" => settings.name.to_value(),
+        "video-type" => settings.video_type.to_value(),
+        "video-content-hint" => settings.content_hint.to_value(),
+        "max-video-bitrate" => settings.max_video_bitrate.to_value(),
+        "preferred-framerate" => settings.preferred_framerate.to_value(),
+        "preferred-width" => settings.preferred_width.to_value(),
+        "preferred-height" => settings.preferred_height.to_value(),
+        "audio-fallback" => settings.audio_fallback.to_value(),
+        "publish-audio" => settings.publish_audio.to_value(),
+        "publish-video" => settings.publish_video.to_value(),
+        _ => unimplemented!(),
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Reconnecting sessions that lost their connection.
+//!
+//! The SDK tries to restore a lost connection once by itself, reporting
+//! [`SessionEvent::Reconnecting`](super::backend::SessionEvent::Reconnecting)
+//! with attempt 0. When that fails too, as reported by
+//! [`SessionEvent::ConnectionLost`](super::backend::SessionEvent::ConnectionLost),
+//! the shared session disconnects and connects again with an exponential
+//! backoff, as configured by the `reconnect-*` properties, before giving
+//! up. The other errors leave the connection as it is.
+//!
+//! Applications follow the connection through element messages:
+//! `opentok/reconnecting` (with the `attempt` and `max-attempts` fields),
+//! `opentok/reconnected` and `opentok/disconnected`, the latter being
+//! followed by an error message when reconnecting failed. See
+//! [`super::messages`].
+
+use glib::ToValue;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+
+pub const DEFAULT_ATTEMPTS: u32 = 5;
+pub const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// How many times, and how often, to connect a session again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Number of attempts before giving up, 0 disables reconnection.
+    pub attempts: u32,
+    /// Delay before the first attempt, doubled on each subsequent one.
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: DEFAULT_ATTEMPTS,
+            initial_delay: DEFAULT_INITIAL_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+fn millis(duration: Duration) -> u32 {
+    duration.as_millis().min(u32::MAX.into()) as u32
+}
+
+/// Properties configuring the [`ReconnectPolicy`], shared by the elements.
+pub fn reconnect_properties() -> &'static [glib::ParamSpec] {
+    static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+        vec![
+            glib::ParamSpecUInt::new(
+                "reconnect-attempts",
+                "Reconnect attempts",
+                "Number of attempts to connect the session again once its connection is lost (0 = fail right away)",
+                0,
+                u32::MAX,
+                DEFAULT_ATTEMPTS,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "reconnect-initial-delay",
+                "Reconnect initial delay",
+                "Delay before the first reconnection attempt, doubled on each subsequent one, in milliseconds",
+                0,
+                u32::MAX,
+                millis(DEFAULT_INITIAL_DELAY),
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "reconnect-max-delay",
+                "Reconnect max delay",
+                "Maximum delay between reconnection attempts, in milliseconds",
+                0,
+                u32::MAX,
+                millis(DEFAULT_MAX_DELAY),
+                glib::ParamFlags::READWRITE,
+            ),
+        ]
+    });
+
+    PROPERTIES.as_ref()
+}
+
+/// Sets one of the [`reconnect_properties`].
+pub fn set_reconnect_property(policy: &mut ReconnectPolicy, name: &str, value: &glib::Value) {
+    let value = value.get::<u32>().expect("type checked upstream");
+    match name {
+        "reconnect-attempts" => policy.attempts = value,
+        "reconnect-initial-delay" => policy.initial_delay = Duration::from_millis(value.into()),
+        "reconnect-max-delay" => policy.max_delay = Duration::from_millis(value.into()),
+        _ => unimplemented!(),
+    }
+}
+
+/// Gets one of the [`reconnect_properties`].
+pub fn reconnect_property(policy: &ReconnectPolicy, name: &str) -> glib::Value {
+    match name {
+        "reconnect-attempts" => policy.attempts.to_value(),
+        "reconnect-initial-delay" => millis(policy.initial_delay).to_value(),
+        "reconnect-max-delay" => millis(policy.max_delay).to_value(),
+        _ => unimplemented!(),
+    }
+}
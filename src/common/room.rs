@@ -0,0 +1,342 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Fetching the credentials of a room from an HTTP service.
+//!
+//! The default provider talks to the OpenTok demo rooms
+//! (`https://opentokdemo.tokbox.com/room/<name>/info`). Other token services
+//! are supported by changing the URL template, the request headers and the
+//! paths of the fields in the JSON answer.
+
+use super::secret::Secret;
+use super::{Error, CAT};
+
+use gst::prelude::*;
+use gst::{gst_debug, gst_warning};
+use json::JsonValue;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+use url::Url;
+
+/// Placeholder replaced by the room URI, without trailing slash, in
+/// [`RoomInfoProvider::url`].
+pub const ROOM_PLACEHOLDER: &str = "{room}";
+
+pub const DEFAULT_URL_TEMPLATE: &str = "{room}/info";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before the first retry, doubled on each subsequent one.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Dot separated paths of the room info fields in the JSON answer, e.g.
+/// `data.credentials.token`. Numeric components index arrays.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomInfoFields {
+    pub api_key: String,
+    pub session_id: String,
+    pub token: String,
+    /// Optional stream to subscribe to.
+    pub stream_id: Option<String>,
+}
+
+impl Default for RoomInfoFields {
+    fn default() -> Self {
+        Self {
+            api_key: "apiKey".into(),
+            session_id: "sessionId".into(),
+            token: "token".into(),
+            stream_id: None,
+        }
+    }
+}
+
+/// Credentials of a room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomInfo {
+    pub api_key: String,
+    pub session_id: String,
+    pub token: Secret,
+    pub stream_id: Option<String>,
+}
+
+/// How to get the credentials of a room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomInfoProvider {
+    /// URL of the room info, [`ROOM_PLACEHOLDER`] being replaced by the room
+    /// URI.
+    pub url: String,
+    /// Extra request headers. Their values are redacted, as they usually
+    /// hold an authorization token.
+    pub headers: Vec<(String, Secret)>,
+    pub fields: RoomInfoFields,
+    /// Number of attempts after the first failed one.
+    pub retries: u32,
+    /// Timeout of each attempt.
+    pub timeout: Duration,
+}
+
+impl Default for RoomInfoProvider {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL_TEMPLATE.into(),
+            headers: vec![],
+            fields: Default::default(),
+            retries: 0,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+fn room_info_error(reason: impl Into<String>) -> Error {
+    Error::RoomInfo(reason.into())
+}
+
+/// Looks up a dot separated `path` in `json`. Numbers are stringified, as
+/// some services return the API key as one.
+fn lookup(json: &JsonValue, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(json, |value, key| {
+        let next = match key.parse::<usize>() {
+            Ok(index) if value.is_array() => &value[index],
+            _ => &value[key],
+        };
+        if next.is_null() {
+            None
+        } else {
+            Some(next)
+        }
+    })?;
+    value
+        .as_str()
+        .map(String::from)
+        .or_else(|| value.as_number().map(|number| number.to_string()))
+}
+
+impl RoomInfoProvider {
+    /// URL of the info of `room`.
+    pub fn url(&self, room: &Url) -> String {
+        self.url
+            .replace(ROOM_PLACEHOLDER, room.as_str().trim_end_matches('/'))
+    }
+
+    /// Extracts the room info from a JSON answer.
+    pub fn parse(&self, body: &str) -> Result<RoomInfo, Error> {
+        let json = json::parse(body).map_err(|err| room_info_error(err.to_string()))?;
+        let field = |path: &str| {
+            lookup(&json, path)
+                .ok_or_else(|| room_info_error(format!("No `{}` in room info", path)))
+        };
+
+        Ok(RoomInfo {
+            api_key: field(&self.fields.api_key)?,
+            session_id: field(&self.fields.session_id)?,
+            token: Secret::new(field(&self.fields.token)?),
+            stream_id: self
+                .fields
+                .stream_id
+                .as_deref()
+                .and_then(|path| lookup(&json, path)),
+        })
+    }
+
+    /// Fetches the info of `room`, retrying up to [`Self::retries`] times.
+    pub async fn fetch(&self, room: &Url) -> Result<RoomInfo, Error> {
+        let url = self.url(room);
+        // The query may hold secrets as well.
+        gst_debug!(
+            CAT,
+            "Fetching room info from {}",
+            url.split('?').next().unwrap_or_default()
+        );
+
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match self.fetch_once(&url).await {
+                Ok(info) => return Ok(info),
+                Err(err) if attempt < self.retries => {
+                    attempt += 1;
+                    gst_warning!(
+                        CAT,
+                        "{}, retry {} of {} in {:?}",
+                        err,
+                        attempt,
+                        self.retries,
+                        delay
+                    );
+                    async_std::task::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn fetch_once(&self, url: &str) -> Result<RoomInfo, Error> {
+        let mut request = surf::get(url);
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.expose());
+        }
+
+        let body = async_std::future::timeout(self.timeout, async {
+            let mut response = request
+                .await
+                .map_err(|err| room_info_error(err.to_string()))?;
+            if !response.status().is_success() {
+                return Err(room_info_error(format!(
+                    "Room info request failed with status {}",
+                    response.status()
+                )));
+            }
+            response
+                .body_string()
+                .await
+                .map_err(|err| room_info_error(err.to_string()))
+        })
+        .await
+        .map_err(|_| room_info_error(format!("No room info after {:?}", self.timeout)))??;
+
+        self.parse(&body)
+    }
+
+    fn headers_structure(&self) -> Option<gst::Structure> {
+        if self.headers.is_empty() {
+            return None;
+        }
+        let mut headers = gst::Structure::new_empty("headers");
+        for (name, value) in &self.headers {
+            headers.set(name, value.expose());
+        }
+        Some(headers)
+    }
+
+    fn fields_structure(&self) -> gst::Structure {
+        let mut fields = gst::Structure::builder("fields")
+            .field("api-key", &self.fields.api_key)
+            .field("session-id", &self.fields.session_id)
+            .field("token", &self.fields.token)
+            .build();
+        if let Some(ref stream_id) = self.fields.stream_id {
+            fields.set("stream-id", stream_id);
+        }
+        fields
+    }
+
+    fn set_fields_structure(&mut self, structure: Option<gst::Structure>) {
+        let defaults = RoomInfoFields::default();
+        let field = |name: &str| structure.as_ref().and_then(|s| s.get::<String>(name).ok());
+        self.fields = RoomInfoFields {
+            api_key: field("api-key").unwrap_or(defaults.api_key),
+            session_id: field("session-id").unwrap_or(defaults.session_id),
+            token: field("token").unwrap_or(defaults.token),
+            stream_id: field("stream-id"),
+        };
+    }
+}
+
+/// Properties configuring the [`RoomInfoProvider`] used with the
+/// `demo-room-uri` property, shared by all the elements.
+pub fn room_info_properties() -> &'static [glib::ParamSpec] {
+    static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+        vec![
+            glib::ParamSpecString::new(
+                "room-info-url",
+                "Room info URL",
+                "URL template of the room info, {room} being replaced by the room URI",
+                Some(DEFAULT_URL_TEMPLATE),
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecBoxed::new(
+                "room-info-headers",
+                "Room info headers",
+                "Extra HTTP headers of the room info request, as a structure of name and value strings",
+                gst::Structure::static_type(),
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecBoxed::new(
+                "room-info-fields",
+                "Room info fields",
+                "Dot separated paths of the api-key, session-id, token and optional stream-id fields in the room info",
+                gst::Structure::static_type(),
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "room-info-retries",
+                "Room info retries",
+                "Number of retries of failed room info requests",
+                0,
+                u32::MAX,
+                0,
+                glib::ParamFlags::READWRITE,
+            ),
+            glib::ParamSpecUInt::new(
+                "room-info-timeout",
+                "Room info timeout",
+                "Timeout of each room info request, in seconds",
+                1,
+                u32::MAX,
+                DEFAULT_TIMEOUT.as_secs() as u32,
+                glib::ParamFlags::READWRITE,
+            ),
+        ]
+    });
+
+    PROPERTIES.as_ref()
+}
+
+/// Sets one of the [`room_info_properties`].
+pub fn set_room_info_property(provider: &mut RoomInfoProvider, name: &str, value: &glib::Value) {
+    match name {
+        "room-info-url" => {
+            provider.url = value
+                .get::<Option<String>>()
+                .expect("type checked upstream")
+                .unwrap_or_else(|| DEFAULT_URL_TEMPLATE.into());
+        }
+        "room-info-headers" => {
+            let headers = value
+                .get::<Option<gst::Structure>>()
+                .expect("type checked upstream");
+            provider.headers = headers
+                .iter()
+                .flat_map(|headers| headers.iter())
+                .filter_map(|(name, value)| {
+                    let value = value.get::<String>().ok()?;
+                    Some((name.to_string(), Secret::new(value)))
+                })
+                .collect();
+        }
+        "room-info-fields" => {
+            provider.set_fields_structure(
+                value
+                    .get::<Option<gst::Structure>>()
+                    .expect("type checked upstream"),
+            );
+        }
+        "room-info-retries" => {
+            provider.retries = value.get::<u32>().expect("type checked upstream");
+        }
+        "room-info-timeout" => {
+            let timeout = value.get::<u32>().expect("type checked upstream");
+            provider.timeout = Duration::from_secs(timeout.into());
+        }
+        _ => unimplemented!(),
+    }
+}
+
+/// Gets one of the [`room_info_properties`].
+pub fn room_info_property(provider: &RoomInfoProvider, name: &str) -> glib::Value {
+    match name {
+        "room-info-url" => provider.url.to_value(),
+        "room-info-headers" => provider.headers_structure().to_value(),
+        "room-info-fields" => provider.fields_structure().to_value(),
+        "room-info-retries" => provider.retries.to_value(),
+        "room-info-timeout" => (provider.timeout.as_secs() as u32).to_value(),
+        _ => unimplemented!(),
+    }
+}
@@ -0,0 +1,943 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! [`Backend`] implementation on top of the OpenTok SDK.
+
+use super::audio::AudioSettings;
+use super::backend::{
+    AudioSamples, AudioSamplesHandler, Backend, CapturerSettings, ConnectionInfo, PublisherBackend,
+    PublisherEvent, PublisherEventHandler, SessionBackend, SessionEvent, SessionEventHandler,
+    Signal, StreamChange, StreamInfo, SubscriberBackend, SubscriberEvent, SubscriberEventHandler,
+    SubscriberSettings, VideoFrameData,
+};
+use super::publisher::{PublisherSettings, VideoType};
+use super::{gst_from_otc_format, init, otc_format_from_gst_format, pipe_opentok_to_gst_log};
+use super::{Error, CAT};
+
+use gst::{gst_debug, gst_trace, gst_warning};
+use gst_video::VideoFormat;
+use opentok::audio_device::{AudioDevice, AudioDeviceSettings, AudioSampleData};
+use opentok::log::{self, LogLevel};
+use opentok::publisher::{Publisher, PublisherCallbacks};
+use opentok::session::SessionError;
+use opentok::stream::{Stream, StreamVideoType};
+use opentok::subscriber::{Subscriber, SubscriberCallbacks};
+use opentok::video_capturer::{VideoCapturer, VideoCapturerCallbacks, VideoCapturerSettings};
+use opentok::video_frame::{FrameFormat, VideoFrame};
+use opentok::IntoResult;
+use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How long dropping a session waits for the SDK to disconnect it.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Copies a string owned by the SDK, empty if there is none.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn otc_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// The opentok crate does not expose the identifiers of connections, they
+/// are read through the C API.
+///
+/// # Safety
+///
+/// `connection` must be null or a connection of the SDK.
+unsafe fn connection_id(connection: *const opentok_sys::otc_connection) -> String {
+    if connection.is_null() {
+        return String::new();
+    }
+    otc_string(opentok_sys::otc_connection_get_id(connection))
+}
+
+/// The connection belongs to the stream.
+fn stream_connection_id(stream: &Stream) -> String {
+    unsafe { connection_id(opentok_sys::otc_stream_get_connection(stream.inner())) }
+}
+
+fn stream_info(stream: &Stream) -> StreamInfo {
+    StreamInfo {
+        id: stream.id(),
+        connection_id: stream_connection_id(stream),
+        name: stream.name(),
+        has_audio: stream.has_audio(),
+        has_video: stream.has_video(),
+    }
+}
+
+/// # Safety
+///
+/// `connection` must be a connection of the SDK.
+unsafe fn connection_info(connection: *const opentok_sys::otc_connection) -> ConnectionInfo {
+    ConnectionInfo {
+        id: connection_id(connection),
+        data: otc_string(opentok_sys::otc_connection_get_data(connection)),
+        creation_time: opentok_sys::otc_connection_get_creation_time(connection),
+    }
+}
+
+/// A copy of a connection of the SDK, which signals can be sent to.
+struct OwnedConnection(*mut opentok_sys::otc_connection);
+
+// Like the other SDK objects.
+unsafe impl Send for OwnedConnection {}
+unsafe impl Sync for OwnedConnection {}
+
+impl OwnedConnection {
+    /// # Safety
+    ///
+    /// `connection` must be a connection of the SDK.
+    unsafe fn copy(connection: *const opentok_sys::otc_connection) -> Self {
+        Self(opentok_sys::otc_connection_copy(connection))
+    }
+}
+
+impl Drop for OwnedConnection {
+    fn drop(&mut self) {
+        unsafe {
+            opentok_sys::otc_connection_delete(self.0);
+        }
+    }
+}
+
+fn video_type_name(video_type: StreamVideoType) -> &'static str {
+    match video_type {
+        StreamVideoType::Camera => "camera",
+        StreamVideoType::Screen => "screen",
+        StreamVideoType::Custom => "custom",
+        StreamVideoType::__Unknown => "unknown",
+    }
+}
+
+pub struct OpenTokBackend {}
+
+impl OpenTokBackend {
+    pub fn new() -> Self {
+        init();
+
+        log::enable_log(LogLevel::Error);
+        pipe_opentok_to_gst_log(*CAT);
+
+        // Make sure the audio device is ready before any session is initiated,
+        // otherwise OpenTok will use the libwebrtc default audio device.
+        let _ = AudioDevice::get_instance();
+
+        Self {}
+    }
+}
+
+impl Backend for OpenTokBackend {
+    fn name(&self) -> &'static str {
+        "opentok"
+    }
+
+    fn create_session(
+        &self,
+        api_key: &str,
+        session_id: &str,
+        handler: SessionEventHandler,
+    ) -> Result<Arc<dyn SessionBackend>, Error> {
+        let api_key = CString::new(api_key).map_err(|_| Error::Init("Invalid API key".into()))?;
+        let session_id =
+            CString::new(session_id).map_err(|_| Error::Init("Invalid session ID".into()))?;
+        let context = Arc::new(SessionContext {
+            handler,
+            streams: Default::default(),
+            connections: Default::default(),
+            state: Default::default(),
+            disconnected: Condvar::new(),
+        });
+
+        let callbacks = opentok_sys::otc_session_callbacks {
+            on_connected: Some(on_connected),
+            on_disconnected: Some(on_disconnected),
+            on_connection_created: Some(on_connection_created),
+            on_connection_dropped: Some(on_connection_dropped),
+            on_stream_received: Some(on_stream_received),
+            on_stream_dropped: Some(on_stream_dropped),
+            on_stream_has_audio_changed: Some(on_stream_has_audio_changed),
+            on_stream_has_video_changed: Some(on_stream_has_video_changed),
+            on_stream_video_dimensions_changed: Some(on_stream_video_dimensions_changed),
+            on_stream_video_type_changed: Some(on_stream_video_type_changed),
+            on_signal_received: Some(on_signal_received),
+            on_reconnection_started: Some(on_reconnection_started),
+            on_reconnected: Some(on_reconnected),
+            on_archive_started: Some(on_archive_started),
+            on_archive_stopped: Some(on_archive_stopped),
+            on_error: Some(on_session_error),
+            user_data: Arc::as_ptr(&context) as *mut c_void,
+            reserved: ptr::null_mut(),
+        };
+        let session = unsafe {
+            opentok_sys::otc_session_new(api_key.as_ptr(), session_id.as_ptr(), &callbacks)
+        };
+        if session.is_null() {
+            return Err(Error::Init("Failed to create OpenTok session".into()));
+        }
+
+        Ok(Arc::new(OpenTokSession { session, context }))
+    }
+
+    fn check_publisher_settings(&self, settings: &PublisherSettings) -> Result<(), Error> {
+        check_publisher_settings(settings)
+    }
+
+    fn create_publisher(
+        &self,
+        name: &str,
+        settings: &PublisherSettings,
+        capturer: &CapturerSettings,
+        handler: PublisherEventHandler,
+    ) -> Result<Arc<dyn PublisherBackend>, Error> {
+        check_publisher_settings(settings)?;
+
+        let video_capturer: Arc<Mutex<Option<VideoCapturer>>> = Default::default();
+        let video_capturer_callbacks = VideoCapturerCallbacks::builder()
+            .start({
+                let video_capturer = video_capturer.clone();
+                move |capturer| {
+                    gst_debug!(CAT, "Video capturer ready");
+                    *video_capturer.lock().unwrap() = Some(capturer.clone());
+                    Ok(())
+                }
+            })
+            .build();
+
+        let capturer_settings = VideoCapturerSettings {
+            format: otc_format(capturer.format)?,
+            width: capturer.width as i32,
+            height: capturer.height as i32,
+            fps: otc_fps(capturer.fps),
+            ..Default::default()
+        };
+        let capturer = VideoCapturer::new(capturer_settings, video_capturer_callbacks);
+
+        let callbacks = PublisherCallbacks::builder()
+            .on_stream_created({
+                let handler = handler.clone();
+                move |_, stream| handler(PublisherEvent::StreamCreated(stream_info(&stream)))
+            })
+            .on_stream_destroyed({
+                let handler = handler.clone();
+                move |_, stream| handler(PublisherEvent::StreamDestroyed(stream_info(&stream)))
+            })
+            .on_error(move |_, error, _| handler(PublisherEvent::Error(error.to_string())))
+            .build();
+
+        let publisher = Publisher::new(name, Some(capturer), callbacks);
+        apply_publisher_settings(&publisher, settings)?;
+
+        Ok(Arc::new(OpenTokPublisher {
+            publisher,
+            video_capturer,
+        }))
+    }
+
+    fn push_audio_samples(&self, samples: &[i16]) {
+        AudioDevice::get_instance()
+            .lock()
+            .unwrap()
+            .push_audio_sample(AudioSampleData(samples.to_vec()));
+    }
+
+    fn set_audio_capture_settings(&self, settings: &AudioSettings) {
+        AudioDevice::get_instance()
+            .lock()
+            .unwrap()
+            .override_capture_settings(AudioDeviceSettings {
+                sampling_rate: settings.rate as i32,
+                number_of_channels: settings.channels as i32,
+            });
+    }
+
+    fn set_audio_render_settings(&self, settings: &AudioSettings) {
+        AudioDevice::get_instance()
+            .lock()
+            .unwrap()
+            .override_render_settings(AudioDeviceSettings {
+                sampling_rate: settings.rate as i32,
+                number_of_channels: settings.channels as i32,
+            });
+    }
+
+    fn set_audio_samples_handler(&self, handler: Option<AudioSamplesHandler>) {
+        let callback = Box::new(move |sample: opentok::audio_device::AudioSample| {
+            if let Some(ref handler) = handler {
+                handler(AudioSamples {
+                    data: sample.data.0,
+                    rate: sample.sampling_rate as u32,
+                    channels: sample.number_of_channels as u32,
+                });
+            }
+        });
+        AudioDevice::get_instance()
+            .lock()
+            .unwrap()
+            .set_on_audio_sample_callback(callback);
+    }
+}
+
+/// Connection state of an SDK session, which the C API does not expose.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    Disconnecting,
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::Disconnected
+    }
+}
+
+/// Shared with the callbacks of an SDK session, as their user data.
+struct SessionContext {
+    handler: SessionEventHandler,
+    /// The SDK hands us stream objects that we need later on to subscribe,
+    /// so keep them around indexed by stream id.
+    streams: Mutex<HashMap<String, Stream>>,
+    /// The other connections to the session, indexed by connection id, to
+    /// send signals to them.
+    connections: Mutex<HashMap<String, OwnedConnection>>,
+    state: Mutex<ConnectionState>,
+    /// Notified once the session is disconnected.
+    disconnected: Condvar,
+}
+
+impl SessionContext {
+    /// # Safety
+    ///
+    /// `user_data` must be the user data of the callbacks of a live
+    /// [`OpenTokSession`].
+    unsafe fn from_user_data<'a>(user_data: *mut c_void) -> &'a Self {
+        &*(user_data as *const Self)
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        *self.state.lock().unwrap() = state;
+        if state == ConnectionState::Disconnected {
+            self.disconnected.notify_all();
+        }
+    }
+}
+
+unsafe extern "C" fn on_connected(_: *mut opentok_sys::otc_session, user_data: *mut c_void) {
+    let context = SessionContext::from_user_data(user_data);
+    context.set_state(ConnectionState::Connected);
+    (context.handler)(SessionEvent::Connected);
+}
+
+unsafe extern "C" fn on_disconnected(_: *mut opentok_sys::otc_session, user_data: *mut c_void) {
+    let context = SessionContext::from_user_data(user_data);
+    context.set_state(ConnectionState::Disconnected);
+    context.connections.lock().unwrap().clear();
+    (context.handler)(SessionEvent::Disconnected);
+}
+
+unsafe extern "C" fn on_reconnection_started(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    context.set_state(ConnectionState::Connecting);
+    (context.handler)(SessionEvent::Reconnecting {
+        attempt: 0,
+        max_attempts: 0,
+    });
+}
+
+unsafe extern "C" fn on_reconnected(_: *mut opentok_sys::otc_session, user_data: *mut c_void) {
+    let context = SessionContext::from_user_data(user_data);
+    context.set_state(ConnectionState::Connected);
+    (context.handler)(SessionEvent::Reconnected);
+}
+
+unsafe extern "C" fn on_connection_created(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    connection: *const opentok_sys::otc_connection,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    let info = connection_info(connection);
+    context
+        .connections
+        .lock()
+        .unwrap()
+        .insert(info.id.clone(), OwnedConnection::copy(connection));
+    (context.handler)(SessionEvent::ConnectionCreated(info));
+}
+
+unsafe extern "C" fn on_connection_dropped(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    connection: *const opentok_sys::otc_connection,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    let info = connection_info(connection);
+    context.connections.lock().unwrap().remove(&info.id);
+    (context.handler)(SessionEvent::ConnectionDropped(info));
+}
+
+unsafe extern "C" fn on_stream_received(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    let stream = Stream::from(stream);
+    let info = stream_info(&stream);
+    context
+        .streams
+        .lock()
+        .unwrap()
+        .insert(info.id.clone(), stream);
+    (context.handler)(SessionEvent::StreamReceived(info));
+}
+
+unsafe extern "C" fn on_stream_dropped(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    let info = stream_info(&Stream::from(stream));
+    context.streams.lock().unwrap().remove(&info.id);
+    (context.handler)(SessionEvent::StreamDropped(info));
+}
+
+/// Notifies a change of the stream `stream`.
+///
+/// # Safety
+///
+/// `user_data` must be the user data of the callbacks of a live
+/// [`OpenTokSession`], `stream` a stream of the SDK.
+unsafe fn stream_changed(
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+    change: StreamChange,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    let info = stream_info(&Stream::from(stream));
+    (context.handler)(SessionEvent::StreamPropertyChanged(info, change));
+}
+
+unsafe extern "C" fn on_stream_has_audio_changed(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+    has_audio: opentok_sys::otc_bool,
+) {
+    stream_changed(user_data, stream, StreamChange::HasAudio(has_audio != 0));
+}
+
+unsafe extern "C" fn on_stream_has_video_changed(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+    has_video: opentok_sys::otc_bool,
+) {
+    stream_changed(user_data, stream, StreamChange::HasVideo(has_video != 0));
+}
+
+unsafe extern "C" fn on_stream_video_dimensions_changed(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+    width: c_int,
+    height: c_int,
+) {
+    stream_changed(
+        user_data,
+        stream,
+        StreamChange::VideoDimensions {
+            width: width.max(0) as u32,
+            height: height.max(0) as u32,
+        },
+    );
+}
+
+unsafe extern "C" fn on_stream_video_type_changed(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    stream: *const opentok_sys::otc_stream,
+    video_type: opentok_sys::otc_stream_video_type,
+) {
+    let video_type = video_type_name(StreamVideoType::from(video_type));
+    stream_changed(user_data, stream, StreamChange::VideoType(video_type));
+}
+
+unsafe extern "C" fn on_signal_received(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    signal_type: *const c_char,
+    data: *const c_char,
+    connection: *const opentok_sys::otc_connection,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    (context.handler)(SessionEvent::SignalReceived(Signal {
+        signal_type: otc_string(signal_type),
+        data: otc_string(data),
+        connection_id: connection_id(connection),
+    }));
+}
+
+unsafe extern "C" fn on_archive_started(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    archive_id: *const c_char,
+    name: *const c_char,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    (context.handler)(SessionEvent::ArchiveStarted {
+        id: otc_string(archive_id),
+        name: otc_string(name),
+    });
+}
+
+unsafe extern "C" fn on_archive_stopped(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    archive_id: *const c_char,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    (context.handler)(SessionEvent::ArchiveStopped {
+        id: otc_string(archive_id),
+    });
+}
+
+unsafe extern "C" fn on_session_error(
+    _: *mut opentok_sys::otc_session,
+    user_data: *mut c_void,
+    error: *const c_char,
+    code: opentok_sys::otc_session_error_code,
+) {
+    let context = SessionContext::from_user_data(user_data);
+    let error = otc_string(error);
+    (context.handler)(match SessionError::from(code) {
+        SessionError::AuthorizationFailure | SessionError::InvalidSession => {
+            SessionEvent::AuthenticationFailed(error)
+        }
+        SessionError::ConnectionDropped
+        | SessionError::ConnectionFailed
+        | SessionError::ConnectionRefused
+        | SessionError::ConnectionTimedOut
+        | SessionError::NoMessagingServer => SessionEvent::ConnectionLost(error),
+        _ => SessionEvent::Error(error),
+    });
+}
+
+/// An SDK session, created through the C API: the opentok crate keeps the
+/// `otc_session` of its sessions private, and it is needed for the calls
+/// the crate does not wrap, such as sending signals.
+struct OpenTokSession {
+    session: *mut opentok_sys::otc_session,
+    context: Arc<SessionContext>,
+}
+
+// The SDK objects are internally synchronized, the elements were already
+// sharing them across threads before this abstraction existed.
+unsafe impl Send for OpenTokSession {}
+unsafe impl Sync for OpenTokSession {}
+
+impl Drop for OpenTokSession {
+    fn drop(&mut self) {
+        // The callbacks must be done with the context before the session
+        // is deleted.
+        if self.disconnect().is_ok() {
+            let state = self.context.state.lock().unwrap();
+            let _ =
+                self.context
+                    .disconnected
+                    .wait_timeout_while(state, DISCONNECT_TIMEOUT, |state| {
+                        *state != ConnectionState::Disconnected
+                    });
+        }
+        unsafe {
+            opentok_sys::otc_session_delete(self.session);
+        }
+    }
+}
+
+impl SessionBackend for OpenTokSession {
+    fn connect(&self, token: &str) -> Result<(), Error> {
+        {
+            let mut state = self.context.state.lock().unwrap();
+            if matches!(
+                *state,
+                ConnectionState::Connected | ConnectionState::Connecting
+            ) {
+                return Ok(());
+            }
+            *state = ConnectionState::Connecting;
+        }
+        let token = CString::new(token).map_err(|_| Error::Init("Invalid token".into()))?;
+        unsafe { opentok_sys::otc_session_connect(self.session, token.as_ptr()) }
+            .into_result()
+            .map_err(|e| {
+                self.context.set_state(ConnectionState::Disconnected);
+                Error::Init(format!("Connection error {:?}", e))
+            })
+    }
+
+    fn disconnect(&self) -> Result<(), Error> {
+        {
+            let mut state = self.context.state.lock().unwrap();
+            if matches!(
+                *state,
+                ConnectionState::Disconnected | ConnectionState::Disconnecting
+            ) {
+                return Ok(());
+            }
+            *state = ConnectionState::Disconnecting;
+        }
+        unsafe { opentok_sys::otc_session_disconnect(self.session) }
+            .into_result()
+            .map_err(|e| Error::Backend(e.to_string()))
+    }
+
+    fn publish(&self, publisher: &Arc<dyn PublisherBackend>) -> Result<(), Error> {
+        let publisher = publisher
+            .as_any()
+            .downcast_ref::<OpenTokPublisher>()
+            .ok_or_else(|| Error::Backend("Not an OpenTok publisher".into()))?;
+        unsafe {
+            opentok_sys::otc_session_publish(
+                self.session,
+                publisher.publisher.inner() as *mut opentok_sys::otc_publisher,
+            )
+        }
+        .into_result()
+        .map_err(|e| Error::Backend(e.to_string()))
+    }
+
+    fn subscribe(
+        &self,
+        stream_id: &str,
+        settings: &SubscriberSettings,
+        handler: SubscriberEventHandler,
+    ) -> Result<Arc<dyn SubscriberBackend>, Error> {
+        // The stream stays known until dropped, it can be subscribed again.
+        let stream = self
+            .context
+            .streams
+            .lock()
+            .unwrap()
+            .get(stream_id)
+            .cloned()
+            .ok_or_else(|| Error::SubscriberStreamSetupFailed(stream_id.into()))?;
+
+        let callbacks = SubscriberCallbacks::builder()
+            .on_render_frame({
+                let handler = handler.clone();
+                move |_, frame| {
+                    let otc_format = frame.get_format().ok();
+                    let format = match otc_format.and_then(gst_from_otc_format) {
+                        Some(format) => format,
+                        None => {
+                            gst_warning!(
+                                CAT,
+                                "Dropping video frame in unsupported format {:?}",
+                                otc_format
+                            );
+                            return;
+                        }
+                    };
+                    let data = match frame.get_buffer() {
+                        Ok(data) => data.to_vec(),
+                        Err(_) => return,
+                    };
+                    handler(SubscriberEvent::VideoFrame(VideoFrameData {
+                        format,
+                        width: frame.get_width().unwrap() as u32,
+                        height: frame.get_height().unwrap() as u32,
+                        data,
+                        timestamp: frame.get_timestamp().ok().and_then(clock_time_from_otc),
+                    }));
+                }
+            })
+            .on_video_enabled({
+                let handler = handler.clone();
+                move |_, _| handler(SubscriberEvent::VideoEnabled)
+            })
+            .on_video_disabled({
+                let handler = handler.clone();
+                move |_, _| handler(SubscriberEvent::VideoDisabled)
+            })
+            .on_audio_enabled({
+                let handler = handler.clone();
+                move |_| handler(SubscriberEvent::AudioEnabled)
+            })
+            .on_audio_disabled({
+                let handler = handler.clone();
+                move |_| handler(SubscriberEvent::AudioDisabled)
+            })
+            .on_connected({
+                let handler = handler.clone();
+                move |_, _| handler(SubscriberEvent::Connected)
+            })
+            .on_disconnected({
+                let handler = handler.clone();
+                move |_| handler(SubscriberEvent::Disconnected)
+            })
+            .on_reconnected({
+                let handler = handler.clone();
+                move |_| handler(SubscriberEvent::Reconnected)
+            })
+            .on_video_disable_warning({
+                let handler = handler.clone();
+                move |_| handler(SubscriberEvent::VideoDisableWarning)
+            })
+            .on_video_disable_warning_lifted({
+                let handler = handler.clone();
+                move |_| handler(SubscriberEvent::VideoDisableWarningLifted)
+            })
+            .on_error(move |_, error, _| handler(SubscriberEvent::Error(error.to_string())))
+            .build();
+
+        let subscriber = Subscriber::new(callbacks);
+        subscriber
+            .set_stream(stream)
+            .map_err(|e| Error::SubscriberStreamSetupFailed(format!("{}", e)))?;
+        subscriber
+            .set_subscribe_to_audio(settings.subscribe_to_audio)
+            .map_err(|e| Error::SubscriberStreamSetupFailed(format!("{}", e)))?;
+        subscriber
+            .set_subscribe_to_video(settings.subscribe_to_video)
+            .map_err(|e| Error::SubscriberStreamSetupFailed(format!("{}", e)))?;
+        if let Some((width, height)) = settings.preferred_resolution {
+            subscriber
+                .set_preferred_resolution(width, height)
+                .map_err(|e| Error::SubscriberStreamSetupFailed(format!("{}", e)))?;
+        }
+        unsafe {
+            opentok_sys::otc_session_subscribe(
+                self.session,
+                subscriber.inner() as *mut opentok_sys::otc_subscriber,
+            )
+        }
+        .into_result()
+        .map_err(|e| Error::Backend(e.to_string()))?;
+
+        Ok(Arc::new(OpenTokSubscriber { subscriber }))
+    }
+
+    fn send_signal(
+        &self,
+        signal_type: &str,
+        data: &str,
+        connection_id: Option<&str>,
+    ) -> Result<(), Error> {
+        let signal_type =
+            CString::new(signal_type).map_err(|_| Error::Backend("Invalid signal type".into()))?;
+        let data = CString::new(data).map_err(|_| Error::Backend("Invalid signal data".into()))?;
+        let status = match connection_id {
+            None => unsafe {
+                opentok_sys::otc_session_send_signal(
+                    self.session,
+                    signal_type.as_ptr(),
+                    data.as_ptr(),
+                )
+            },
+            Some(connection_id) => {
+                let connections = self.context.connections.lock().unwrap();
+                let connection = connections.get(connection_id).ok_or_else(|| {
+                    Error::Backend(format!("Unknown connection {}", connection_id))
+                })?;
+                unsafe {
+                    opentok_sys::otc_session_send_signal_to_connection(
+                        self.session,
+                        signal_type.as_ptr(),
+                        data.as_ptr(),
+                        connection.0,
+                    )
+                }
+            }
+        };
+        status
+            .into_result()
+            .map_err(|e| Error::Backend(format!("Failed to send signal: {}", e)))
+    }
+}
+
+/// The SDK publishers have no content hint nor maximum video bitrate.
+fn check_publisher_settings(settings: &PublisherSettings) -> Result<(), Error> {
+    let defaults = PublisherSettings::default();
+    let unsupported = [
+        (
+            "video content hint",
+            settings.content_hint != defaults.content_hint,
+        ),
+        (
+            "max video bitrate",
+            settings.max_video_bitrate != defaults.max_video_bitrate,
+        ),
+    ]
+    .iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(setting, _)| *setting)
+    .collect::<Vec<_>>();
+    if unsupported.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Backend(format!(
+        "Setting the {} of publishers is not supported by the OpenTok SDK",
+        unsupported.join(", ")
+    )))
+}
+
+/// The opentok crate only gives publishers a name and a video capturer, the
+/// video type and audio fallback are set through the C API. The SDK only
+/// reads the audio fallback when publishing.
+fn apply_publisher_settings(
+    publisher: &Publisher,
+    settings: &PublisherSettings,
+) -> Result<(), Error> {
+    let ptr = publisher.inner() as *mut opentok_sys::otc_publisher;
+    let video_type = match settings.video_type {
+        VideoType::Camera => opentok_sys::otc_publisher_video_type_OTC_PUBLISHER_VIDEO_TYPE_CAMERA,
+        VideoType::Screen => opentok_sys::otc_publisher_video_type_OTC_PUBLISHER_VIDEO_TYPE_SCREEN,
+    };
+    unsafe { opentok_sys::otc_publisher_set_video_type(ptr, video_type) }
+        .into_result()
+        .map_err(|e| Error::Backend(format!("Failed to set the video type: {}", e)))?;
+    let audio_fallback = match settings.audio_fallback {
+        true => opentok_sys::otc_constant_OTC_TRUE,
+        false => opentok_sys::otc_constant_OTC_FALSE,
+    };
+    unsafe {
+        opentok_sys::otc_publisher_set_audio_fallback_enabled(
+            ptr,
+            audio_fallback as opentok_sys::otc_bool,
+        )
+    }
+    .into_result()
+    .map_err(|e| Error::Backend(format!("Failed to set the audio fallback: {}", e)))
+}
+
+fn otc_format(format: VideoFormat) -> Result<FrameFormat, Error> {
+    otc_format_from_gst_format(format)
+        .ok_or_else(|| Error::Backend(format!("Unsupported video format {}", format)))
+}
+
+/// The SDK timestamps video frames in microseconds, 0 meaning none.
+fn clock_time_from_otc(timestamp: i64) -> Option<gst::ClockTime> {
+    match timestamp {
+        timestamp if timestamp > 0 => Some(gst::ClockTime::from_useconds(timestamp as u64)),
+        _ => None,
+    }
+}
+
+/// The SDK video capturers only take whole frame rates, the closest one is
+/// used.
+fn otc_fps(fps: gst::Fraction) -> i32 {
+    if fps.numer() <= 0 || fps.denom() <= 0 {
+        return CapturerSettings::default().fps.numer();
+    }
+    ((fps.numer() as f64 / fps.denom() as f64).round() as i32).max(1)
+}
+
+struct OpenTokPublisher {
+    publisher: Publisher,
+    video_capturer: Arc<Mutex<Option<VideoCapturer>>>,
+}
+
+unsafe impl Send for OpenTokPublisher {}
+unsafe impl Sync for OpenTokPublisher {}
+
+impl PublisherBackend for OpenTokPublisher {
+    fn provide_video_frame(&self, frame: &VideoFrameData) -> Result<(), Error> {
+        match *self.video_capturer.lock().unwrap() {
+            Some(ref capturer) => {
+                let mut otc_frame = VideoFrame::new(
+                    otc_format(frame.format)?,
+                    frame.width as i32,
+                    frame.height as i32,
+                    frame.data.clone(),
+                );
+                if let Some(timestamp) = frame.timestamp {
+                    otc_frame
+                        .set_timestamp(timestamp.useconds() as i64)
+                        .map_err(|e| Error::Backend(e.to_string()))?;
+                }
+                gst_trace!(CAT, "Providing frame through video capturer");
+                capturer
+                    .provide_frame(0, &otc_frame)
+                    .map_err(|e| Error::Backend(e.to_string()))
+            }
+            None => {
+                gst_trace!(CAT, "Video capturer not started yet, dropping frame");
+                Ok(())
+            }
+        }
+    }
+
+    fn toggle_audio(&self, enabled: bool) -> Result<(), Error> {
+        self.publisher
+            .toggle_audio(enabled)
+            .map_err(|e| Error::Backend(e.to_string()))
+    }
+
+    fn toggle_video(&self, enabled: bool) -> Result<(), Error> {
+        self.publisher
+            .toggle_video(enabled)
+            .map_err(|e| Error::Backend(e.to_string()))
+    }
+
+    fn update_settings(&self, settings: &PublisherSettings) -> Result<(), Error> {
+        check_publisher_settings(settings)?;
+        apply_publisher_settings(&self.publisher, settings)
+    }
+
+    fn reconfigure_capturer(&self, _capturer: &CapturerSettings) -> Result<(), Error> {
+        // The frames carry their own format and size, which the SDK adapts
+        // to. The opentok crate does not hand the capturer settings to the
+        // SDK, which paces the stream after the timestamps of the frames, so
+        // the frame rate can change as well.
+        Ok(())
+    }
+
+    fn unpublish(&self) -> Result<(), Error> {
+        self.publisher
+            .unpublish()
+            .map_err(|e| Error::Backend(e.to_string()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+struct OpenTokSubscriber {
+    subscriber: Subscriber,
+}
+
+unsafe impl Send for OpenTokSubscriber {}
+unsafe impl Sync for OpenTokSubscriber {}
+
+impl SubscriberBackend for OpenTokSubscriber {
+    fn stream(&self) -> StreamInfo {
+        self.subscriber
+            .get_stream()
+            .map(|stream| stream_info(&stream))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Redaction of session tokens and API secrets.
+//!
+//! Secrets are formatted as a short fingerprint, which is enough to tell two
+//! tokens apart in the logs without leaking them. Setting
+//! `GST_OPENTOK_REVEAL_SECRETS=1` prints them in full, for local debugging
+//! only.
+
+use super::OpenTokUri;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Environment variable opting into printing secrets in full.
+pub const REVEAL_SECRETS_ENV_VAR: &str = "GST_OPENTOK_REVEAL_SECRETS";
+
+static REVEAL: Lazy<AtomicBool> = Lazy::new(|| {
+    let reveal = std::env::var(REVEAL_SECRETS_ENV_VAR).map_or(false, |value| {
+        matches!(value.as_str(), "1" | "true" | "yes")
+    });
+    AtomicBool::new(reveal)
+});
+
+/// Whether secrets are currently printed in full.
+pub fn reveal_secrets() -> bool {
+    REVEAL.load(Ordering::Relaxed)
+}
+
+/// Overrides `GST_OPENTOK_REVEAL_SECRETS` for the whole process.
+pub fn set_reveal_secrets(reveal: bool) {
+    REVEAL.store(reveal, Ordering::Relaxed);
+}
+
+/// A string that is never printed as is.
+///
+/// `Debug` and `Display` only show a fingerprint of the value, use
+/// [`Secret::expose`] where the actual value is needed.
+#[derive(Clone, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// First 8 hexadecimal digits of the SHA-1 of the value.
+    pub fn fingerprint(&self) -> String {
+        Sha1::digest(self.0.as_bytes())
+            .iter()
+            .take(4)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret(value.into())
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if reveal_secrets() {
+            f.write_str(&self.0)
+        } else if self.0.is_empty() {
+            f.write_str("<empty>")
+        } else {
+            write!(f, "<redacted {}>", self.fingerprint())
+        }
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if reveal_secrets() {
+            write!(f, "Secret({:?})", self.0)
+        } else {
+            write!(f, "Secret({})", self)
+        }
+    }
+}
+
+/// Formats an `opentok://` location for the logs, with its token replaced by
+/// a fingerprint.
+pub fn redact_location(location: &str) -> String {
+    if reveal_secrets() {
+        return location.into();
+    }
+    match location.parse::<OpenTokUri>() {
+        Ok(uri) => uri.redacted(),
+        // Can't tell where the token is, keep the part that can't hold it.
+        Err(_) => location.split('?').next().unwrap_or_default().into(),
+    }
+}
@@ -0,0 +1,647 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Sessions shared by the elements of a pipeline.
+//!
+//! A [`SharedSession`] wraps a single backend session, hence a single
+//! connection to the room, on behalf of any number of `opentoksrc` and
+//! `opentoksink` elements. It is distributed through the `opentok.session`
+//! [`gst::Context`]: the first element needing a session creates it and
+//! advertises it with a `have-context` message, the next ones get it back
+//! from the pipeline when posting `need-context`.
+//!
+//! Each element joins the session through its own [`SessionHandle`], which
+//! gets all the session events. The backend connects with the first handle
+//! and disconnects when the last one leaves. When the connection is lost,
+//! the shared session connects again as described in [`super::reconnect`].
+
+use super::backend::{
+    backend, PublisherBackend, SessionBackend, SessionEvent, SessionEventHandler, StreamInfo,
+    SubscriberBackend, SubscriberEventHandler, SubscriberSettings,
+};
+use super::reconnect::ReconnectPolicy;
+use super::secret::Secret;
+use super::{Error, CAT};
+
+use gst::prelude::*;
+use gst::{gst_debug, gst_info, gst_warning};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// Type of the contexts holding a [`SharedSession`].
+pub const CONTEXT_TYPE: &str = "opentok.session";
+
+/// Time given to the streams of the room to be announced again once the
+/// session reconnected, before the missing ones are considered gone.
+const STREAMS_SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+struct Member {
+    id: u64,
+    handler: SessionEventHandler,
+    /// Whether the streams published through the session are announced to
+    /// this member.
+    own_streams: bool,
+    reconnect_policy: ReconnectPolicy,
+    /// Set once the member connected, it keeps the session connected.
+    active: bool,
+    /// Set once the events the member missed were replayed, it gets the
+    /// session events from then on.
+    joined: bool,
+}
+
+#[derive(Default)]
+struct Members {
+    next_id: u64,
+    members: Vec<Member>,
+    /// The backend session is connecting or connected.
+    connecting: bool,
+    connected: bool,
+    /// Token the session connected with, to connect again.
+    token: Option<Secret>,
+    /// Current reconnection attempt, 0 while the SDK reconnects by itself.
+    reconnecting: Option<u32>,
+    /// Bumped on each reset, so that scheduled reconnection attempts can
+    /// tell they are obsolete.
+    generation: u64,
+    /// Remote streams, announced again to members joining later on.
+    streams: HashMap<String, StreamInfo>,
+    /// Remote streams known before the connection was lost, dropped unless
+    /// announced again after reconnecting.
+    lost: HashMap<String, StreamInfo>,
+    /// Streams published through this session.
+    published: HashMap<String, StreamInfo>,
+}
+
+impl Members {
+    fn handlers(&self, own_streams_only: bool) -> Vec<SessionEventHandler> {
+        self.members
+            .iter()
+            .filter(|member| member.joined && (member.own_streams || !own_streams_only))
+            .map(|member| member.handler.clone())
+            .collect()
+    }
+
+    /// The most patient policy of the members.
+    fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.members
+            .iter()
+            .filter(|member| member.active)
+            .map(|member| member.reconnect_policy)
+            .max_by_key(|policy| policy.attempts)
+            .unwrap_or_default()
+    }
+
+    fn reset(&mut self) {
+        self.connecting = false;
+        self.connected = false;
+        self.reconnecting = None;
+        self.generation += 1;
+        self.streams.clear();
+        self.lost.clear();
+    }
+
+    /// Schedules `job` for the current generation, after `delay`.
+    fn schedule(&self, jobs: &mpsc::Sender<Job>, delay: Duration, job: fn(u64) -> Job) {
+        let jobs = jobs.clone();
+        let generation = self.generation;
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let _ = jobs.send(job(generation));
+        });
+    }
+}
+
+/// Work of the thread dispatching the session events to the members.
+enum Job {
+    Event(SessionEvent),
+    Join(u64),
+    Published(StreamInfo),
+    Unpublished(String),
+    /// Connects again, unless the session was reset in the meantime.
+    Reconnect(u64),
+    /// Drops the streams not announced again since reconnecting.
+    Expire(u64),
+    Stop,
+}
+
+/// State of the dispatching thread.
+struct Dispatcher {
+    members: Arc<Mutex<Members>>,
+    session: Weak<dyn SessionBackend>,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Dispatcher {
+    fn run(&self, receiver: mpsc::Receiver<Job>) {
+        for job in receiver {
+            if let Job::Stop = job {
+                break;
+            }
+            let (handlers, events) = self.process(job);
+            for event in events {
+                for handler in &handlers {
+                    handler(event.clone());
+                }
+            }
+        }
+    }
+
+    /// Updates the session state, returning the handlers to notify and the
+    /// events to notify them of.
+    fn process(&self, job: Job) -> (Vec<SessionEventHandler>, Vec<SessionEvent>) {
+        let mut members = self.members.lock().unwrap();
+        match job {
+            Job::Event(event) => self.session_event(&mut members, event),
+            Job::Join(id) => {
+                let connected = members.connected;
+                let mut events = vec![];
+                if connected {
+                    events.push(SessionEvent::Connected);
+                    events.extend(
+                        members
+                            .streams
+                            .values()
+                            .cloned()
+                            .map(SessionEvent::StreamReceived),
+                    );
+                }
+                let published = members.published.values().cloned().collect::<Vec<_>>();
+                match members.members.iter_mut().find(|m| m.id == id && m.active) {
+                    Some(member) => {
+                        member.joined = true;
+                        if connected && member.own_streams {
+                            events.extend(published.into_iter().map(SessionEvent::StreamReceived));
+                        }
+                        (vec![member.handler.clone()], events)
+                    }
+                    None => Default::default(),
+                }
+            }
+            Job::Published(stream) => {
+                members.published.insert(stream.id.clone(), stream.clone());
+                members.streams.remove(&stream.id);
+                (
+                    members.handlers(true),
+                    vec![SessionEvent::StreamReceived(stream)],
+                )
+            }
+            Job::Unpublished(stream_id) => match members.published.remove(&stream_id) {
+                Some(stream) => (
+                    members.handlers(true),
+                    vec![SessionEvent::StreamDropped(stream)],
+                ),
+                None => Default::default(),
+            },
+            Job::Reconnect(generation) => {
+                if generation != members.generation || members.reconnecting.is_none() {
+                    return Default::default();
+                }
+                let token = members.token.clone();
+                drop(members);
+                if let (Some(session), Some(token)) = (self.session.upgrade(), token) {
+                    // The backend may still consider itself connected, and
+                    // would then ignore the connection request.
+                    if let Err(err) = session.disconnect() {
+                        gst_debug!(CAT, "Failed to disconnect before connecting again: {}", err);
+                    }
+                    gst_debug!(CAT, "Connecting again");
+                    if let Err(err) = session.connect(token.expose()) {
+                        let _ = self
+                            .jobs
+                            .send(Job::Event(SessionEvent::ConnectionLost(err.to_string())));
+                    }
+                }
+                Default::default()
+            }
+            Job::Expire(generation) => {
+                if generation != members.generation || members.reconnecting.is_some() {
+                    return Default::default();
+                }
+                let events = members
+                    .lost
+                    .drain()
+                    .map(|(_, stream)| SessionEvent::StreamDropped(stream))
+                    .collect();
+                (members.handlers(false), events)
+            }
+            Job::Stop => Default::default(),
+        }
+    }
+
+    fn session_event(
+        &self,
+        members: &mut Members,
+        event: SessionEvent,
+    ) -> (Vec<SessionEventHandler>, Vec<SessionEvent>) {
+        let events = match event {
+            SessionEvent::Connected => {
+                members.connected = true;
+                match members.reconnecting.take() {
+                    Some(_) => {
+                        members.schedule(&self.jobs, STREAMS_SETTLE_DELAY, Job::Expire);
+                        vec![SessionEvent::Reconnected]
+                    }
+                    None => vec![event],
+                }
+            }
+            SessionEvent::Reconnecting { .. } => {
+                members.reconnecting = Some(0);
+                vec![event]
+            }
+            SessionEvent::Reconnected => {
+                members.reconnecting = None;
+                members.schedule(&self.jobs, STREAMS_SETTLE_DELAY, Job::Expire);
+                vec![event]
+            }
+            SessionEvent::Disconnected => {
+                // Expected while connecting again, and after the connection
+                // was given up on or left.
+                if members.reconnecting.is_some() || !members.connecting {
+                    return Default::default();
+                }
+                members.reset();
+                vec![event]
+            }
+            SessionEvent::AuthenticationFailed(_) => {
+                members.reset();
+                vec![event]
+            }
+            SessionEvent::ConnectionLost(ref error)
+                if members.connected || members.reconnecting.is_some() =>
+            {
+                let policy = members.reconnect_policy();
+                let attempt = members.reconnecting.unwrap_or(0) + 1;
+                if attempt > policy.attempts {
+                    gst_warning!(CAT, "Connection lost: {}", error);
+                    let error = match policy.attempts {
+                        0 => error.clone(),
+                        attempts => format!("{}, after {} reconnection attempts", error, attempts),
+                    };
+                    members.reset();
+                    vec![SessionEvent::Disconnected, SessionEvent::Error(error)]
+                } else {
+                    let delay = policy.delay(attempt);
+                    gst_info!(
+                        CAT,
+                        "Connection lost: {}, reconnecting in {:?}",
+                        error,
+                        delay
+                    );
+                    members.connected = false;
+                    members.reconnecting = Some(attempt);
+                    // The streams are announced again once connected.
+                    let streams = std::mem::take(&mut members.streams);
+                    members.lost.extend(streams);
+                    members.schedule(&self.jobs, delay, Job::Reconnect);
+                    vec![SessionEvent::Reconnecting {
+                        attempt,
+                        max_attempts: policy.attempts,
+                    }]
+                }
+            }
+            // The connection could not be established in the first place.
+            SessionEvent::ConnectionLost(error) => {
+                members.reset();
+                vec![SessionEvent::Error(error)]
+            }
+            SessionEvent::Error(_) => vec![event],
+            SessionEvent::StreamReceived(ref stream) => {
+                if members.published.contains_key(&stream.id) {
+                    return Default::default();
+                }
+                members.lost.remove(&stream.id);
+                members.streams.insert(stream.id.clone(), stream.clone());
+                vec![event]
+            }
+            SessionEvent::StreamDropped(ref stream) => {
+                if members.published.contains_key(&stream.id) {
+                    return Default::default();
+                }
+                members.streams.remove(&stream.id);
+                members.lost.remove(&stream.id);
+                vec![event]
+            }
+            SessionEvent::StreamPropertyChanged(ref stream, _) => {
+                if members.published.contains_key(&stream.id) {
+                    return Default::default();
+                }
+                if let Some(known) = members.streams.get_mut(&stream.id) {
+                    *known = stream.clone();
+                }
+                vec![event]
+            }
+            SessionEvent::ConnectionCreated(_)
+            | SessionEvent::ConnectionDropped(_)
+            | SessionEvent::ArchiveStarted { .. }
+            | SessionEvent::ArchiveStopped { .. }
+            | SessionEvent::SignalReceived(_) => vec![event],
+        };
+        (members.handlers(false), events)
+    }
+}
+
+/// A backend session used by several elements.
+pub struct SharedSession {
+    api_key: String,
+    session_id: String,
+    session: Arc<dyn SessionBackend>,
+    members: Arc<Mutex<Members>>,
+    /// Feeds the dispatching thread, which notifies the members in order and
+    /// never from the caller's stack, like the backends do.
+    jobs: Mutex<mpsc::Sender<Job>>,
+}
+
+impl SharedSession {
+    pub fn new(api_key: &str, session_id: &str) -> Result<Arc<Self>, Error> {
+        let members = Arc::new(Mutex::new(Members::default()));
+        let (sender, receiver) = mpsc::channel::<Job>();
+
+        let events = Mutex::new(sender.clone());
+        let handler: SessionEventHandler = Arc::new(move |event| {
+            let _ = events.lock().unwrap().send(Job::Event(event));
+        });
+        let session = backend().create_session(api_key, session_id, handler)?;
+
+        let dispatcher = Dispatcher {
+            members: members.clone(),
+            session: Arc::downgrade(&session),
+            jobs: sender.clone(),
+        };
+        thread::spawn(move || dispatcher.run(receiver));
+
+        Ok(Arc::new(Self {
+            api_key: api_key.into(),
+            session_id: session_id.into(),
+            session,
+            members,
+            jobs: Mutex::new(sender),
+        }))
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Registers the session events handler of an element. `own_streams`
+    /// tells whether the streams published through this session are
+    /// announced to it as well. The session reconnects according to the
+    /// most patient `reconnect_policy` of its members.
+    pub fn handle(
+        self: &Arc<Self>,
+        handler: SessionEventHandler,
+        own_streams: bool,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Arc<SessionHandle> {
+        let mut members = self.members.lock().unwrap();
+        let id = members.next_id;
+        members.next_id += 1;
+        members.members.push(Member {
+            id,
+            handler,
+            own_streams,
+            reconnect_policy,
+            active: false,
+            joined: false,
+        });
+        Arc::new(SessionHandle {
+            shared: self.clone(),
+            id,
+        })
+    }
+
+    /// Records a stream published through this session, so that it is not
+    /// mistaken for a remote one.
+    pub fn stream_published(&self, stream: &StreamInfo) {
+        self.send(Job::Published(stream.clone()));
+    }
+
+    pub fn stream_unpublished(&self, stream_id: &str) {
+        self.send(Job::Unpublished(stream_id.into()));
+    }
+
+    /// Wraps the session in an `opentok.session` context.
+    pub fn to_context(self: &Arc<Self>) -> gst::Context {
+        let mut context = gst::Context::new(CONTEXT_TYPE, true);
+        {
+            let context = context.get_mut().unwrap();
+            let structure = context.structure_mut();
+            structure.set("session-id", &self.session_id);
+            structure.set("session", SessionBox(self.clone()));
+        }
+        context
+    }
+
+    /// Unwraps the session of an `opentok.session` context.
+    pub fn from_context(context: &gst::Context) -> Option<Arc<Self>> {
+        if context.context_type() != CONTEXT_TYPE {
+            return None;
+        }
+        context
+            .structure()
+            .get::<SessionBox>("session")
+            .ok()
+            .map(|session| session.0)
+    }
+
+    fn send(&self, job: Job) {
+        let _ = self.jobs.lock().unwrap().send(job);
+    }
+
+    fn join(&self, id: u64, token: &str) -> Result<(), Error> {
+        let connect = {
+            let mut members = self.members.lock().unwrap();
+            match members.members.iter_mut().find(|m| m.id == id) {
+                Some(member) if !member.active => member.active = true,
+                _ => return Ok(()),
+            }
+            // Queued ahead of the events of a connection started below, and
+            // after the events the member missed otherwise.
+            self.send(Job::Join(id));
+            let connect = !members.connecting;
+            if connect {
+                members.connecting = true;
+                members.token = Some(Secret::new(token));
+            }
+            connect
+        };
+
+        if connect {
+            gst_debug!(CAT, "Connecting shared session {}", self.session_id);
+            if let Err(err) = self.session.connect(token) {
+                let mut members = self.members.lock().unwrap();
+                members.reset();
+                if let Some(member) = members.members.iter_mut().find(|m| m.id == id) {
+                    member.active = false;
+                    member.joined = false;
+                }
+                return Err(err);
+            }
+        } else {
+            gst_debug!(CAT, "Joining shared session {}", self.session_id);
+        }
+        Ok(())
+    }
+
+    /// Replaces the token to connect again with, if the session is
+    /// connecting or connected.
+    fn update_token(&self, token: &str) {
+        let mut members = self.members.lock().unwrap();
+        if members.connecting {
+            members.token = Some(Secret::new(token));
+        }
+    }
+
+    fn leave(&self, id: u64) -> Result<(), Error> {
+        let disconnect = {
+            let mut members = self.members.lock().unwrap();
+            members.members.retain(|m| m.id != id);
+            let disconnect = members.connecting && !members.members.iter().any(|m| m.active);
+            if disconnect {
+                members.reset();
+            }
+            disconnect
+        };
+
+        if disconnect {
+            gst_debug!(
+                CAT,
+                "Last member left, disconnecting shared session {}",
+                self.session_id
+            );
+            self.session.disconnect()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SharedSession {
+    fn drop(&mut self) {
+        self.send(Job::Stop);
+    }
+}
+
+#[derive(Clone, glib::Boxed)]
+#[boxed_type(name = "GstOpenTokSharedSession")]
+struct SessionBox(Arc<SharedSession>);
+
+/// An element's membership of a [`SharedSession`]. Dropping it leaves the
+/// session.
+pub struct SessionHandle {
+    shared: Arc<SharedSession>,
+    id: u64,
+}
+
+impl SessionHandle {
+    pub fn shared(&self) -> &Arc<SharedSession> {
+        &self.shared
+    }
+
+    /// Makes the shared session use `token` when it connects again.
+    pub fn update_token(&self, token: &str) {
+        self.shared.update_token(token)
+    }
+}
+
+impl SessionBackend for SessionHandle {
+    /// Connects the backend session if this is the first member to join,
+    /// otherwise the events of the already connected session are replayed
+    /// to this member.
+    fn connect(&self, token: &str) -> Result<(), Error> {
+        self.shared.join(self.id, token)
+    }
+
+    fn disconnect(&self) -> Result<(), Error> {
+        self.shared.leave(self.id)
+    }
+
+    fn publish(&self, publisher: &Arc<dyn PublisherBackend>) -> Result<(), Error> {
+        self.shared.session.publish(publisher)
+    }
+
+    fn subscribe(
+        &self,
+        stream_id: &str,
+        settings: &SubscriberSettings,
+        handler: SubscriberEventHandler,
+    ) -> Result<Arc<dyn SubscriberBackend>, Error> {
+        self.shared.session.subscribe(stream_id, settings, handler)
+    }
+
+    fn send_signal(
+        &self,
+        signal_type: &str,
+        data: &str,
+        connection_id: Option<&str>,
+    ) -> Result<(), Error> {
+        self.shared
+            .session
+            .send_signal(signal_type, data, connection_id)
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        let _ = self.shared.leave(self.id);
+    }
+}
+
+/// Returns the shared session of `session_id`, as given to the element
+/// through [`CONTEXT_TYPE`] contexts and stored in `slot`. When the pipeline
+/// has none, a new session is created and advertised to the pipeline.
+pub fn obtain_session(
+    element: &gst::Element,
+    slot: &Mutex<Option<Arc<SharedSession>>>,
+    api_key: &str,
+    session_id: &str,
+) -> Result<Arc<SharedSession>, Error> {
+    let lookup = || {
+        slot.lock()
+            .unwrap()
+            .clone()
+            .filter(|session| session.api_key == api_key && session.session_id == session_id)
+    };
+
+    if let Some(session) = lookup() {
+        return Ok(session);
+    }
+
+    // Elements starting concurrently would both create a session otherwise.
+    static OBTAIN_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+    let _guard = OBTAIN_LOCK.lock().unwrap();
+
+    // Bins answer synchronously, through set_context(), if they know about
+    // the session already.
+    let _ = element.post_message(
+        gst::message::NeedContext::builder(CONTEXT_TYPE)
+            .src(element)
+            .build(),
+    );
+    if let Some(session) = lookup() {
+        gst_debug!(CAT, obj: element, "Using the shared session of the pipeline");
+        return Ok(session);
+    }
+
+    gst_debug!(CAT, obj: element, "Creating shared session {}", session_id);
+    let session = SharedSession::new(api_key, session_id)?;
+    let context = session.to_context();
+    *slot.lock().unwrap() = Some(session.clone());
+    let _ = element.post_message(
+        gst::message::HaveContext::builder(context)
+            .src(element)
+            .build(),
+    );
+    Ok(session)
+}
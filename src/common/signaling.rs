@@ -0,0 +1,113 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Access to the signaling channel of the session.
+//!
+//! The elements have a `send-signal` action signal, taking the type and the
+//! data of the signal, and the connection to send it to, or `NULL` for the
+//! whole session. It returns whether the session accepted the signal. The
+//! signals received are emitted as `signal-received`, with their type, data
+//! and the connection of their sender, and posted as `opentok/signal-received`
+//! element messages, see [`super::messages`].
+
+use super::backend::{SessionBackend, Signal};
+use super::session::SessionHandle;
+use super::CAT;
+
+use glib::ToValue;
+use gst::prelude::*;
+use gst::{gst_debug, gst_warning};
+use std::sync::Arc;
+
+/// Caps of the buffers of the `signal_stream` pad of `opentoksrc`.
+pub const SIGNAL_CAPS: &str = "application/x-json";
+
+/// The `send-signal` action signal. `session` returns the session of the
+/// element, if any.
+pub fn send_signal_signal<F>(session: F) -> glib::subclass::Signal
+where
+    F: Fn(&gst::Element) -> Option<Arc<SessionHandle>> + Send + Sync + 'static,
+{
+    glib::subclass::Signal::builder(
+        "send-signal",
+        &[
+            String::static_type().into(),
+            String::static_type().into(),
+            String::static_type().into(),
+        ],
+        bool::static_type().into(),
+    )
+    .action()
+    .class_handler(move |_, args| {
+        let element = args[0].get::<gst::Element>().expect("signal arg");
+        let signal_type = args[1].get::<Option<String>>().expect("signal arg");
+        let data = args[2].get::<Option<String>>().expect("signal arg");
+        let connection_id = args[3].get::<Option<String>>().expect("signal arg");
+
+        let session = match session(&element) {
+            Some(session) => session,
+            None => {
+                gst_warning!(CAT, obj: &element, "Cannot send signal without a session");
+                return Some(false.to_value());
+            }
+        };
+        let signal_type = signal_type.unwrap_or_default();
+        gst_debug!(CAT, obj: &element, "Sending signal of type {:?}", signal_type);
+        let result = session.send_signal(
+            &signal_type,
+            data.as_deref().unwrap_or_default(),
+            connection_id.as_deref(),
+        );
+        if let Err(ref err) = result {
+            gst_warning!(CAT, obj: &element, "Cannot send signal: {}", err);
+        }
+        Some(result.is_ok().to_value())
+    })
+    .build()
+}
+
+/// The `signal-received` signal, with the type, data and sender connection
+/// of the signal.
+pub fn signal_received_signal() -> glib::subclass::Signal {
+    glib::subclass::Signal::builder(
+        "signal-received",
+        &[
+            String::static_type().into(),
+            String::static_type().into(),
+            String::static_type().into(),
+        ],
+        glib::types::Type::UNIT.into(),
+    )
+    .build()
+}
+
+/// Emits `signal-received` on `element`.
+pub fn signal_received(element: &gst::Element, signal: &Signal) {
+    gst_debug!(
+        CAT,
+        obj: element,
+        "Received signal of type {:?} from {}",
+        signal.signal_type,
+        signal.connection_id
+    );
+    element.emit_by_name::<()>(
+        "signal-received",
+        &[&signal.signal_type, &signal.data, &signal.connection_id],
+    );
+}
+
+/// Serializes `signal` as a JSON object with the `type`, `data` and
+/// `connectionId` members.
+pub fn signal_to_json(signal: &Signal) -> String {
+    let mut object = json::JsonValue::new_object();
+    object["type"] = signal.signal_type.as_str().into();
+    object["data"] = signal.data.as_str().into();
+    object["connectionId"] = signal.connection_id.as_str().into();
+    object.dump()
+}
@@ -0,0 +1,176 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Asynchronous start of `opentoksink` and `opentoksrc`.
+//!
+//! Loading the credentials of a room and connecting the session both take
+//! network round trips, so NULL→READY only kicks them off in the background.
+//! READY→PAUSED of `opentoksink` returns `ASYNC` until the session is
+//! connected. `opentoksrc` is a live source and returns `NO_PREROLL` right
+//! away, its pads only producing data once the session is connected. Either
+//! element posts an error message if connecting takes longer than its
+//! `timeout`.
+
+use super::CAT;
+
+use gst::prelude::*;
+use gst::{gst_debug, gst_warning};
+
+/// Default of the `timeout` property, in seconds.
+pub const DEFAULT_TIMEOUT: u32 = 30;
+
+/// The `timeout` property, shared by the elements.
+pub fn timeout_property() -> glib::ParamSpec {
+    glib::ParamSpecUInt::new(
+        "timeout",
+        "Timeout",
+        "Time allowed to load the credentials and connect the session, in seconds (0 = unlimited)",
+        0,
+        u32::MAX,
+        DEFAULT_TIMEOUT,
+        glib::ParamFlags::READWRITE,
+    )
+}
+
+/// Progress of the session connection, as far as state changes are
+/// concerned.
+#[derive(Debug)]
+pub struct Startup {
+    /// Value of the `timeout` property.
+    timeout_secs: u32,
+    /// The session is connected.
+    connected: bool,
+    /// READY→PAUSED returned `ASYNC` and waits for the session.
+    pending: bool,
+    /// An error message was posted, READY→PAUSED fails until the session
+    /// connects.
+    failed: bool,
+    /// Bumped on each reset, so that background tasks of a previous start
+    /// can tell they are obsolete.
+    epoch: u64,
+    /// Fires when the session took too long to connect.
+    timeout: Option<gst::SingleShotClockId>,
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_TIMEOUT,
+            connected: false,
+            pending: false,
+            failed: false,
+            epoch: 0,
+            timeout: None,
+        }
+    }
+}
+
+impl Startup {
+    pub fn timeout(&self) -> u32 {
+        self.timeout_secs
+    }
+
+    pub fn set_timeout(&mut self, seconds: u32) {
+        self.timeout_secs = seconds;
+    }
+
+    /// Schedules `on_timeout` after the `timeout`, unless the session is
+    /// connected already, a timeout is scheduled or the timeout is disabled.
+    pub fn arm<F>(&mut self, element: &gst::Element, on_timeout: F)
+    where
+        F: Fn(&gst::Element) + Send + 'static,
+    {
+        if self.connected || self.timeout.is_some() || self.timeout_secs == 0 {
+            return;
+        }
+
+        let clock = gst::SystemClock::obtain();
+        let deadline = match clock.time() {
+            Some(now) => now + gst::ClockTime::from_seconds(self.timeout_secs.into()),
+            None => return,
+        };
+        let id = clock.new_single_shot_id(deadline);
+        let element_weak = element.downgrade();
+        if let Err(err) = id.wait_async(move |_, _, _| {
+            if let Some(element) = element_weak.upgrade() {
+                on_timeout(&element);
+            }
+        }) {
+            gst_warning!(CAT, obj: element, "Cannot schedule connection timeout: {:?}", err);
+            return;
+        }
+        self.timeout = Some(id);
+    }
+
+    /// Called on READY→PAUSED. Returns whether the state change has to be
+    /// `ASYNC`.
+    pub fn begin(&mut self) -> Result<bool, gst::StateChangeError> {
+        if self.failed {
+            return Err(gst::StateChangeError);
+        }
+        self.pending = !self.connected;
+        Ok(self.pending)
+    }
+
+    /// Called on READY→PAUSED by live elements, which do not wait for the
+    /// session.
+    pub fn begin_live(&mut self) -> Result<(), gst::StateChangeError> {
+        if self.failed {
+            return Err(gst::StateChangeError);
+        }
+        Ok(())
+    }
+
+    /// Called once the session is connected. Returns whether a pending state
+    /// change has to complete.
+    pub fn connected(&mut self) -> bool {
+        gst_debug!(
+            CAT,
+            "Session connected, pending state change: {}",
+            self.pending
+        );
+        self.connected = true;
+        self.failed = false;
+        self.disarm();
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Called when the session cannot connect. Returns whether a pending
+    /// state change has to complete, the error message being posted already.
+    pub fn failed(&mut self) -> bool {
+        self.failed = true;
+        self.disarm();
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Called when the session went away while the element keeps it, e.g.
+    /// because it refused the token.
+    pub fn disconnected(&mut self) {
+        self.connected = false;
+    }
+
+    /// Called when the element drops the session.
+    pub fn reset(&mut self) {
+        self.connected = false;
+        self.pending = false;
+        self.failed = false;
+        self.epoch += 1;
+        self.disarm();
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    fn disarm(&mut self) {
+        if let Some(id) = self.timeout.take() {
+            id.unschedule();
+        }
+    }
+}
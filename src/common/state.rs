@@ -0,0 +1,180 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! State of the session of an element, as seen by applications.
+//!
+//! Both elements feed a [`SessionStateMachine`] with what happens to their
+//! session and expose the resulting [`SessionState`] as the read-only
+//! `session-state` property. Each transition notifies the property and posts
+//! an `opentok/session-state-changed` element message, with the
+//! `old-state` and `new-state` fields.
+
+use super::CAT;
+
+use gst::gst_debug;
+use gst::prelude::*;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstOpenTokSessionState")]
+pub enum SessionState {
+    #[enum_value(name = "Idle: not started", nick = "idle")]
+    Idle,
+    #[enum_value(name = "Loading credentials", nick = "loading-credentials")]
+    LoadingCredentials,
+    #[enum_value(name = "Connecting", nick = "connecting")]
+    Connecting,
+    #[enum_value(name = "Connected", nick = "connected")]
+    Connected,
+    #[enum_value(name = "Publishing a stream", nick = "publishing")]
+    Publishing,
+    #[enum_value(name = "Subscribed to streams", nick = "subscribing")]
+    Subscribing,
+    #[enum_value(name = "Reconnecting", nick = "reconnecting")]
+    Reconnecting,
+    #[enum_value(name = "Disconnected", nick = "disconnected")]
+    Disconnected,
+    #[enum_value(name = "Failed", nick = "failed")]
+    Failed,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        SessionState::Idle
+    }
+}
+
+/// What happens to the session of an element.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SessionInput {
+    /// The element started loading its credentials.
+    Start,
+    /// The element connects its session.
+    Connect,
+    Connected,
+    /// The stream of the element was published.
+    Published,
+    /// The element subscribed to its first stream.
+    Subscribed,
+    /// The element no longer publishes nor subscribes to any stream.
+    Inactive,
+    Reconnecting,
+    Reconnected,
+    /// The session was closed, or refused the token.
+    Disconnected,
+    Failed,
+    /// The element dropped its session.
+    Stop,
+}
+
+/// Session state of an element.
+#[derive(Debug, Default)]
+pub struct SessionStateMachine {
+    state: SessionState,
+    /// State of a connected session, once the element publishes or
+    /// subscribes.
+    active: Option<SessionState>,
+}
+
+impl SessionStateMachine {
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Whether media can flow through the session.
+    pub fn is_connected(&self) -> bool {
+        matches!(
+            self.state,
+            SessionState::Connected | SessionState::Publishing | SessionState::Subscribing
+        )
+    }
+
+    fn connected_state(&self) -> SessionState {
+        self.active.unwrap_or(SessionState::Connected)
+    }
+
+    /// Applies `input`, returning the previous and new states if the state
+    /// changed.
+    pub fn handle(&mut self, input: SessionInput) -> Option<(SessionState, SessionState)> {
+        use SessionState::*;
+
+        match input {
+            SessionInput::Published => self.active = Some(Publishing),
+            SessionInput::Subscribed => self.active = Some(Subscribing),
+            SessionInput::Inactive => self.active = None,
+            SessionInput::Stop => self.active = None,
+            _ => {}
+        }
+
+        let next = match (self.state, input) {
+            (_, SessionInput::Stop) => Idle,
+            (Idle | Disconnected | Failed, SessionInput::Start) => LoadingCredentials,
+            (Idle | LoadingCredentials | Disconnected | Failed, SessionInput::Connect) => {
+                Connecting
+            }
+            (Connecting | Reconnecting, SessionInput::Connected | SessionInput::Reconnected) => {
+                self.connected_state()
+            }
+            (
+                Connected | Publishing | Subscribing,
+                SessionInput::Published | SessionInput::Subscribed | SessionInput::Inactive,
+            ) => self.connected_state(),
+            (Connected | Publishing | Subscribing, SessionInput::Reconnecting) => Reconnecting,
+            (Idle, SessionInput::Disconnected | SessionInput::Failed) => Idle,
+            (_, SessionInput::Disconnected) => Disconnected,
+            (_, SessionInput::Failed) => Failed,
+            (state, _) => state,
+        };
+
+        if next == self.state {
+            return None;
+        }
+        let previous = std::mem::replace(&mut self.state, next);
+        Some((previous, next))
+    }
+}
+
+/// The read-only `session-state` property, shared by the elements.
+pub fn session_state_property() -> glib::ParamSpec {
+    glib::ParamSpecEnum::new(
+        "session-state",
+        "Session state",
+        "State of the OpenTok session of the element",
+        SessionState::static_type(),
+        SessionState::Idle as i32,
+        glib::ParamFlags::READABLE,
+    )
+}
+
+/// Feeds `input` to the state machine of `element`, notifying the
+/// `session-state` property and posting an `opentok/session-state-changed`
+/// element message if the state changed.
+pub fn update_session_state(
+    element: &gst::Element,
+    machine: &Mutex<SessionStateMachine>,
+    input: SessionInput,
+) {
+    let (previous, state) = match machine.lock().unwrap().handle(input) {
+        Some(transition) => transition,
+        None => return,
+    };
+
+    gst_debug!(CAT, obj: element, "Session state changed from {:?} to {:?}", previous, state);
+    element.notify("session-state");
+    let structure = gst::Structure::builder("opentok/session-state-changed")
+        .field("old-state", previous)
+        .field("new-state", state)
+        .build();
+    let _ = element.post_message(
+        gst::message::Element::builder(structure)
+            .src(element)
+            .build(),
+    );
+}
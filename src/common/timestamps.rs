@@ -0,0 +1,155 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Timestamps of the received streams.
+//!
+//! The SDK stamps each frame with its capture time, on a timeline of its
+//! own. [`FrameTimestamps`] maps these timestamps onto the running time of
+//! the element, through an offset estimated from the arrival times of the
+//! frames. Network jitter only ever delays frames, so the offset follows the
+//! earliest arrivals right away and the later ones slowly, which keeps the
+//! spacing of the capture timestamps while absorbing clock drift. Frames
+//! arriving more than the allowed latency after their timestamp move the
+//! offset forward.
+//!
+//! The audio and video cannot be aligned on a common timeline: the SDK
+//! mixes the audio of all the streams without any timing information, and
+//! does not expose the RTP and NTP timing that would relate the capture
+//! times of the video to it.
+
+/// Weight of a late frame in the estimated offset, as a power of two.
+const OFFSET_SMOOTHING: u32 = 4;
+/// Weight of a new interval in the estimated frame duration, as a power of
+/// two.
+const DURATION_SMOOTHING: u32 = 3;
+/// Capture timestamps jumping by more than this are a discontinuity, such
+/// as a restarted stream, and the offset is estimated again.
+const MAX_GAP: gst::ClockTime = gst::ClockTime::from_seconds(1);
+
+/// Timestamps of a frame, in running time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameTiming {
+    pub pts: gst::ClockTime,
+    /// Estimated from the previous frames, unknown for the first one.
+    pub duration: Option<gst::ClockTime>,
+    /// Set when the frame does not follow the previous one.
+    pub discont: bool,
+}
+
+/// Maps the capture timestamps of the frames of a stream to running time.
+#[derive(Debug, Default)]
+pub struct FrameTimestamps {
+    /// Running time minus capture time, in nanoseconds.
+    offset: Option<i128>,
+    last_capture: Option<gst::ClockTime>,
+    last_arrival: Option<gst::ClockTime>,
+    last_pts: Option<gst::ClockTime>,
+    duration: Option<gst::ClockTime>,
+    /// Number of intervals in the estimated duration, up to the smoothing
+    /// window.
+    intervals: u32,
+    max_framerate: Option<gst::Fraction>,
+}
+
+impl FrameTimestamps {
+    /// Forgets the previous frames, for instance after a flush.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Computes the timing of a frame captured at `capture`, if the SDK
+    /// gave it a timestamp, and arriving at the `arrival` running time, at
+    /// most `latency` after its timestamp.
+    pub fn map(
+        &mut self,
+        capture: Option<gst::ClockTime>,
+        arrival: gst::ClockTime,
+        latency: gst::ClockTime,
+    ) -> FrameTiming {
+        // The spacing of the frames, from the capture timestamps when there
+        // are some.
+        let interval = match (capture, self.last_capture, self.last_arrival) {
+            (Some(capture), Some(last), _) => capture.nseconds().checked_sub(last.nseconds()),
+            (None, None, Some(last)) => arrival.nseconds().checked_sub(last.nseconds()),
+            _ => None,
+        };
+        let discont = self.last_pts.is_some()
+            && interval.map_or(true, |interval| interval > MAX_GAP.nseconds());
+
+        let pts = match capture {
+            Some(capture) => {
+                let observed = arrival.nseconds() as i128 - capture.nseconds() as i128;
+                let offset = match self.offset {
+                    Some(offset) if !discont && observed > offset => {
+                        offset + ((observed - offset) >> OFFSET_SMOOTHING)
+                    }
+                    _ => observed,
+                };
+                let offset = offset.max(observed - latency.nseconds() as i128);
+                self.offset = Some(offset);
+                let pts = capture.nseconds() as i128 + offset;
+                pts.clamp(0, arrival.nseconds() as i128) as u64
+            }
+            // Without capture timestamps, the arrival times are all there
+            // is.
+            None => arrival.nseconds(),
+        };
+        // Keep the timestamps increasing when the offset goes down.
+        let pts = match self.last_pts {
+            Some(last) if !discont && pts <= last.nseconds() => last.nseconds() + 1,
+            _ => pts,
+        };
+
+        match interval {
+            Some(interval) if !discont && interval > 0 => self.update_duration(interval),
+            _ if discont => {
+                self.duration = None;
+                self.intervals = 0;
+            }
+            _ => (),
+        }
+        self.last_capture = capture;
+        self.last_arrival = Some(arrival);
+        self.last_pts = Some(gst::ClockTime::from_nseconds(pts));
+
+        FrameTiming {
+            pts: gst::ClockTime::from_nseconds(pts),
+            duration: self.duration,
+            discont,
+        }
+    }
+
+    /// Highest frame rate measured so far, rounded to a whole number.
+    pub fn max_framerate(&self) -> Option<gst::Fraction> {
+        self.max_framerate
+    }
+
+    fn update_duration(&mut self, interval: u64) {
+        let duration = match self.duration {
+            Some(duration) => {
+                let duration = duration.nseconds() as i128;
+                (duration + ((interval as i128 - duration) >> DURATION_SMOOTHING)) as u64
+            }
+            None => interval,
+        };
+        self.duration = Some(gst::ClockTime::from_nseconds(duration.max(1)));
+        self.intervals = (self.intervals + 1).min(1 << DURATION_SMOOTHING);
+
+        // The first intervals are too noisy to be trusted as a maximum.
+        if self.intervals < 1 << DURATION_SMOOTHING {
+            return;
+        }
+        let duration = duration.max(1);
+        let rate = (gst::ClockTime::SECOND.nseconds() + duration / 2) / duration;
+        let rate = gst::Fraction::new(rate.min(i32::MAX as u64) as i32, 1);
+        if self.max_framerate.map_or(true, |max| rate > max) {
+            self.max_framerate = Some(rate);
+        }
+    }
+}
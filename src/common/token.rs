@@ -0,0 +1,270 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Offline generation of OpenTok `T1==` tokens.
+//!
+//! This is what the OpenTok server SDKs do: the token data is a form encoded
+//! string signed with HMAC-SHA1 using the project API secret, and the whole
+//! thing is base64 encoded behind the `T1==` sentinel.
+
+use super::{Error, CAT};
+
+use gst::prelude::*;
+use gst::{gst_debug, gst_warning};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::form_urlencoded;
+use uuid::Uuid;
+
+pub const TOKEN_SENTINEL: &str = "T1==";
+
+/// Lifetime of tokens generated without an explicit expiry time.
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest lifetime accepted by the OpenTok servers.
+pub const MAX_TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const MAX_CONNECTION_DATA_LENGTH: usize = 1000;
+
+/// How long before the expiry of its token an element emits
+/// `request-token`.
+pub const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Name of the element message posted along with the `request-token`
+/// signal.
+pub const TOKEN_REQUEST_MESSAGE: &str = "opentok/request-token";
+
+/// Role granted to the clients connecting with a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Can only subscribe to streams.
+    Subscriber,
+    /// Can publish and subscribe to streams.
+    Publisher,
+    /// Can also force other clients to disconnect or unpublish.
+    Moderator,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Publisher
+    }
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Subscriber => "subscriber",
+            Role::Publisher => "publisher",
+            Role::Moderator => "moderator",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "subscriber" => Ok(Role::Subscriber),
+            "publisher" => Ok(Role::Publisher),
+            "moderator" => Ok(Role::Moderator),
+            _ => Err(Error::TokenGeneration(format!("Unknown role {}", role))),
+        }
+    }
+}
+
+/// Optional token settings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenOptions {
+    pub role: Role,
+    /// Absolute expiry time, in seconds since the Unix epoch. Defaults to
+    /// [`DEFAULT_TOKEN_LIFETIME`] after the token creation.
+    pub expire_time: Option<u64>,
+    /// Application data made available to the other clients of the
+    /// session, at most 1000 characters.
+    pub connection_data: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Generates a token allowing to connect to `session_id`.
+pub fn generate_token(
+    api_key: &str,
+    api_secret: &str,
+    session_id: &str,
+    options: &TokenOptions,
+) -> Result<String, Error> {
+    if api_key.is_empty() || api_secret.is_empty() || session_id.is_empty() {
+        return Err(Error::TokenGeneration(
+            "API key, API secret and session ID are all required".into(),
+        ));
+    }
+
+    let create_time = now();
+    let expire_time = options
+        .expire_time
+        .unwrap_or(create_time + DEFAULT_TOKEN_LIFETIME.as_secs());
+    if expire_time <= create_time {
+        return Err(Error::TokenGeneration(format!(
+            "Expire time {} is in the past",
+            expire_time
+        )));
+    }
+    if expire_time > create_time + MAX_TOKEN_LIFETIME.as_secs() {
+        return Err(Error::TokenGeneration(format!(
+            "Expire time {} is more than 30 days in the future",
+            expire_time
+        )));
+    }
+
+    let mut data = form_urlencoded::Serializer::new(String::new());
+    data.append_pair("session_id", session_id)
+        .append_pair("create_time", &create_time.to_string())
+        .append_pair("expire_time", &expire_time.to_string())
+        .append_pair("role", options.role.as_str())
+        .append_pair("nonce", &(Uuid::new_v4().as_u128() % 1_000_000).to_string());
+    if let Some(ref connection_data) = options.connection_data {
+        if connection_data.chars().count() > MAX_CONNECTION_DATA_LENGTH {
+            return Err(Error::TokenGeneration(format!(
+                "Connection data is longer than {} characters",
+                MAX_CONNECTION_DATA_LENGTH
+            )));
+        }
+        data.append_pair("connection_data", connection_data);
+    }
+    let data = data.finish();
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(api_secret.as_bytes())
+        .map_err(|err| Error::TokenGeneration(err.to_string()))?;
+    mac.update(data.as_bytes());
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let payload = format!("partner_id={}&sig={}:{}", api_key, signature, data);
+    Ok(format!("{}{}", TOKEN_SENTINEL, base64::encode(payload)))
+}
+
+/// Decodes the expiry time of a `T1==` token, in seconds since the Unix epoch.
+pub fn token_expire_time(token: &str) -> Option<u64> {
+    let payload = base64::decode(token.strip_prefix(TOKEN_SENTINEL)?).ok()?;
+    let payload = String::from_utf8(payload).ok()?;
+    let (_, data) = payload.split_once(':')?;
+    form_urlencoded::parse(data.as_bytes())
+        .find(|(key, _)| key == "expire_time")
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Whether `token` is known to be expired. Tokens that cannot be decoded are
+/// assumed to be valid, the OpenTok servers are the judge of that.
+pub fn token_is_expired(token: &str) -> bool {
+    token_expire_time(token).map_or(false, |expire_time| expire_time <= now())
+}
+
+/// Why an element asks the application for a new token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenRequestReason {
+    /// The current token expires in less than [`TOKEN_EXPIRY_MARGIN`].
+    Expiring,
+    /// The session refused the current token.
+    AuthenticationFailed,
+}
+
+impl TokenRequestReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenRequestReason::Expiring => "expiring",
+            TokenRequestReason::AuthenticationFailed => "auth-failure",
+        }
+    }
+}
+
+/// The `request-token` signal, shared by the elements connecting to a
+/// session. Its arguments are the reason of the request and the expiry time
+/// of the current token, 0 if unknown. The application answers by setting
+/// the `token` property, which is used on the next connection.
+pub fn request_token_signal() -> glib::subclass::Signal {
+    glib::subclass::Signal::builder(
+        "request-token",
+        &[String::static_type().into(), u64::static_type().into()],
+        glib::types::Type::UNIT.into(),
+    )
+    .build()
+}
+
+/// Emits `request-token` on `element` and posts the matching
+/// `opentok/request-token` element message, so that applications not
+/// connected to the signal still get a chance to react.
+pub fn request_token(element: &gst::Element, reason: TokenRequestReason, token: Option<&str>) {
+    let expire_time = token.and_then(token_expire_time).unwrap_or(0);
+    gst_debug!(
+        CAT,
+        obj: element,
+        "Requesting new token ({}), current one expires at {}",
+        reason.as_str(),
+        expire_time
+    );
+
+    element.emit_by_name::<()>("request-token", &[&reason.as_str(), &expire_time]);
+
+    let structure = gst::Structure::builder(TOKEN_REQUEST_MESSAGE)
+        .field("reason", reason.as_str())
+        .field("expire-time", expire_time)
+        .build();
+    let _ = element.post_message(
+        gst::message::Element::builder(structure)
+            .src(element)
+            .build(),
+    );
+}
+
+/// Schedules a `request-token` emission [`TOKEN_EXPIRY_MARGIN`] before the
+/// expiry of `token`. The returned clock id has to be unscheduled when the
+/// token is not used anymore.
+pub fn watch_token_expiry(element: &gst::Element, token: &str) -> Option<gst::SingleShotClockId> {
+    let expire_time = token_expire_time(token)?;
+    let delay = expire_time
+        .saturating_sub(TOKEN_EXPIRY_MARGIN.as_secs())
+        .saturating_sub(now());
+
+    let clock = gst::SystemClock::obtain();
+    let deadline = clock.time()? + gst::ClockTime::from_seconds(delay);
+    let id = clock.new_single_shot_id(deadline);
+
+    let element_weak = element.downgrade();
+    let token = token.to_string();
+    if let Err(err) = id.wait_async(move |_, _, _| {
+        if let Some(element) = element_weak.upgrade() {
+            request_token(&element, TokenRequestReason::Expiring, Some(&token));
+        }
+    }) {
+        gst_warning!(CAT, obj: element, "Cannot watch token expiry: {:?}", err);
+        return None;
+    }
+
+    Some(id)
+}
@@ -0,0 +1,216 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! `opentok://` and `opentok-remote://` locations.
+//!
+//! ```text
+//! opentok://<session id>[/<stream id>]?key=<api key>&token=<token>
+//!     [&name=<stream name>][&audio-only=true|&video-only=true]
+//!     [&resolution=<width>x<height>]
+//! ```
+//!
+//! Every field survives a parse and serialize round trip, so that the URI
+//! returned by an element can be fed back to another one.
+
+use super::secret::{reveal_secrets, Secret};
+use super::Error;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use url::{form_urlencoded, Url};
+
+pub const SCHEME: &str = "opentok";
+pub const REMOTE_SCHEME: &str = "opentok-remote";
+
+/// A parsed OpenTok location.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct OpenTokUri {
+    /// Whether this is an `opentok-remote://` location.
+    pub remote: bool,
+    pub session_id: String,
+    /// Stream to subscribe to. Subscribers only.
+    pub stream_id: Option<String>,
+    pub api_key: Option<String>,
+    pub token: Option<Secret>,
+    /// Only subscribe to streams with this name. Publishers use it as the
+    /// name of their stream.
+    pub stream_name: Option<String>,
+    /// Only receive or send audio.
+    pub audio_only: bool,
+    /// Only receive or send video.
+    pub video_only: bool,
+    /// Preferred resolution of the subscribed video streams, as width and
+    /// height.
+    pub resolution: Option<(u32, u32)>,
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidUri(reason.into())
+}
+
+fn encode_pair(key: &str, value: &str) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .append_pair(key, value)
+        .finish()
+}
+
+/// Stream IDs are encoded like query values, which [`decode_segment`]
+/// reverts.
+fn encode_segment(segment: &str) -> String {
+    form_urlencoded::byte_serialize(segment.as_bytes()).collect()
+}
+
+fn decode_segment(segment: &str) -> String {
+    form_urlencoded::parse(format!("s={}", segment).as_bytes())
+        .next()
+        .map(|(_, segment)| segment.into_owned())
+        .unwrap_or_default()
+}
+
+fn parse_flag(key: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "" | "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" => Ok(false),
+        _ => Err(invalid(format!("Invalid {} value {:?}", key, value))),
+    }
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), Error> {
+    value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| {
+            invalid(format!(
+                "Invalid resolution {:?}, expected <width>x<height>",
+                value
+            ))
+        })
+}
+
+impl OpenTokUri {
+    pub fn scheme(&self) -> &'static str {
+        if self.remote {
+            REMOTE_SCHEME
+        } else {
+            SCHEME
+        }
+    }
+
+    /// Serializes the URI, token included.
+    pub fn to_uri(&self) -> String {
+        self.format(false)
+    }
+
+    /// Serializes the URI with the token replaced by its fingerprint, unless
+    /// secrets are revealed.
+    pub fn redacted(&self) -> String {
+        self.format(!reveal_secrets())
+    }
+
+    fn format(&self, redact: bool) -> String {
+        let mut uri = format!("{}://{}", self.scheme(), self.session_id);
+        if let Some(ref stream_id) = self.stream_id {
+            uri.push('/');
+            uri.push_str(&encode_segment(stream_id));
+        }
+
+        let mut query = vec![];
+        if let Some(ref api_key) = self.api_key {
+            query.push(encode_pair("key", api_key));
+        }
+        if let Some(ref token) = self.token {
+            if redact {
+                query.push(format!("token={}", token));
+            } else {
+                query.push(encode_pair("token", token.expose()));
+            }
+        }
+        if let Some(ref stream_name) = self.stream_name {
+            query.push(encode_pair("name", stream_name));
+        }
+        if self.audio_only {
+            query.push(encode_pair("audio-only", "true"));
+        }
+        if self.video_only {
+            query.push(encode_pair("video-only", "true"));
+        }
+        if let Some((width, height)) = self.resolution {
+            query.push(encode_pair("resolution", &format!("{}x{}", width, height)));
+        }
+
+        if !query.is_empty() {
+            uri.push('?');
+            uri.push_str(&query.join("&"));
+        }
+        uri
+    }
+}
+
+impl FromStr for OpenTokUri {
+    type Err = Error;
+
+    fn from_str(location: &str) -> Result<Self, Self::Err> {
+        let url =
+            Url::parse(location).map_err(|err| invalid(format!("Malformed url {:?}", err)))?;
+
+        let remote = match url.scheme() {
+            SCHEME => false,
+            REMOTE_SCHEME => true,
+            scheme => return Err(invalid(format!("Unsupported scheme {}", scheme))),
+        };
+
+        let session_id = url
+            .host_str()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| invalid("No session ID"))?
+            .to_string();
+
+        let segments: Vec<_> = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        let stream_id = match segments.as_slice() {
+            [] => None,
+            [stream_id] => Some(decode_segment(stream_id)),
+            _ => return Err(invalid(format!("Unexpected path {}", url.path()))),
+        };
+
+        let mut uri = OpenTokUri {
+            remote,
+            session_id,
+            stream_id,
+            ..Default::default()
+        };
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "key" => uri.api_key = Some(value.into_owned()),
+                "token" => uri.token = Some(Secret::new(value.into_owned())),
+                "name" => uri.stream_name = Some(value.into_owned()),
+                "audio-only" => uri.audio_only = parse_flag(&key, &value)?,
+                "video-only" => uri.video_only = parse_flag(&key, &value)?,
+                "resolution" => uri.resolution = Some(parse_resolution(&value)?),
+                _ => return Err(invalid(format!("Unknown parameter {}", key))),
+            }
+        }
+        if uri.audio_only && uri.video_only {
+            return Err(invalid("audio-only and video-only are mutually exclusive"));
+        }
+
+        Ok(uri)
+    }
+}
+
+/// Shows the redacted form.
+impl fmt::Display for OpenTokUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.redacted())
+    }
+}
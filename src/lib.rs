@@ -15,7 +15,7 @@ mod opentoksrc;
 #[path = "./opentoksrc-remote/mod.rs"]
 mod opentoksrc_remote;
 
-pub use opentoksink::OpenTokSink;
+pub use opentoksink::{OpenTokSink, OpenTokSinkPad};
 pub use opentoksink_remote::OpenTokSinkRemote;
 pub use opentoksrc::OpenTokSrc;
 pub use opentoksrc_remote::OpenTokSrcRemote;
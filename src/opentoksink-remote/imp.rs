@@ -7,22 +7,26 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::common::{caps, Credentials, Error, IpcMessage, StreamMessage, StreamMessageData};
+use crate::common::secret::redact_location;
+use crate::common::{
+    caps, Credentials, Error, IpcMessage, IpcPeers, OpenTokUri, RemoteDirection, RemoteSettings,
+    StreamMessage, StreamMessageData,
+};
 
 use glib::subclass::prelude::*;
 use glib::{clone, ToValue};
 use gst::prelude::*;
 use gst::subclass::prelude::*;
 use gst::{gst_debug, gst_error, gst_trace, gst_warning};
-use ipc_channel::ipc::{IpcOneShotServer, IpcReceiver, IpcSender};
+use ipc_channel::ipc::{IpcOneShotServer, IpcSender};
 use once_cell::sync::{Lazy, OnceCell};
 use signal_child::Signalable;
 use std::fmt::{self, Display};
+use std::path::PathBuf;
 use std::process::Child;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use url::Url;
 use uuid::Uuid;
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
@@ -33,8 +37,6 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     )
 });
 
-type IpcPeers = (IpcSender<IpcMessage>, IpcReceiver<IpcMessage>);
-
 /// Stream type enumeration.
 #[derive(Clone, Debug, PartialEq)]
 enum StreamType {
@@ -109,28 +111,32 @@ pub struct OpenTokSinkRemote {
     audio_bin: Mutex<Option<gst::Element>>,
     /// Video stream bin.
     video_bin: Mutex<Option<gst::Element>>,
+    /// Last location set, for the options that are not credentials.
+    uri: Mutex<OpenTokUri>,
 }
 
 impl OpenTokSinkRemote {
+    /// Location of the wrapped element, with the given scheme.
+    fn session_uri(&self, credentials: &Credentials, remote: bool) -> Option<OpenTokUri> {
+        let base = OpenTokUri {
+            remote,
+            stream_id: self.stream_id.get().cloned(),
+            ..self.uri.lock().unwrap().clone()
+        };
+        credentials.to_uri(&base)
+    }
+
     fn location(&self) -> Option<String> {
-        self.credentials
-            .lock()
-            .unwrap()
-            .session_id()
-            .map(|id| format!("opentok://{}", id))
+        let credentials = self.credentials.lock().unwrap();
+        self.session_uri(&credentials, true).map(|uri| uri.to_uri())
     }
 
     fn set_location(&self, location: &str) -> Result<(), glib::BoolError> {
-        gst_debug!(CAT, "Setting location to {}", location);
-        let url = Url::parse(location).map_err(|err| {
-            glib::BoolError::new(
-                format!("Malformed url {:?}", err),
-                file!(),
-                "set_location",
-                line!(),
-            )
+        gst_debug!(CAT, "Setting location to {}", redact_location(location));
+        let uri = location.parse::<OpenTokUri>().map_err(|err| {
+            glib::BoolError::new(err.to_string(), file!(), "set_location", line!())
         })?;
-        let credentials: Credentials = url.into();
+        let credentials = Credentials::from(&uri);
         gst_debug!(CAT, "Credentials {:?}", credentials);
         if let Some(ref stream_id) = credentials.stream_id() {
             if !stream_id.is_empty() {
@@ -139,6 +145,7 @@ impl OpenTokSinkRemote {
         }
 
         *self.credentials.lock().unwrap() = credentials;
+        *self.uri.lock().unwrap() = uri;
         Ok(())
     }
 
@@ -154,29 +161,12 @@ impl OpenTokSinkRemote {
         })
     }
 
-    fn launch_child_process(
-        &self,
-        ipc_server_name: &str,
-        api_key: &str,
-        session_id: &str,
-        token: &str,
-    ) -> Result<(), Error> {
+    fn launch_child_process(&self, ipc_server_name: &str) -> Result<(), Error> {
         gst_debug!(CAT, "Spawning child process");
+        // Everything else, credentials included, goes through IPC.
         let mut command = std::process::Command::new("gst-opentok-helper");
-        command
-            .arg("--api-key")
-            .arg(api_key)
-            .arg("--session-id")
-            .arg(session_id)
-            .arg("--token")
-            .arg(token)
-            .arg("--direction")
-            .arg("sink")
-            .arg("--ipc-server")
-            .arg(ipc_server_name);
-        if let Some(stream_id) = self.stream_id.get() {
-            command.arg("--stream-id").arg(stream_id);
-        }
+        command.arg("--ipc-server").arg(ipc_server_name);
+
         *self.child_process.lock().unwrap() = Some(
             command
                 .spawn()
@@ -207,23 +197,24 @@ impl OpenTokSinkRemote {
         }
     }
 
-    fn init(
-        &self,
-        element: &gst::Element,
-        api_key: &str,
-        session_id: &str,
-        token: &str,
-    ) -> Result<(), Error> {
+    fn init(&self, element: &gst::Element, uri: OpenTokUri) -> Result<(), Error> {
         gst_debug!(CAT, obj: element, "Init");
         // Spawn the child process and the auxiliary threads and hand over the
         // ipc server name.
         let (ipc_server, ipc_server_name): (IpcOneShotServer<IpcPeers>, String) =
             IpcOneShotServer::new().map_err(|_| Error::OpenTokRemoteLaunchFailed)?;
 
-        self.launch_child_process(&ipc_server_name, api_key, session_id, token)?;
+        self.launch_child_process(&ipc_server_name)?;
 
         let (_, (ipc_sender, ipc_receiver)) = ipc_server.accept().unwrap();
-        gst_debug!(CAT, obj: element, "Got IPC sender");
+        gst_debug!(CAT, obj: element, "Got IPC peers");
+        ipc_sender
+            .send(IpcMessage::Settings(RemoteSettings {
+                direction: RemoteDirection::Sink,
+                uri,
+                properties: vec![],
+            }))
+            .map_err(|_| Error::OpenTokRemoteLaunchFailed)?;
         *self.ipc_sender.lock().unwrap() = Some(ipc_sender);
 
         let child_process = self.child_process.clone();
@@ -259,15 +250,13 @@ impl OpenTokSinkRemote {
                             IpcMessage::PublishedStream(stream_id) => {
                                 if let Some(signal_emitter) = signal_emitter.lock().unwrap().as_ref() {
                                     *published_stream_id.lock().unwrap() = Some(stream_id.clone());
-                                    let credentials = credentials.lock().unwrap().clone();
-                                    let url = format!("opentok://{}/{}?key={}&token={}",
-                                                      credentials.session_id().unwrap(),
-                                                      stream_id,
-                                                      credentials.api_key().unwrap(),
-                                                      credentials.token().unwrap()
-                                    );
-
-                                    signal_emitter.emit_published_stream(&stream_id, &url);
+                                    let base = OpenTokUri {
+                                        stream_id: Some(stream_id.clone()),
+                                        ..Default::default()
+                                    };
+                                    let uri = credentials.lock().unwrap().to_uri(&base).unwrap();
+
+                                    signal_emitter.emit_published_stream(&stream_id, &uri.to_uri());
                                 }
                             },
                             _ => {},
@@ -283,15 +272,21 @@ impl OpenTokSinkRemote {
     }
 
     fn maybe_init(&self, element: &gst::Element) -> Result<(), Error> {
-        let credentials = self.credentials.lock().unwrap();
-        if let Some(ref api_key) = credentials.api_key() {
-            if let Some(ref session_id) = credentials.session_id() {
-                if let Some(ref token) = credentials.token() {
-                    return self.init(element, api_key, session_id, token);
-                }
+        if self.ipc_sender.lock().unwrap().is_some() {
+            return Ok(());
+        }
+        let mut credentials = self.credentials.lock().unwrap();
+        async_std::task::block_on(credentials.load())
+            .map_err(|err| Error::Init(err.to_string()))?;
+        if let Some(stream_id) = credentials.stream_id().cloned() {
+            if !stream_id.is_empty() && self.stream_id.get().is_none() {
+                let _ = self.set_stream_id(stream_id);
             }
         }
-        Ok(())
+        match self.session_uri(&credentials, false) {
+            Some(uri) if uri.api_key.is_some() && uri.token.is_some() => self.init(element, uri),
+            _ => Ok(()),
+        }
     }
 
     fn teardown(&self) {
@@ -329,10 +324,17 @@ impl ObjectImpl for OpenTokSinkRemote {
     fn properties() -> &'static [glib::ParamSpec] {
         static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
             vec![
+                glib::ParamSpecString::new(
+                    "credentials-file",
+                    "CredentialsFile",
+                    "YAML or JSON file holding the apiKey, sessionId, token and optional streamId of the session",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecString::new(
                     "location",
                     "Location",
-                    "OpenTok session location (i.e. opentok-remote://<session id>/key=<api key>&token=<token>)",
+                    "OpenTok session location (i.e. opentok-remote://<session id>[/<stream id>]?key=<api key>&token=<token>[&name=<stream name>][&audio-only=true|&video-only=true][&resolution=<width>x<height>])",
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
@@ -358,6 +360,17 @@ impl ObjectImpl for OpenTokSinkRemote {
     ) {
         gst_trace!(CAT, obj: obj, "Setting property {:?}", pspec.name());
         match pspec.name() {
+            "credentials-file" => {
+                let path = value.get::<Option<String>>().expect("expected a string");
+                if let Err(e) = self
+                    .credentials
+                    .lock()
+                    .unwrap()
+                    .set_credentials_file(path.map(PathBuf::from))
+                {
+                    gst_error!(CAT, obj: obj, "Failed to set credentials file: {:?}", e)
+                }
+            }
             "location" => {
                 let location = value.get::<String>().expect("expected a string");
                 if let Err(e) = self.set_location(&location) {
@@ -370,6 +383,13 @@ impl ObjectImpl for OpenTokSinkRemote {
 
     fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
         match pspec.name() {
+            "credentials-file" => self
+                .credentials
+                .lock()
+                .unwrap()
+                .credentials_file()
+                .map(|path| path.to_string_lossy().into_owned())
+                .to_value(),
             "location" => self.location().to_value(),
             "stream-id" => self
                 .published_stream_id
@@ -599,6 +619,19 @@ impl ElementImpl for OpenTokSinkRemote {
         if transition == gst::StateChange::ReadyToNull {
             self.teardown();
         }
+        if transition == gst::StateChange::NullToReady {
+            // The location may have been set before the credentials file,
+            // or not at all.
+            if let Err(e) = self.maybe_init(element.upcast_ref::<gst::Element>()) {
+                gst_error!(
+                    CAT,
+                    obj: element,
+                    "Failed to initialize OpenTokSinkRemote: {:?}",
+                    e
+                );
+                return Err(gst::StateChangeError);
+            }
+        }
 
         let success = self.parent_change_state(element, transition)?;
         gst_debug!(CAT, obj: element, "State changed {:?}", transition);
@@ -7,27 +7,46 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::common::{
-    caps, otc_format_from_gst_format, pipe_opentok_to_gst_log, Credentials, Error, init
+use crate::common::audio::{
+    audio_device_caps, audio_properties, audio_property, audio_settings_mutable, is_audio_property,
+    set_audio_property, AudioSettings,
 };
+use crate::common::backend::{
+    backend, CapturerSettings, PublisherBackend, PublisherEvent, PublisherEventHandler,
+    SessionBackend, SessionEvent, SessionEventHandler, StreamChange, StreamInfo, VideoFrameData,
+};
+use crate::common::messages::{
+    post_session_message, with_publisher_messages, with_session_messages,
+};
+use crate::common::publisher::{
+    is_publisher_property, publisher_properties, publisher_property, set_publisher_property,
+    PublisherSettings, RUNTIME_PROPERTIES,
+};
+use crate::common::reconnect::{reconnect_properties, reconnect_property, set_reconnect_property};
+use crate::common::room::{room_info_properties, room_info_property, set_room_info_property};
+use crate::common::secret::redact_location;
+use crate::common::session::{obtain_session, SessionHandle, SharedSession};
+use crate::common::signaling::{send_signal_signal, signal_received, signal_received_signal};
+use crate::common::startup::{timeout_property, Startup};
+use crate::common::state::{
+    session_state_property, update_session_state, SessionInput, SessionStateMachine,
+};
+use crate::common::token::{
+    request_token, request_token_signal, token_is_expired, watch_token_expiry, TokenRequestReason,
+};
+use crate::common::{caps, Credentials, Error, OpenTokUri, ReconnectPolicy, SessionState};
 
 use byte_slice_cast::*;
 use glib::subclass::prelude::*;
 use glib::{clone, ToValue};
 use gst::prelude::*;
 use gst::subclass::prelude::*;
-use gst::{gst_debug, gst_error, gst_info, gst_trace, gst_warning};
+use gst::{gst_debug, gst_error, gst_info, gst_log, gst_trace, gst_warning};
 use once_cell::sync::Lazy;
-use opentok::audio_device::{AudioDevice, AudioSampleData};
-use opentok::log::{self, LogLevel};
-use opentok::publisher::{Publisher, PublisherCallbacks};
-use opentok::session::{Session, SessionCallbacks};
-use opentok::video_capturer::{VideoCapturer, VideoCapturerCallbacks, VideoCapturerSettings};
-use opentok::video_frame::VideoFrame;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use url::Url;
+use std::thread;
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     gst::DebugCategory::new(
@@ -38,14 +57,47 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
 });
 
 /// Stream type enumeration.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum StreamType {
     Audio,
     Video,
     Unknown__,
 }
 
-/// Element sink pad name to StreamType conversion.
+/// Copies the planes of `frame` one after the other, without any padding,
+/// which is the layout of the frames given to the SDK.
+fn pack_video_frame(
+    frame: &gst_video::VideoFrameRef<&gst::BufferRef>,
+) -> Result<Vec<u8>, glib::BoolError> {
+    let format_info = frame.format_info();
+    let mut data = Vec::new();
+    for plane in 0..frame.n_planes() {
+        // The components stored in the plane give its size, the chroma
+        // planes being subsampled.
+        let (row_size, height) = (0..format_info.n_components())
+            .filter(|component| format_info.plane()[*component as usize] == plane)
+            .map(|component| {
+                let width = frame.comp_width(component) as usize
+                    * format_info.pixel_stride()[component as usize] as usize;
+                (width, frame.comp_height(component) as usize)
+            })
+            .fold((0, 0), |(row_size, height), (width, comp_height)| {
+                (row_size.max(width), height.max(comp_height))
+            });
+        let stride = frame.plane_stride()[plane as usize] as usize;
+        let plane_data = frame.plane_data(plane)?;
+        for row in 0..height {
+            let offset = row * stride;
+            let row_data = plane_data
+                .get(offset..offset + row_size)
+                .ok_or_else(|| glib::bool_error!("Plane {} is too small", plane))?;
+            data.extend_from_slice(row_data);
+        }
+    }
+    Ok(data)
+}
+
+/// Element sink pad name prefix to StreamType conversion.
 impl From<&str> for StreamType {
     fn from(stream_type: &str) -> StreamType {
         match stream_type {
@@ -66,18 +118,92 @@ impl From<StreamType> for &'static str {
     }
 }
 
+/// Splits a sink pad name into its stream type and publisher group. The
+/// `video_sink` and `audio_sink` pads belong to the first group.
+fn parse_pad_name(name: &str) -> Option<(StreamType, u32)> {
+    let (prefix, group) = match name.rsplit_once('_') {
+        Some((prefix, group)) if prefix.ends_with("_sink") => (prefix, group.parse().ok()?),
+        _ => (name, 0),
+    };
+    match StreamType::from(prefix) {
+        StreamType::Unknown__ => None,
+        stream_type => Some((stream_type, group)),
+    }
+}
+
 struct SignalEmitter {
     element: glib::object::WeakRef<gst::Element>,
 }
 
 impl SignalEmitter {
-    pub fn emit_published_stream(&self, stream_id: &str, url: &str) {
+    pub fn emit_published_stream(&self, stream_id: &str, url: &str, group: u32) {
         if let Some(element) = self.element.upgrade() {
-            element.emit_by_name::<()>("published-stream", &[&stream_id, &url]);
+            element.emit_by_name::<()>("published-stream", &[&stream_id, &url, &group]);
+        }
+    }
+}
+
+/// A publisher, fed by the `video_sink_N` and `audio_sink_N` pads of its
+/// group.
+#[derive(Default)]
+struct PublisherGroup {
+    /// OpenTok Publisher instance, shared with the video sink.
+    publisher: Arc<Mutex<Option<Arc<dyn PublisherBackend>>>>,
+    /// Audio sink, if any.
+    audio_sink: Option<gst::Element>,
+    /// Video sink, if any.
+    video_sink: Option<gst::Element>,
+    video_caps: Option<gst::Caps>,
+    /// Settings of the video capturer of the publisher, if any.
+    capturer: Option<CapturerSettings>,
+    /// Published stream, as last toggled.
+    published_stream: Option<StreamInfo>,
+    /// Whether the `muted` property of the audio and video pads is set.
+    audio_muted: bool,
+    video_muted: bool,
+    /// Settings of the publisher: the ones of the element, but for the
+    /// `overrides` set on the pads of the group.
+    settings: PublisherSettings,
+    overrides: HashSet<String>,
+}
+
+impl PublisherGroup {
+    fn publisher(&self) -> Option<Arc<dyn PublisherBackend>> {
+        self.publisher.lock().unwrap().clone()
+    }
+
+    fn sink(&self, stream_type: StreamType) -> &Option<gst::Element> {
+        match stream_type {
+            StreamType::Audio => &self.audio_sink,
+            _ => &self.video_sink,
+        }
+    }
+
+    fn sink_mut(&mut self, stream_type: StreamType) -> &mut Option<gst::Element> {
+        match stream_type {
+            StreamType::Audio => &mut self.audio_sink,
+            _ => &mut self.video_sink,
+        }
+    }
+
+    fn muted(&self, stream_type: StreamType) -> bool {
+        match stream_type {
+            StreamType::Audio => self.audio_muted,
+            _ => self.video_muted,
+        }
+    }
+
+    fn muted_mut(&mut self, stream_type: StreamType) -> &mut bool {
+        match stream_type {
+            StreamType::Audio => &mut self.audio_muted,
+            _ => &mut self.video_muted,
         }
     }
 }
 
+/// Publisher groups, by index.
+type PublisherGroups = BTreeMap<u32, PublisherGroup>;
+
 #[derive(Default)]
 pub struct OpenTokSink {
     /// OpenTok session credentials (API key, session ID and token).
@@ -86,50 +212,54 @@ pub struct OpenTokSink {
     /// Every Vonage Video API video chat occurs within a session.
     /// You can think of a session as a “room” where clients can interact
     /// with one another in real-time.
-    session: Arc<Mutex<Option<Session>>>,
-    /// Boolean flag indicating whether we are connected to a session or not.
-    /// No audio or video can be published until the session is connected.
-    session_connected: Arc<AtomicBool>,
-    /// OpenTok Publisher instance.
-    publisher: Arc<Mutex<Option<Publisher>>>,
-    /// Audio sink, if any.
-    audio_sink: Arc<Mutex<Option<gst::Element>>>,
-    /// Video sink, if any.
-    video_sink: Arc<Mutex<Option<gst::Element>>>,
-    /// Video capturer.
-    video_capturer: Arc<Mutex<Option<VideoCapturer>>>,
-    /// Published stream unique identifier.
-    published_stream_id: Arc<Mutex<Option<String>>>,
+    session: Arc<Mutex<Option<Arc<SessionHandle>>>>,
+    /// Session shared with the other elements of the pipeline, as given by
+    /// the `opentok.session` context.
+    shared_session: Mutex<Option<Arc<SharedSession>>>,
+    /// Whether we are connected to a session or not. No audio or video can
+    /// be published until the session is connected, and buffers are dropped
+    /// while it reconnects.
+    session_state: Arc<Mutex<SessionStateMachine>>,
+    reconnect_policy: Mutex<ReconnectPolicy>,
+    /// Publishers, one per group of sink pads.
+    groups: Arc<Mutex<PublisherGroups>>,
+    /// Publisher settings, as set on the element.
+    publisher_settings: Mutex<PublisherSettings>,
+    /// Format of the audio pushed to the audio device.
+    audio_settings: Mutex<AudioSettings>,
     /// Takes care of signaling when the stream is published.
     signal_emitter: Arc<Mutex<Option<SignalEmitter>>>,
-    video_caps: Arc<Mutex<Option<gst::Caps>>>,
+    /// Fires `request-token` ahead of the expiry of the session token.
+    token_watch: Arc<Mutex<Option<gst::SingleShotClockId>>>,
+    /// Last location set, for the stream name and the audio or video only
+    /// flags.
+    uri: Arc<Mutex<OpenTokUri>>,
+    /// Tracks the asynchronous READY→PAUSED transition.
+    startup: Arc<Mutex<Startup>>,
 }
 
 impl OpenTokSink {
     fn location(&self) -> Option<String> {
+        let base = self.uri.lock().unwrap().clone();
         self.credentials
             .lock()
             .unwrap()
-            .session_id()
-            .map(|id| format!("opentok://{}", id))
+            .to_uri(&base)
+            .map(|uri| uri.to_uri())
     }
 
     fn set_location(&self, location: &str) -> Result<(), glib::BoolError> {
-        gst_debug!(CAT, "Setting location to {}", location);
-        let url = match Url::parse(location) {
-            Ok(url) => url,
-            Err(err) => {
-                return Err(glib::BoolError::new(
-                    format!("Malformed url {:?}", err),
-                    file!(),
-                    "set_location",
-                    line!(),
-                ))
-            }
-        };
-        let credentials: Credentials = url.into();
+        gst_debug!(CAT, "Setting location to {}", redact_location(location));
+        let uri = location.parse::<OpenTokUri>().map_err(|err| {
+            glib::BoolError::new(err.to_string(), file!(), "set_location", line!())
+        })?;
+        let mut credentials = Credentials::from(&uri);
+        let mut current = self.credentials.lock().unwrap();
+        credentials.set_room_info(current.room_info().clone());
         gst_debug!(CAT, "Credentials {:?}", credentials);
-        *self.credentials.lock().unwrap() = credentials;
+        *current = credentials;
+        drop(current);
+        *self.uri.lock().unwrap() = uri;
         Ok(())
     }
 
@@ -149,41 +279,99 @@ impl OpenTokSink {
             return Ok(());
         }
 
-        let session_connected = &self.session_connected;
-        let publisher = &self.publisher;
-        let session_callbacks = SessionCallbacks::builder()
-            .on_connected(clone!(
-                @weak session_connected,
-                @strong publisher,
-            => move |session| {
+        let session_state = &self.session_state;
+        let groups = &self.groups;
+        let session = &self.session;
+        let credentials = &self.credentials;
+        let handler: SessionEventHandler = Arc::new(clone!(
+            @weak session_state,
+            @weak groups,
+            @weak session,
+            @weak credentials,
+            @weak element
+        => move |event| match event {
+            SessionEvent::Connected => {
                 gst_debug!(CAT, "Session connected");
-                session_connected.store(true, Ordering::Relaxed);
-                if let Some(ref publisher) = *publisher.lock().unwrap() {
-                    gst_debug!(CAT, "Publishing on session");
-                    if let Err(err) = session.publish(publisher) {
-                        gst_error!(CAT, "Session publish error {}", err);
-                    }
-                }
-            }))
-            .on_error(clone!(
-                @weak element
-            => move |_, error, _| {
-                gst::element_error!(&element, gst::ResourceError::Read, [error]);
-            }))
-            .build();
-        match Session::new(api_key, session_id, session_callbacks) {
-            Ok(session) => {
-                let result = session
-                    .connect(token)
-                    .map_err(|e| Error::Init(format!("Connection error {:?}", e)));
-                *self.session.lock().unwrap() = Some(session);
-                result
-            }
-            Err(err) => Err(Error::Init(format!(
-                "Failed to create OpenTok session `{:?}",
-                err
-            ))),
+                update_session_state(&element, &session_state, SessionInput::Connected);
+                OpenTokSink::publish_pending(&element, &groups, &session);
+                OpenTokSink::connected(&element);
+            }
+            SessionEvent::AuthenticationFailed(error) => {
+                gst_warning!(CAT, obj: &element, "Session authentication failed: {}", error);
+                // Drop the session, setting a new token connects again.
+                update_session_state(&element, &session_state, SessionInput::Disconnected);
+                let session = session.lock().unwrap().take();
+                drop(session);
+                OpenTokSink::from_element(&element).startup.lock().unwrap().disconnected();
+                let token = credentials.lock().unwrap().token().cloned();
+                request_token(
+                    &element,
+                    TokenRequestReason::AuthenticationFailed,
+                    token.as_ref().map(|token| token.expose()),
+                );
+            }
+            SessionEvent::Reconnecting { attempt, max_attempts } => {
+                gst_warning!(CAT, obj: &element, "Reconnecting, attempt {} of {}", attempt, max_attempts);
+                update_session_state(&element, &session_state, SessionInput::Reconnecting);
+            }
+            SessionEvent::Reconnected => {
+                gst_info!(CAT, obj: &element, "Reconnected");
+                update_session_state(&element, &session_state, SessionInput::Reconnected);
+                // A new connection needs the streams to be published again.
+                OpenTokSink::publish_pending(&element, &groups, &session);
+            }
+            SessionEvent::Disconnected => {
+                update_session_state(&element, &session_state, SessionInput::Disconnected);
+            }
+            // Errors not affecting the connection, such as refused signals.
+            SessionEvent::Error(error) if session_state.lock().unwrap().is_connected() => {
+                gst::element_warning!(element, gst::ResourceError::Write, ["{}", error]);
+            }
+            SessionEvent::Error(error) => {
+                OpenTokSink::connection_failed(&element, &error);
+            }
+            SessionEvent::SignalReceived(signal) => signal_received(&element, &signal),
+            _ => {}
+        }));
+        let handler = with_session_messages(element, handler);
+
+        let shared = obtain_session(element, &self.shared_session, api_key, session_id)?;
+        let reconnect_policy = *self.reconnect_policy.lock().unwrap();
+        let session = shared.handle(handler, false, reconnect_policy);
+        *self.session.lock().unwrap() = Some(session.clone());
+        update_session_state(element, &self.session_state, SessionInput::Connect);
+        if let Err(err) = session.connect(token) {
+            self.session.lock().unwrap().take();
+            if token_is_expired(token) {
+                request_token(
+                    element,
+                    TokenRequestReason::AuthenticationFailed,
+                    Some(token),
+                );
+            }
+            return Err(err);
+        }
+
+        if let Some(id) = self.token_watch.lock().unwrap().take() {
+            id.unschedule();
+        }
+        *self.token_watch.lock().unwrap() = watch_token_expiry(element, token);
+        Ok(())
+    }
+
+    /// Makes a connected session use `token` from now on, and watches its
+    /// expiry instead of the previous token's.
+    fn update_token(&self, element: &gst::Element, token: &str) {
+        let session = match *self.session.lock().unwrap() {
+            Some(ref session) => session.clone(),
+            None => return,
+        };
+        gst_debug!(CAT, obj: element, "Updating the session token");
+        session.update_token(token);
+        if let Some(id) = self.token_watch.lock().unwrap().take() {
+            id.unschedule();
         }
+        *self.token_watch.lock().unwrap() = watch_token_expiry(element, token);
     }
 
     fn maybe_init_session(&self, element: &gst::Element) -> Result<(), Error> {
@@ -192,7 +380,7 @@ impl OpenTokSink {
         if let Some(ref api_key) = credentials.api_key() {
             if let Some(ref session_id) = credentials.session_id() {
                 if let Some(ref token) = credentials.token() {
-                    return self.init_session(element, api_key, session_id, token);
+                    return self.init_session(element, api_key, session_id, token.expose());
                 }
             }
         }
@@ -200,27 +388,163 @@ impl OpenTokSink {
         Ok(())
     }
 
-    fn teardown(&self) {
+    /// Publishes the streams of the groups not published yet.
+    fn publish_pending(
+        element: &gst::Element,
+        groups: &Mutex<PublisherGroups>,
+        session: &Mutex<Option<Arc<SessionHandle>>>,
+    ) {
+        let publishers = groups
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|group| group.published_stream.is_none())
+            .filter_map(|group| group.publisher())
+            .collect::<Vec<_>>();
+        if let Some(ref session) = *session.lock().unwrap() {
+            for publisher in publishers {
+                gst_debug!(CAT, obj: element, "Publishing on session");
+                if let Err(err) = session.publish(&publisher) {
+                    gst_error!(CAT, obj: element, "Session publish error {}", err);
+                }
+            }
+        }
+    }
+
+    fn from_element(element: &gst::Element) -> &Self {
+        OpenTokSink::from_instance(element.downcast_ref::<super::OpenTokSink>().unwrap())
+    }
+
+    /// Loads the credentials in the background, then connects the session.
+    fn start(&self, element: &super::OpenTokSink) {
+        let mut credentials = self.credentials.lock().unwrap().clone();
+        let epoch = {
+            let mut startup = self.startup.lock().unwrap();
+            startup.arm(element.upcast_ref(), |element| {
+                OpenTokSink::connection_failed(element, "Timed out connecting the session");
+            });
+            startup.epoch()
+        };
+        update_session_state(
+            element.upcast_ref(),
+            &self.session_state,
+            SessionInput::Start,
+        );
+
+        let element_weak = element.downgrade();
+        thread::spawn(move || {
+            let result = async_std::task::block_on(credentials.load());
+            let element = match element_weak.upgrade() {
+                Some(element) => element,
+                None => return,
+            };
+            let sink = OpenTokSink::from_instance(&element);
+            if sink.startup.lock().unwrap().epoch() != epoch {
+                gst_debug!(CAT, obj: &element, "Stopped while loading the credentials");
+                return;
+            }
+            if let Err(err) = result {
+                OpenTokSink::connection_failed(
+                    element.upcast_ref(),
+                    &format!("Cannot load credentials: {}", err),
+                );
+                return;
+            }
+
+            *sink.credentials.lock().unwrap() = credentials;
+            if let Err(err) = sink.maybe_init_session(element.upcast_ref()) {
+                OpenTokSink::connection_failed(element.upcast_ref(), &err.to_string());
+            }
+        });
+    }
+
+    /// Completes a pending READY→PAUSED transition.
+    fn connected(element: &gst::Element) {
+        let sink = OpenTokSink::from_element(element);
+        if sink.startup.lock().unwrap().connected() {
+            sink.async_done(element.downcast_ref().unwrap());
+        }
+    }
+
+    /// Posts an error message, and completes a pending READY→PAUSED
+    /// transition so that the application gets a chance to handle it.
+    fn connection_failed(element: &gst::Element, error: &str) {
+        gst::element_error!(element, gst::ResourceError::OpenWrite, ["{}", error]);
+        let sink = OpenTokSink::from_element(element);
+        update_session_state(element, &sink.session_state, SessionInput::Failed);
+        if sink.startup.lock().unwrap().failed() {
+            sink.async_done(element.downcast_ref().unwrap());
+        }
+    }
+
+    fn async_start(&self, element: &super::OpenTokSink) {
+        self.parent_handle_message(
+            element,
+            gst::message::AsyncStart::builder().src(element).build(),
+        );
+    }
+
+    fn async_done(&self, element: &super::OpenTokSink) {
+        self.parent_handle_message(
+            element,
+            gst::message::AsyncDone::builder(gst::ClockTime::NONE)
+                .src(element)
+                .build(),
+        );
+    }
+
+    fn teardown(&self, element: &super::OpenTokSink) {
         gst_debug!(CAT, "Teardown");
-        if let Some(publisher) = self.publisher.lock().unwrap().as_ref() {
+        let (publishers, stream_ids) = {
+            let mut groups = self.groups.lock().unwrap();
+            let publishers = groups
+                .values()
+                .filter_map(|group| group.publisher.lock().unwrap().take())
+                .collect::<Vec<_>>();
+            let stream_ids = groups
+                .values_mut()
+                .filter_map(|group| group.published_stream.take().map(|stream| stream.id))
+                .collect::<Vec<_>>();
+            (publishers, stream_ids)
+        };
+        for publisher in publishers {
             gst_debug!(CAT, "Unpublishing");
             if let Err(e) = publisher.unpublish() {
                 gst_error!(CAT, "Unpublish error {}", e);
             }
         }
-        if self.session_connected.load(Ordering::Relaxed) {
-            if let Some(session) = self.session.lock().unwrap().as_ref() {
+        // Leave the session, so that it disconnects once no other element
+        // uses it, and a new handle is created if the element goes back to
+        // READY.
+        let session = self.session.lock().unwrap().take();
+        if let Some(ref session) = session {
+            for stream_id in stream_ids {
+                session.shared().stream_unpublished(&stream_id);
+            }
+        }
+        let connected = self.session_state.lock().unwrap().is_connected();
+        if connected {
+            if let Some(session) = session {
                 gst_debug!(CAT, "Disconnecting");
                 if let Err(e) = session.disconnect() {
                     gst_error!(CAT, "Session disconnect error {}", e);
                 }
             }
         }
+        update_session_state(
+            element.upcast_ref(),
+            &self.session_state,
+            SessionInput::Stop,
+        );
+        if let Some(id) = self.token_watch.lock().unwrap().take() {
+            id.unschedule();
+        }
+        self.startup.lock().unwrap().reset();
     }
 
     fn sink_event(
         &self,
-        pad: &gst::GhostPad,
+        pad: &super::OpenTokSinkPad,
         element: &super::OpenTokSink,
         event: gst::Event,
     ) -> bool {
@@ -234,7 +558,9 @@ impl OpenTokSink {
                 // needed.
                 let s = caps.structure(0).unwrap();
                 if s.name().starts_with("video") {
-                    *self.video_caps.lock().unwrap() = Some(caps);
+                    if let Some((_, index)) = parse_pad_name(&pad.name()) {
+                        self.set_video_caps(element, index, caps);
+                    }
                 }
                 pad.event_default(Some(element), event)
             }
@@ -242,26 +568,81 @@ impl OpenTokSink {
         }
     }
 
-    fn setup_video_sink(sink: &gst::Element, video_capturer: &VideoCapturer) {
+    fn sink_query(
+        &self,
+        pad: &super::OpenTokSinkPad,
+        element: &super::OpenTokSink,
+        query: &mut gst::QueryRef,
+    ) -> bool {
+        gst_log!(CAT, obj: pad, "Handling query {:?}", query);
+        let res = pad.query_default(Some(element), query);
+        let video = parse_pad_name(&pad.name()).map(|(stream_type, _)| stream_type)
+            == Some(StreamType::Video);
+        match query.view_mut() {
+            // Video frames are mapped according to their video meta, so
+            // upstream can use padded strides and plane offsets.
+            gst::QueryView::Allocation(mut q) if video => {
+                q.add_allocation_meta::<gst_video::VideoMeta>(None);
+                res
+            }
+            _ => res,
+        }
+    }
+
+    fn setup_video_sink(
+        sink: &gst::Element,
+        publisher: &Arc<Mutex<Option<Arc<dyn PublisherBackend>>>>,
+        session_state: &Arc<Mutex<SessionStateMachine>>,
+    ) {
         gst_debug!(CAT, "Setting up video sink");
 
-        let video_capturer_ = video_capturer.clone();
+        let publisher = publisher.clone();
+        let session_state = session_state.clone();
         let on_new_sample =
             move |appsink: &gst_app::AppSink| -> Result<gst::FlowSuccess, gst::FlowError> {
                 let sample = appsink.pull_sample().unwrap();
-                let buffer = sample.buffer_owned().unwrap();
+                let buffer = sample.buffer().unwrap();
                 let caps = sample.caps().unwrap();
                 let info = gst_video::VideoInfo::from_caps(caps).unwrap();
-                let map = buffer.into_mapped_buffer_readable().unwrap();
-                let frame = VideoFrame::new(
-                    otc_format_from_gst_format(info.format()),
-                    info.width() as i32,
-                    info.height() as i32,
-                    map.to_vec(),
-                );
-                gst_trace!(CAT, "Providing frame through video capturer");
-                if let Err(error) = video_capturer_.provide_frame(0, &frame) {
-                    gst_error!(CAT, "Cannot provide frame to video capturer: {}", error,);
+                if session_state.lock().unwrap().state() == SessionState::Reconnecting {
+                    gst_trace!(CAT, "Reconnecting, dropping frame");
+                    return Ok(gst::FlowSuccess::Ok);
+                }
+                // Frames arriving before the publisher is created are dropped.
+                if let Some(ref publisher) = *publisher.lock().unwrap() {
+                    // The video meta of the buffer, if any, gives the actual
+                    // layout of its planes.
+                    let video_frame =
+                        match gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info) {
+                            Ok(video_frame) => video_frame,
+                            Err(err) => {
+                                gst_error!(CAT, "Cannot map video frame: {}", err);
+                                return Ok(gst::FlowSuccess::Ok);
+                            }
+                        };
+                    let data = match pack_video_frame(&video_frame) {
+                        Ok(data) => data,
+                        Err(err) => {
+                            gst_error!(CAT, "Cannot read video frame: {}", err);
+                            return Ok(gst::FlowSuccess::Ok);
+                        }
+                    };
+                    // Receivers pace the frames with their running time.
+                    let timestamp = sample
+                        .segment()
+                        .and_then(|segment| segment.downcast_ref::<gst::ClockTime>())
+                        .and_then(|segment| segment.to_running_time(buffer.pts()));
+                    let frame = VideoFrameData {
+                        format: info.format(),
+                        width: info.width(),
+                        height: info.height(),
+                        data,
+                        timestamp,
+                    };
+                    gst_trace!(CAT, "Providing frame to publisher");
+                    if let Err(error) = publisher.provide_video_frame(&frame) {
+                        gst_error!(CAT, "Cannot provide frame to publisher: {}", error,);
+                    }
                 }
                 Ok(gst::FlowSuccess::Ok)
             };
@@ -271,21 +652,22 @@ impl OpenTokSink {
                 .new_sample(on_new_sample)
                 .build(),
         );
-        sink.sync_state_with_parent().unwrap();
     }
 
-    fn setup_audio_sink(sink: &gst::Element) {
-        let audio_device = AudioDevice::get_instance();
+    fn setup_audio_sink(sink: &gst::Element, session_state: &Arc<Mutex<SessionStateMachine>>) {
+        let backend = backend();
+        let session_state = session_state.clone();
         let on_new_sample =
             move |appsink: &gst_app::AppSink| -> Result<gst::FlowSuccess, gst::FlowError> {
                 let sample = appsink.pull_sample().unwrap();
+                if session_state.lock().unwrap().state() == SessionState::Reconnecting {
+                    gst_trace!(CAT, "Reconnecting, dropping audio samples");
+                    return Ok(gst::FlowSuccess::Ok);
+                }
                 let buffer = sample.buffer_owned().unwrap();
                 let map = buffer.into_mapped_buffer_readable().unwrap();
                 gst_trace!(CAT, "Providing audio sample");
-                audio_device
-                    .lock()
-                    .unwrap()
-                    .push_audio_sample(AudioSampleData(map.as_slice_of::<i16>().unwrap().to_vec()));
+                backend.push_audio_samples(map.as_slice_of::<i16>().unwrap());
                 Ok(gst::FlowSuccess::Ok)
             };
         let sink = sink.downcast_ref::<gst_app::AppSink>().unwrap();
@@ -294,86 +676,271 @@ impl OpenTokSink {
                 .new_sample(on_new_sample)
                 .build(),
         );
-        sink.sync_state_with_parent().unwrap();
     }
 
-    fn ensure_publisher(&self, element: &super::OpenTokSink) {
-        if self.publisher.lock().unwrap().is_some() {
+    /// Sets one of the publisher properties, on the element when `index` is
+    /// `None`, or on the pads of the publisher `index`. The settings that can
+    /// change while publishing are applied right away.
+    fn set_group_property(
+        &self,
+        element: &super::OpenTokSink,
+        index: Option<u32>,
+        name: &str,
+        value: &glib::Value,
+    ) {
+        // Refuse the settings the backend cannot apply, keeping the current
+        // ones.
+        let mut settings = match index.and_then(|index| {
+            self.groups
+                .lock()
+                .unwrap()
+                .get(&index)
+                .map(|group| group.settings.clone())
+        }) {
+            Some(settings) => settings,
+            None => self.publisher_settings.lock().unwrap().clone(),
+        };
+        set_publisher_property(&mut settings, name, value);
+        if let Err(err) = backend().check_publisher_settings(&settings) {
+            gst_error!(CAT, obj: element, "Cannot set {}: {}", name, err);
             return;
         }
 
-        gst_debug!(CAT, obj: element, "Initializing publisher");
+        if index.is_none() {
+            set_publisher_property(&mut self.publisher_settings.lock().unwrap(), name, value);
+        }
+        let updated = self
+            .groups
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|(group_index, group)| match index {
+                Some(index) => **group_index == index,
+                None => !group.overrides.contains(name),
+            })
+            .map(|(group_index, group)| {
+                if index.is_some() {
+                    group.overrides.insert(name.to_string());
+                }
+                set_publisher_property(&mut group.settings, name, value);
+                (*group_index, group.publisher(), group.settings.clone())
+            })
+            .collect::<Vec<_>>();
+
+        for (index, publisher, settings) in updated {
+            // The properties of the pads follow the ones of the element.
+            for pad in element.sink_pads() {
+                if parse_pad_name(&pad.name()).map(|(_, pad_index)| pad_index) == Some(index) {
+                    pad.notify(name);
+                }
+            }
 
-        // Even if we are only publishing audio for now, we need to add a video
-        // capturer, in case a video pad is requested at some point.
-        let credentials = &self.credentials;
-        let published_stream_id = &self.published_stream_id;
-        let video_sink = &self.video_sink;
-        let video_capturer = &self.video_capturer;
-        let video_capturer_callbacks = VideoCapturerCallbacks::builder()
-            .start(clone!(
-                @weak video_capturer,
-                @weak video_sink,
-                @weak element,
-            => @default-return Ok(()), move |capturer| {
-                gst_debug!(CAT, obj: &element, "Video capturer ready");
-                if let Some(ref video_sink) = *video_sink.lock().unwrap() {
-                    OpenTokSink::setup_video_sink(
-                        video_sink,
-                        capturer,
-                    );
+            if name == "publish-audio" || name == "publish-video" {
+                self.toggle_media(element, index);
+            } else if RUNTIME_PROPERTIES.contains(&name) {
+                if let Some(publisher) = publisher {
+                    gst_debug!(CAT, obj: element, "Updating {} of publisher {}", name, index);
+                    if let Err(err) = publisher.update_settings(&settings) {
+                        gst_warning!(CAT, obj: element, "Cannot update publisher {}: {}", index, err);
+                    }
                 }
-                *video_capturer.lock().unwrap() = Some(capturer.clone());
-                Ok(())
-            }))
-            .build();
+            }
+        }
+    }
+
+    /// Gets one of the publisher properties of the publisher `index`.
+    fn group_property(&self, index: u32, name: &str) -> glib::Value {
+        match self.groups.lock().unwrap().get(&index) {
+            Some(group) => publisher_property(&group.settings, name),
+            None => publisher_property(&self.publisher_settings.lock().unwrap(), name),
+        }
+    }
+
+    /// Creates the publishers of the groups that have none yet.
+    fn ensure_publishers(&self, element: &super::OpenTokSink) {
+        let pending = self
+            .groups
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, group)| group.publisher().is_none())
+            .map(|(index, group)| {
+                let name_overridden = group.overrides.contains("publisher-name");
+                (
+                    *index,
+                    group.video_caps.clone(),
+                    group.settings.clone(),
+                    name_overridden,
+                )
+            })
+            .collect::<Vec<_>>();
+        for (index, video_caps, settings, name_overridden) in pending {
+            let name = settings
+                .name
+                .clone()
+                .or_else(|| self.uri.lock().unwrap().stream_name.clone())
+                .unwrap_or_else(|| "opentoksink".into());
+            // Names set on the pads are used as is.
+            let name = match index {
+                0 => name,
+                _ if name_overridden => name,
+                index => format!("{}-{}", name, index),
+            };
+            self.create_publisher(element, index, &name, &settings, video_caps.as_ref());
+        }
+    }
 
-        let mut settings = VideoCapturerSettings::default();
+    /// Settings of a video capturer fed with `video_caps`, but for the
+    /// preferred ones of `settings`.
+    fn capturer_settings(
+        element: &super::OpenTokSink,
+        video_caps: Option<&gst::Caps>,
+        settings: &PublisherSettings,
+    ) -> CapturerSettings {
+        let mut capturer = CapturerSettings::default();
 
-        if let Some(ref video_caps) = *self.video_caps.lock().unwrap() {
+        if let Some(video_caps) = video_caps {
             match gst_video::VideoInfo::from_caps(video_caps) {
                 Ok(info) => {
-                    settings.width = info.width() as i32;
-                    settings.height = info.height() as i32;
-                    let fps = info.fps();
-                    settings.fps = (fps.numer() / fps.denom()) as i32;
-                    settings.format = otc_format_from_gst_format(info.format());
+                    capturer.width = info.width();
+                    capturer.height = info.height();
+                    // Variable frame rates keep the default one.
+                    if info.fps().numer() > 0 {
+                        capturer.fps = info.fps();
+                    }
+                    capturer.format = info.format();
                 }
                 Err(_) => {
                     gst_warning!(CAT, obj: element, "Invalid video caps, using default capturer settings");
                 }
             };
-        } else {
+        }
+        if settings.preferred_width > 0 {
+            capturer.width = settings.preferred_width;
+        }
+        if settings.preferred_height > 0 {
+            capturer.height = settings.preferred_height;
+        }
+        if settings.preferred_framerate.numer() > 0 {
+            capturer.fps = settings.preferred_framerate;
+        }
+        capturer
+    }
+
+    /// Applies new video caps to the group `index`. The capturer of its
+    /// publisher is reconfigured, keeping the stream published, unless the
+    /// backend cannot, in which case a new publisher replaces it.
+    fn set_video_caps(&self, element: &super::OpenTokSink, index: u32, caps: gst::Caps) {
+        let (publisher, capturer) = {
+            let mut groups = self.groups.lock().unwrap();
+            let group = match groups.get_mut(&index) {
+                Some(group) => group,
+                None => return,
+            };
+            let capturer = OpenTokSink::capturer_settings(element, Some(&caps), &group.settings);
+            group.video_caps = Some(caps);
+            // Publishers created later on use the new caps.
+            match group.publisher() {
+                Some(publisher) if group.capturer.as_ref() != Some(&capturer) => {
+                    (publisher, capturer)
+                }
+                _ => return,
+            }
+        };
+
+        gst_info!(CAT, obj: element, "Reconfiguring video capturer of publisher {}: {:?}", index, capturer);
+        match publisher.reconfigure_capturer(&capturer) {
+            Ok(()) => {
+                if let Some(group) = self.groups.lock().unwrap().get_mut(&index) {
+                    group.capturer = Some(capturer);
+                }
+            }
+            Err(err) => {
+                gst_warning!(CAT, obj: element, "Cannot reconfigure publisher {}, publishing it again: {}", index, err);
+                let group_publisher = match self.groups.lock().unwrap().get_mut(&index) {
+                    Some(group) => {
+                        group.capturer = None;
+                        group.publisher.clone()
+                    }
+                    None => return,
+                };
+                group_publisher.lock().unwrap().take();
+                if let Err(err) = publisher.unpublish() {
+                    gst_error!(CAT, obj: element, "Failed to unpublish publisher {}: {}", index, err);
+                }
+                self.ensure_publishers(element);
+            }
+        }
+    }
+
+    fn create_publisher(
+        &self,
+        element: &super::OpenTokSink,
+        index: u32,
+        name: &str,
+        settings: &PublisherSettings,
+        video_caps: Option<&gst::Caps>,
+    ) {
+        gst_debug!(CAT, obj: element, "Initializing publisher {}", index);
+
+        // Even if we are only publishing audio for now, the publisher gets a
+        // video capturer, in case a video pad is requested at some point.
+        if video_caps.is_none() {
             // Ideally we should be able to create the publisher without
             // capturer, but this is not yet supported in opentok-rs.
             gst_debug!(CAT, obj: element, "No video pad, using default capturer settings");
         }
+        let capturer = OpenTokSink::capturer_settings(element, video_caps, settings);
 
-        let video_capturer = VideoCapturer::new(settings, video_capturer_callbacks);
-        gst_debug!(CAT, obj: element, "Video capturer created");
-
+        let credentials = &self.credentials;
+        let groups = &self.groups;
         let signal_emitter = &self.signal_emitter;
-        let publisher_callbacks = PublisherCallbacks::builder()
-            .on_stream_created(clone!(
-                @weak element,
-                @weak credentials,
-                @weak published_stream_id,
-                @weak signal_emitter,
-            => move |_, stream| {
-                *published_stream_id.lock().unwrap() = Some(stream.id());
-                let credentials = credentials.lock().unwrap().clone();
-                let url = format!("opentok://{}/{}?key={}&token={}",
-                                  credentials.session_id().unwrap(),
-                                  stream.id(),
-                                  credentials.api_key().unwrap(),
-                                  credentials.token().unwrap()
-                );
-                signal_emitter.lock().unwrap().as_ref().unwrap().emit_published_stream(&stream.id(), &url);
-                gst_info!(CAT, obj: &element, "Publisher stream created {}. Url {}", stream.id(), url);
-            }))
-            .on_error(clone!(
-                @weak element,
-            => move |_, error, _| {
+        let session = &self.session;
+        let session_state = &self.session_state;
+        let handler: PublisherEventHandler = Arc::new(clone!(
+            @weak element,
+            @weak credentials,
+            @weak groups,
+            @weak signal_emitter,
+            @weak session,
+            @weak session_state,
+        => move |event| match event {
+            PublisherEvent::StreamCreated(stream) => {
+                // Keep the sources sharing the session from subscribing to
+                // our own stream.
+                if let Some(ref session) = *session.lock().unwrap() {
+                    session.shared().stream_published(&stream);
+                }
+                if let Some(group) = groups.lock().unwrap().get_mut(&index) {
+                    group.published_stream = Some(stream.clone());
+                }
+                let base = OpenTokUri {
+                    stream_id: Some(stream.id.clone()),
+                    ..Default::default()
+                };
+                let uri = credentials.lock().unwrap().to_uri(&base).unwrap();
+                signal_emitter.lock().unwrap().as_ref().unwrap().emit_published_stream(&stream.id, &uri.to_uri(), index);
+                gst_info!(CAT, obj: &element, "Publisher {} stream created {}. Url {}", index, stream.id, uri);
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Published);
+            }
+            PublisherEvent::StreamDestroyed(stream) => {
+                if let Some(ref session) = *session.lock().unwrap() {
+                    session.shared().stream_unpublished(&stream.id);
+                }
+                let idle = {
+                    let mut groups = groups.lock().unwrap();
+                    if let Some(group) = groups.get_mut(&index) {
+                        if group.published_stream.as_ref().map(|s| &s.id) == Some(&stream.id) {
+                            group.published_stream = None;
+                        }
+                    }
+                    groups.values().all(|group| group.published_stream.is_none())
+                };
+                if idle {
+                    update_session_state(element.upcast_ref(), &session_state, SessionInput::Inactive);
+                }
+            }
+            PublisherEvent::Error(error) => {
                 gst_error!(CAT, obj: &element, "Publisher error {}", error,);
                 element.post_error_message(
                     gst::error_msg!(
@@ -383,125 +950,228 @@ impl OpenTokSink {
                         ]
                     )
                 );
-            }))
-            .build();
-        let publisher = Publisher::new("opentoksink", Some(video_capturer), publisher_callbacks);
+            }
+        }));
+        let handler = with_publisher_messages(element.upcast_ref(), handler);
+
+        let publisher = match backend().create_publisher(name, settings, &capturer, handler) {
+            Ok(publisher) => publisher,
+            Err(err) => {
+                gst_error!(CAT, obj: element, "Failed to create publisher: {}", err);
+                return;
+            }
+        };
+
+        // The pads of the group may have been released in the meantime.
+        let group_publisher = match self.groups.lock().unwrap().get_mut(&index) {
+            Some(group) => {
+                group.capturer = Some(capturer);
+                group.publisher.clone()
+            }
+            None => return,
+        };
+        *group_publisher.lock().unwrap() = Some(publisher.clone());
+        self.toggle_media(element, index);
 
         if let Some(ref session) = *self.session.lock().unwrap() {
-            if self.session_connected.load(Ordering::Relaxed) {
+            if self.session_state.lock().unwrap().is_connected() {
                 if let Err(err) = session.publish(&publisher) {
                     gst_error!(CAT, "Session publish error {}", err);
                 }
             }
         }
 
-        *self.publisher.lock().unwrap() = Some(publisher);
-
-        gst_debug!(CAT, "Publisher created");
+        gst_debug!(CAT, "Publisher {} created", index);
     }
 
-    fn publish_video(&self) -> Result<(), Error> {
-        gst_debug!(CAT, "Publish video");
+    /// Publishes the media the group `index` has a pad for, unless muted,
+    /// and only them. Changes of the published stream are posted as
+    /// `opentok/stream-property-changed` messages.
+    fn toggle_media(&self, element: &super::OpenTokSink, index: u32) {
+        let uri = self.uri.lock().unwrap().clone();
+        let (publisher, audio, video) = match self.groups.lock().unwrap().get(&index) {
+            Some(group) => (
+                group.publisher(),
+                group.audio_sink.is_some()
+                    && !uri.video_only
+                    && group.settings.publish_audio
+                    && !group.audio_muted,
+                group.video_sink.is_some()
+                    && !uri.audio_only
+                    && group.settings.publish_video
+                    && !group.video_muted,
+            ),
+            None => return,
+        };
+        let publisher = match publisher {
+            Some(publisher) => publisher,
+            None => return,
+        };
 
-        if self.audio_sink.lock().unwrap().is_none() {
-            gst_debug!(CAT, "Toggling audio off");
-            if let Some(ref publisher) = *self.publisher.lock().unwrap() {
-                if let Err(err) = publisher.toggle_audio(false) {
-                    gst_warning!(CAT, "Error toggling audio off {}", err);
-                }
-            }
+        gst_debug!(CAT, obj: element, "Publisher {}: audio {}, video {}", index, audio, video);
+        if let Err(err) = publisher.toggle_audio(audio) {
+            gst_warning!(CAT, obj: element, "Error toggling audio {}", err);
+        }
+        if let Err(err) = publisher.toggle_video(video) {
+            gst_warning!(CAT, obj: element, "Error toggling video {}", err);
         }
 
-        match *self.video_sink.lock().unwrap() {
-            Some(ref video_sink) => {
-                if let Some(ref video_capturer) = *self.video_capturer.lock().unwrap() {
-                    OpenTokSink::setup_video_sink(video_sink, video_capturer);
+        let mut changes = vec![];
+        if let Some(group) = self.groups.lock().unwrap().get_mut(&index) {
+            if let Some(ref mut stream) = group.published_stream {
+                if stream.has_audio != audio {
+                    stream.has_audio = audio;
+                    changes.push((stream.clone(), StreamChange::HasAudio(audio)));
                 }
-                gst_debug!(CAT, "Toggling video on");
-                if let Some(ref publisher) = *self.publisher.lock().unwrap() {
-                    if let Err(err) = publisher.toggle_video(true) {
-                        gst_warning!(CAT, "Error toggling video on {}", err);
-                    }
+                if stream.has_video != video {
+                    stream.has_video = video;
+                    changes.push((stream.clone(), StreamChange::HasVideo(video)));
                 }
             }
-            None => {
-                return Err(Error::InvalidState(
-                    "Trying to publish video with no video sink {}",
-                ))
-            }
         }
-
-        gst_debug!(CAT, "Ready to publish video");
-        Ok(())
-    }
-
-    fn publish_audio(&self) -> Result<(), Error> {
-        gst_debug!(CAT, "Publish audio");
-
-        if self.video_sink.lock().unwrap().is_none() {
-            if let Some(ref publisher) = *self.publisher.lock().unwrap() {
-                if let Err(err) = publisher.toggle_video(false) {
-                    gst_warning!(CAT, "Error toggling video off {}", err);
-                }
-            }
+        for (stream, change) in changes {
+            post_session_message(
+                element.upcast_ref(),
+                &SessionEvent::StreamPropertyChanged(stream, change),
+            );
         }
+    }
 
-        match *self.audio_sink.lock().unwrap() {
-            Some(ref sink) => {
-                OpenTokSink::setup_audio_sink(sink);
-                if let Some(ref publisher) = *self.publisher.lock().unwrap() {
-                    if let Err(err) = publisher.toggle_audio(true) {
-                        gst_warning!(CAT, "Error toggling audio on {}", err);
-                    }
-                }
-            }
-            None => {
-                return Err(Error::InvalidState(
-                    "Trying to publish audio with no audio sink {}",
-                ))
-            }
+    /// Sets the `muted` property of the `stream_type` pad of the publisher
+    /// `index`.
+    fn set_muted(
+        &self,
+        element: &super::OpenTokSink,
+        stream_type: StreamType,
+        index: u32,
+        muted: bool,
+    ) {
+        match self.groups.lock().unwrap().get_mut(&index) {
+            Some(group) => *group.muted_mut(stream_type) = muted,
+            None => return,
         }
-
-        Ok(())
+        gst_debug!(CAT, obj: element, "Publisher {}: {:?} muted {}", index, stream_type, muted);
+        self.toggle_media(element, index);
     }
 
-    fn create_video_sink(&self, element: &crate::OpenTokSink) -> Result<gst::Pad, Error> {
-        let bin = element
-            .upcast_ref::<gst::Element>()
-            .clone()
-            .downcast::<gst::Bin>()
-            .unwrap();
+    fn muted(&self, stream_type: StreamType, index: u32) -> bool {
+        self.groups
+            .lock()
+            .unwrap()
+            .get(&index)
+            .map_or(false, |group| group.muted(stream_type))
+    }
 
-        let appsink = gst::ElementFactory::make("appsink", Some("video-sink"))
+    /// Creates a bin named `name`, converting any raw audio to the format of
+    /// the audio device, along with the appsink at its end.
+    fn create_audio_bin(
+        name: &str,
+        settings: &AudioSettings,
+    ) -> Result<(gst::Element, gst::Element), Error> {
+        let bin = gst::Bin::new(Some(name));
+        let audioconvert = gst::ElementFactory::make("audioconvert", None)
+            .map_err(|_| Error::MissingElement("audioconvert"))?;
+        let audioresample = gst::ElementFactory::make("audioresample", None)
+            .map_err(|_| Error::MissingElement("audioresample"))?;
+        let appsink = gst::ElementFactory::make("appsink", Some(&format!("{}-appsink", name)))
             .map_err(|_| Error::MissingElement("appsink"))?;
-        appsink.set_property("enable-last-sample", false);
+        appsink.set_property("caps", &audio_device_caps(settings));
+
+        bin.add_many(&[&audioconvert, &audioresample, &appsink])
+            .map_err(|_| Error::AddElement("audioconvert ! audioresample ! appsink"))?;
+        gst::Element::link_many(&[&audioconvert, &audioresample, &appsink])
+            .map_err(|_| Error::LinkElements("audioconvert ! audioresample ! appsink"))?;
+
+        let target_pad = audioconvert.static_pad("sink").unwrap();
+        let ghost_pad = gst::GhostPad::with_target(Some("sink"), &target_pad)
+            .map_err(|_| Error::PadConstruction("audio sink", target_pad.name().to_string()))?;
+        bin.add_pad(&ghost_pad).unwrap();
+        Ok((bin.upcast(), appsink))
+    }
 
-        bin.add(&appsink)
-            .map_err(|_| Error::AddElement("appsink"))?;
+    /// Applies new audio settings to the audio device and to the audio sink,
+    /// if any.
+    fn set_audio_settings(&self, element: &super::OpenTokSink, settings: AudioSettings) {
+        *self.audio_settings.lock().unwrap() = settings;
+        let sinks = self
+            .groups
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|group| group.audio_sink.clone())
+            .collect::<Vec<_>>();
+        if sinks.is_empty() {
+            return;
+        }
 
-        let target_sink_pad = appsink.static_pad("sink").unwrap();
-        *self.video_sink.lock().unwrap() = Some(appsink);
-        self.publish_video()?;
-        Ok(target_sink_pad)
+        // The SDK captures audio through a single device.
+        gst_debug!(CAT, obj: element, "Publishing audio as {:?}", settings);
+        backend().set_audio_capture_settings(&settings);
+        for sink in sinks {
+            let appsink = sink
+                .downcast_ref::<gst::Bin>()
+                .and_then(|bin| bin.by_name(&format!("{}-appsink", sink.name())));
+            if let Some(appsink) = appsink {
+                appsink.set_property("caps", &audio_device_caps(&settings));
+                let pad = appsink.static_pad("sink").unwrap();
+                pad.push_event(gst::event::Reconfigure::new());
+            }
+        }
     }
 
-    fn create_audio_sink(&self, element: &crate::OpenTokSink) -> Result<gst::Pad, Error> {
+    fn create_sink(
+        &self,
+        element: &crate::OpenTokSink,
+        stream_type: StreamType,
+        index: u32,
+    ) -> Result<gst::Pad, Error> {
         let bin = element
             .upcast_ref::<gst::Element>()
             .clone()
             .downcast::<gst::Bin>()
             .unwrap();
 
-        let appsink = gst::ElementFactory::make("appsink", Some("audio-sink"))
-            .map_err(|_| Error::MissingElement("appsink"))?;
+        let (sink, appsink) = match stream_type {
+            StreamType::Video => {
+                let appsink =
+                    gst::ElementFactory::make("appsink", Some(&format!("video-sink-{}", index)))
+                        .map_err(|_| Error::MissingElement("appsink"))?;
+                (appsink.clone(), appsink)
+            }
+            _ => {
+                let audio_settings = *self.audio_settings.lock().unwrap();
+                backend().set_audio_capture_settings(&audio_settings);
+                OpenTokSink::create_audio_bin(&format!("audio-sink-{}", index), &audio_settings)?
+            }
+        };
         appsink.set_property("enable-last-sample", false);
 
-        bin.add(&appsink)
-            .map_err(|_| Error::AddElement("appsink"))?;
+        bin.add(&sink).map_err(|_| Error::AddElement("sink"))?;
+
+        let target_sink_pad = sink.static_pad("sink").unwrap();
+        let settings = self.publisher_settings.lock().unwrap().clone();
+        let publisher = {
+            let mut groups = self.groups.lock().unwrap();
+            let group = groups.entry(index).or_insert_with(|| PublisherGroup {
+                settings,
+                ..Default::default()
+            });
+            *group.sink_mut(stream_type) = Some(sink.clone());
+            group.publisher.clone()
+        };
+        match stream_type {
+            StreamType::Video => {
+                OpenTokSink::setup_video_sink(&appsink, &publisher, &self.session_state)
+            }
+            _ => OpenTokSink::setup_audio_sink(&appsink, &self.session_state),
+        }
+        sink.sync_state_with_parent().unwrap();
+        self.toggle_media(element, index);
 
-        let target_sink_pad = appsink.static_pad("sink").unwrap();
-        *self.audio_sink.lock().unwrap() = Some(appsink);
-        self.publish_audio()?;
+        // Groups created while playing are published right away.
+        if element.current_state() == gst::State::Playing {
+            self.ensure_publishers(element);
+        }
         Ok(target_sink_pad)
     }
 
@@ -510,25 +1180,29 @@ impl OpenTokSink {
         element: &crate::OpenTokSink,
         template: &gst::PadTemplate,
         stream_type: StreamType,
+        index: u32,
+        pad_name: &str,
     ) -> Result<gst::Pad, Error> {
-        let target_pad = match stream_type {
-            StreamType::Video => self.create_video_sink(element),
-            StreamType::Audio => self.create_audio_sink(element),
-            _ => {
-                unreachable!();
-            }
-        }?;
-
-        let ghost_pad = gst::GhostPad::builder_with_template(template, Some(stream_type.into()))
-            .event_function(|pad, parent, event| {
-                OpenTokSink::catch_panic_pad_function(
-                    parent,
-                    || false,
-                    |sink, element| sink.sink_event(pad, element, event),
-                )
-            })
-            .build_with_target(&target_pad)
-            .map_err(|_| Error::PadConstruction("sink pad", target_pad.name().to_string()))?;
+        let target_pad = self.create_sink(element, stream_type, index)?;
+
+        let ghost_pad =
+            gst::PadBuilder::<super::OpenTokSinkPad>::from_template(template, Some(pad_name))
+                .event_function(|pad, parent, event| {
+                    OpenTokSink::catch_panic_pad_function(
+                        parent,
+                        || false,
+                        |sink, element| sink.sink_event(pad, element, event),
+                    )
+                })
+                .query_function(|pad, parent, query| {
+                    OpenTokSink::catch_panic_pad_function(
+                        parent,
+                        || false,
+                        |sink, element| sink.sink_query(pad, element, query),
+                    )
+                })
+                .build_with_target(&target_pad)
+                .map_err(|_| Error::PadConstruction("sink pad", target_pad.name().to_string()))?;
 
         ghost_pad
             .set_active(true)
@@ -559,14 +1233,6 @@ impl ObjectImpl for OpenTokSink {
 
         gst_debug!(CAT, obj: obj, "OpenTokSink initialization");
 
-        log::enable_log(LogLevel::Error);
-
-        // Make sure the audio device is ready before the session is initiated,
-        // otherwise OpenTok will use the libwebrtc default audio device.
-        let _ = AudioDevice::get_instance();
-
-        pipe_opentok_to_gst_log(*CAT);
-
         let element = obj.upcast_ref::<gst::Element>();
         let element = element.downgrade();
         *self.signal_emitter.lock().unwrap() = Some(SignalEmitter { element });
@@ -582,10 +1248,47 @@ impl ObjectImpl for OpenTokSink {
                     None,
                     glib::ParamFlags::WRITABLE,
                 ),
+                glib::ParamSpecString::new(
+                    "api-secret",
+                    "ApiSecret",
+                    "OpenTok API secret, used to generate a token when none is set",
+                    None,
+                    glib::ParamFlags::WRITABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "role",
+                    "Role",
+                    "Role of generated tokens (subscriber, publisher or moderator)",
+                    Some("publisher"),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt64::new(
+                    "expire-time",
+                    "ExpireTime",
+                    "Expiry time of generated tokens, in seconds since the Unix epoch (0 = 24 hours after generation)",
+                    0,
+                    u64::MAX,
+                    0,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "connection-data",
+                    "ConnectionData",
+                    "Connection data of generated tokens",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "credentials-file",
+                    "CredentialsFile",
+                    "YAML or JSON file holding the apiKey, sessionId, token and optional streamId of the session",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecString::new(
                     "location",
                     "Location",
-                    "OpenTok session location (i.e. opentok://<session id>?key=<api key>&token=<token>)",
+                    "OpenTok session location (i.e. opentok://<session id>[/<stream id>]?key=<api key>&token=<token>[&name=<stream name>][&audio-only=true|&video-only=true][&resolution=<width>x<height>])",
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
@@ -610,6 +1313,15 @@ impl ObjectImpl for OpenTokSink {
                     None,
                     glib::ParamFlags::WRITABLE,
                 ),
+                glib::ParamSpecUInt64::new(
+                    "token-expire-time",
+                    "TokenExpireTime",
+                    "Expiry time of the session token, in seconds since the Unix epoch (0 if unknown)",
+                    0,
+                    u64::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
                 glib::ParamSpecString::new(
                     "demo-room-uri",
                     "Room uri of the OpenTok demo",
@@ -617,7 +1329,22 @@ impl ObjectImpl for OpenTokSink {
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecBoolean::new(
+                    "publish-stereo",
+                    "Publish stereo",
+                    "Whether the audio is published in stereo, same as audio-channels=2",
+                    false,
+                    glib::ParamFlags::READWRITE | gst::PARAM_FLAG_MUTABLE_READY,
+                ),
+                timeout_property(),
+                session_state_property(),
             ]
+            .into_iter()
+            .chain(room_info_properties().iter().cloned())
+            .chain(reconnect_properties().iter().cloned())
+            .chain(audio_properties().iter().cloned())
+            .chain(publisher_properties().iter().cloned())
+            .collect()
         });
 
         PROPERTIES.as_ref()
@@ -643,6 +1370,27 @@ impl ObjectImpl for OpenTokSink {
                     log_if_err_fn(self.credentials.lock().unwrap().set_api_key(api_key));
                 }
             }
+            "api-secret" => {
+                if let Ok(api_secret) = value.get::<String>() {
+                    log_if_err_fn(self.credentials.lock().unwrap().set_api_secret(api_secret));
+                }
+            }
+            "role" => {
+                let role = value.get::<String>().expect("expected a string");
+                log_if_err_fn(self.credentials.lock().unwrap().set_role(&role));
+            }
+            "expire-time" => {
+                self.credentials
+                    .lock()
+                    .unwrap()
+                    .set_expire_time(value.get::<u64>().expect("expected a u64"));
+            }
+            "connection-data" => {
+                self.credentials
+                    .lock()
+                    .unwrap()
+                    .set_connection_data(value.get::<Option<String>>().expect("expected a string"));
+            }
             "location" => {
                 let location = value.get::<String>().expect("expected a string");
                 if let Err(e) = self.set_location(&location) {
@@ -656,11 +1404,63 @@ impl ObjectImpl for OpenTokSink {
             }
             "token" => {
                 if let Ok(token) = value.get::<String>() {
-                    log_if_err_fn(self.credentials.lock().unwrap().set_token(token));
+                    log_if_err_fn(self.credentials.lock().unwrap().set_token(token.clone()));
+                    self.update_token(obj.upcast_ref(), &token);
                 }
             }
+            "credentials-file" => {
+                let path = value.get::<Option<String>>().expect("expected a string");
+                log_if_err_fn(
+                    self.credentials
+                        .lock()
+                        .unwrap()
+                        .set_credentials_file(path.map(PathBuf::from)),
+                );
+            }
             "demo-room-uri" => {
-                log_if_err_fn(self.credentials.lock().unwrap().set_room_uri(value.get::<String>().expect("expected a string")));
+                log_if_err_fn(
+                    self.credentials
+                        .lock()
+                        .unwrap()
+                        .set_room_uri(value.get::<String>().expect("expected a string")),
+                );
+            }
+            name if name.starts_with("room-info-") => {
+                set_room_info_property(
+                    self.credentials.lock().unwrap().room_info_mut(),
+                    name,
+                    value,
+                );
+            }
+            name if name.starts_with("reconnect-") => {
+                set_reconnect_property(&mut self.reconnect_policy.lock().unwrap(), name, value);
+            }
+            name if is_publisher_property(name) => {
+                self.set_group_property(obj, None, name, value);
+            }
+            name if (is_audio_property(name) || name == "publish-stereo")
+                && !audio_settings_mutable(obj.upcast_ref()) =>
+            {
+                gst_warning!(CAT, obj: obj, "Cannot change {} once the audio device started", name);
+            }
+            name if is_audio_property(name) => {
+                let mut settings = *self.audio_settings.lock().unwrap();
+                set_audio_property(&mut settings, name, value);
+                self.set_audio_settings(obj, settings);
+            }
+            "publish-stereo" => {
+                let mut settings = *self.audio_settings.lock().unwrap();
+                settings.channels = match value.get::<bool>().expect("expected a boolean") {
+                    true => 2,
+                    false => 1,
+                };
+                self.set_audio_settings(obj, settings);
+            }
+            "timeout" => {
+                self.startup
+                    .lock()
+                    .unwrap()
+                    .set_timeout(value.get::<u32>().expect("expected a u32"));
             }
             _ => unimplemented!(),
         }
@@ -677,27 +1477,91 @@ impl ObjectImpl for OpenTokSink {
 
     fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
         match pspec.name() {
+            "role" => self.credentials.lock().unwrap().role().as_str().to_value(),
+            "expire-time" => self.credentials.lock().unwrap().expire_time().to_value(),
+            "connection-data" => self
+                .credentials
+                .lock()
+                .unwrap()
+                .connection_data()
+                .to_value(),
+            "token-expire-time" => self
+                .credentials
+                .lock()
+                .unwrap()
+                .token_expire_time()
+                .unwrap_or(0)
+                .to_value(),
+            "credentials-file" => self
+                .credentials
+                .lock()
+                .unwrap()
+                .credentials_file()
+                .map(|path| path.to_string_lossy().into_owned())
+                .to_value(),
             "location" => self.location().to_value(),
-            "demo-room-uri" => self.credentials.lock().unwrap().room_uri().map(|url| url.as_str()).to_value(),
+            "demo-room-uri" => self
+                .credentials
+                .lock()
+                .unwrap()
+                .room_uri()
+                .map(|url| url.as_str())
+                .to_value(),
+            "publish-stereo" => (self.audio_settings.lock().unwrap().channels == 2).to_value(),
+            name if is_audio_property(name) => {
+                audio_property(&self.audio_settings.lock().unwrap(), name)
+            }
             "stream-id" => self
-                .published_stream_id
+                .groups
                 .lock()
                 .unwrap()
-                .clone()
+                .values()
+                .find_map(|group| {
+                    group
+                        .published_stream
+                        .as_ref()
+                        .map(|stream| stream.id.clone())
+                })
                 .unwrap_or_else(|| "".into())
                 .to_value(),
+            name if name.starts_with("room-info-") => {
+                room_info_property(self.credentials.lock().unwrap().room_info(), name)
+            }
+            name if name.starts_with("reconnect-") => {
+                reconnect_property(&self.reconnect_policy.lock().unwrap(), name)
+            }
+            name if is_publisher_property(name) => {
+                publisher_property(&self.publisher_settings.lock().unwrap(), name)
+            }
+            "timeout" => self.startup.lock().unwrap().timeout().to_value(),
+            "session-state" => self.session_state.lock().unwrap().state().to_value(),
             _ => unimplemented!(),
         }
     }
 
     fn signals() -> &'static [glib::subclass::Signal] {
         static SIGNALS: Lazy<Vec<glib::subclass::Signal>> = Lazy::new(|| {
-            vec![glib::subclass::Signal::builder(
-                "published-stream",
-                &[String::static_type().into(), String::static_type().into()],
-                glib::types::Type::UNIT.into(),
-            )
-            .build()]
+            vec![
+                glib::subclass::Signal::builder(
+                    "published-stream",
+                    &[
+                        String::static_type().into(),
+                        String::static_type().into(),
+                        u32::static_type().into(),
+                    ],
+                    glib::types::Type::UNIT.into(),
+                )
+                .build(),
+                request_token_signal(),
+                send_signal_signal(|element| {
+                    OpenTokSink::from_element(element)
+                        .session
+                        .lock()
+                        .unwrap()
+                        .clone()
+                }),
+                signal_received_signal(),
+            ]
         });
 
         SIGNALS.as_ref()
@@ -708,8 +1572,6 @@ impl GstObjectImpl for OpenTokSink {}
 
 impl ElementImpl for OpenTokSink {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
-        init();
-
         static ELEMENT_METADATA: Lazy<gst::subclass::ElementMetadata> = Lazy::new(|| {
             gst::subclass::ElementMetadata::new(
                 "OpenTok Sink",
@@ -727,7 +1589,7 @@ impl ElementImpl for OpenTokSink {
             let (video_caps, audio_caps) = caps();
 
             let video_sink_pad_template = gst::PadTemplate::new(
-                "video_sink",
+                "video_sink_%u",
                 gst::PadDirection::Sink,
                 gst::PadPresence::Request,
                 &video_caps,
@@ -735,6 +1597,23 @@ impl ElementImpl for OpenTokSink {
             .unwrap();
 
             let audio_sink_pad_template = gst::PadTemplate::new(
+                "audio_sink_%u",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &audio_caps,
+            )
+            .unwrap();
+
+            // Pads of the first publisher, kept for compatibility.
+            let first_video_sink_pad_template = gst::PadTemplate::new(
+                "video_sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &video_caps,
+            )
+            .unwrap();
+
+            let first_audio_sink_pad_template = gst::PadTemplate::new(
                 "audio_sink",
                 gst::PadDirection::Sink,
                 gst::PadPresence::Request,
@@ -742,7 +1621,12 @@ impl ElementImpl for OpenTokSink {
             )
             .unwrap();
 
-            vec![video_sink_pad_template, audio_sink_pad_template]
+            vec![
+                video_sink_pad_template,
+                audio_sink_pad_template,
+                first_video_sink_pad_template,
+                first_audio_sink_pad_template,
+            ]
         });
         PAD_TEMPLATES.as_ref()
     }
@@ -751,13 +1635,12 @@ impl ElementImpl for OpenTokSink {
         &self,
         element: &Self::Type,
         template: &gst::PadTemplate,
-        _name: Option<String>,
+        name: Option<String>,
         _caps: Option<&gst::Caps>,
     ) -> Option<gst::Pad> {
-        let stream_type: StreamType = match template.name_template() {
-            Some(name) => name.as_str().into(),
-            None => return None,
-        };
+        let name_template = template.name_template()?;
+        let numbered = name_template.ends_with("_%u");
+        let stream_type: StreamType = name_template.trim_end_matches("_%u").into();
 
         gst_debug!(
             CAT,
@@ -768,19 +1651,55 @@ impl ElementImpl for OpenTokSink {
 
         gst_debug!(CAT, "Requesting new pad {:?}", stream_type);
 
-        if (stream_type == StreamType::Audio && self.audio_sink.lock().unwrap().is_some())
-            | (stream_type == StreamType::Video && self.video_sink.lock().unwrap().is_some())
-        {
-            gst_error!(
-                CAT,
-                obj: element,
-                "There is already an existing pad for a stream of type {:?}",
-                stream_type
-            );
-            return None;
-        }
+        let index = {
+            let groups = self.groups.lock().unwrap();
+            let has_sink = |index: &u32| {
+                groups
+                    .get(index)
+                    .map_or(false, |group| group.sink(stream_type).is_some())
+            };
+
+            // All the publishers share the same audio device, so there is no
+            // way to publish different audio streams.
+            if stream_type == StreamType::Audio
+                && groups.values().any(|group| group.audio_sink.is_some())
+            {
+                gst_error!(
+                    CAT,
+                    obj: element,
+                    "There is already an audio pad, and the audio device is shared by all the publishers"
+                );
+                return None;
+            }
+
+            let index = if !numbered {
+                0
+            } else {
+                match name.as_deref().and_then(parse_pad_name) {
+                    Some((_, index)) => index,
+                    None => (0..).find(|index| !has_sink(index)).unwrap(),
+                }
+            };
+            if has_sink(&index) {
+                gst_error!(
+                    CAT,
+                    obj: element,
+                    "There is already an existing pad for a stream of type {:?} in publisher {}",
+                    stream_type,
+                    index
+                );
+                return None;
+            }
+            index
+        };
 
-        match self.setup_sink(element, template, stream_type) {
+        let prefix: &'static str = stream_type.into();
+        let pad_name = if numbered {
+            format!("{}_{}", prefix, index)
+        } else {
+            prefix.to_string()
+        };
+        match self.setup_sink(element, template, stream_type, index, &pad_name) {
             Ok(pad) => Some(pad),
             Err(err) => {
                 gst_error!(CAT, obj: element, "{}", err,);
@@ -792,12 +1711,41 @@ impl ElementImpl for OpenTokSink {
     fn release_pad(&self, element: &Self::Type, pad: &gst::Pad) {
         gst_debug!(CAT, "Release pad {:?}", pad.name());
 
-        let bin = match pad.name().as_str().into() {
-            StreamType::Audio => self.audio_sink.lock().unwrap().take(),
-            StreamType::Video => self.video_sink.lock().unwrap().take(),
-            StreamType::Unknown__ => unreachable!(),
+        let (stream_type, index) = parse_pad_name(&pad.name()).unwrap();
+        let (bin, publisher) = {
+            let mut groups = self.groups.lock().unwrap();
+            let (bin, unused) = match groups.get_mut(&index) {
+                Some(group) => {
+                    let bin = group.sink_mut(stream_type).take();
+                    *group.muted_mut(stream_type) = false;
+                    (
+                        bin,
+                        group.audio_sink.is_none() && group.video_sink.is_none(),
+                    )
+                }
+                None => (None, false),
+            };
+            // A publisher without pads has nothing left to publish.
+            let publisher = if unused {
+                groups
+                    .remove(&index)
+                    .and_then(|group| group.publisher.lock().unwrap().take())
+            } else {
+                None
+            };
+            (bin, publisher)
         };
 
+        match publisher {
+            Some(publisher) => {
+                gst_debug!(CAT, obj: element, "Unpublishing publisher {}", index);
+                if let Err(e) = publisher.unpublish() {
+                    gst_error!(CAT, "Unpublish error {}", e);
+                }
+            }
+            None => self.toggle_media(element, index),
+        }
+
         if let Some(ref bin) = bin {
             if element.by_name(&bin.name()).is_some() {
                 bin.set_state(gst::State::Null).unwrap();
@@ -809,6 +1757,14 @@ impl ElementImpl for OpenTokSink {
         let _ = element.remove_pad(pad);
     }
 
+    fn set_context(&self, element: &Self::Type, context: &gst::Context) {
+        if let Some(session) = SharedSession::from_context(context) {
+            gst_debug!(CAT, obj: element, "Got shared session {}", session.session_id());
+            *self.shared_session.lock().unwrap() = Some(session);
+        }
+        self.parent_set_context(element, context);
+    }
+
     fn change_state(
         &self,
         element: &Self::Type,
@@ -816,30 +1772,38 @@ impl ElementImpl for OpenTokSink {
     ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
         gst_debug!(CAT, obj: element, "State changed {:?}", transition);
         if transition == gst::StateChange::NullToReady {
-            async_std::task::block_on(
-                self.credentials.lock().unwrap().load(Duration::from_secs(5))
-            ).map_err(|error| {
-                gst_error!(CAT, "Error changing state: {:?}", error);
-
-                gst::StateChangeError
+            self.start(element);
+        }
+        // Prerolling only completes once the session is connected.
+        let connecting = if transition == gst::StateChange::ReadyToPaused {
+            let connecting = self.startup.lock().unwrap().begin().map_err(|err| {
+                gst_error!(CAT, obj: element, "The session failed to connect");
+                err
             })?;
-
-            if let Err(e) = self.maybe_init_session(&element.clone().upcast()) {
-                gst_error!(
-                    CAT,
-                    obj: element,
-                    "Failed to initialize OpenTok session: {:?}",
-                    e
-                )
+            if connecting {
+                self.async_start(element);
             }
-
-        }
+            connecting
+        } else {
+            false
+        };
         if transition == gst::StateChange::PausedToPlaying {
-            self.ensure_publisher(element);
+            self.ensure_publishers(element);
         }
-        let success = self.parent_change_state(element, transition)?;
+        let success = match self.parent_change_state(element, transition) {
+            Ok(success) => success,
+            Err(err) => {
+                if connecting && self.startup.lock().unwrap().failed() {
+                    self.async_done(element);
+                }
+                return Err(err);
+            }
+        };
         if transition == gst::StateChange::ReadyToNull {
-            self.teardown();
+            self.teardown(element);
+        }
+        if connecting {
+            return Ok(gst::StateChangeSuccess::Async);
         }
         Ok(success)
     }
@@ -865,3 +1829,89 @@ impl URIHandlerImpl for OpenTokSink {
             .map_err(|e| glib::Error::new(gst::CoreError::Failed, &format!("{:?}", e)))
     }
 }
+
+/// Sink pad of `opentoksink`, with the properties of the publisher of its
+/// group, and a `muted` property withholding the media of the pad.
+#[derive(Default)]
+pub struct OpenTokSinkPad {}
+
+impl OpenTokSinkPad {
+    /// The element of `pad`, the media type of the pad, and the index of
+    /// its publisher.
+    fn publisher(pad: &super::OpenTokSinkPad) -> Option<(super::OpenTokSink, StreamType, u32)> {
+        let element = pad.parent()?.downcast::<super::OpenTokSink>().ok()?;
+        let (stream_type, index) = parse_pad_name(&pad.name())?;
+        Some((element, stream_type, index))
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for OpenTokSinkPad {
+    const NAME: &'static str = "OpenTokSinkPad";
+    type Type = super::OpenTokSinkPad;
+    type ParentType = gst::GhostPad;
+}
+
+impl ObjectImpl for OpenTokSinkPad {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+            vec![glib::ParamSpecBoolean::new(
+                "muted",
+                "Muted",
+                "Whether the media of this pad is withheld from the published stream",
+                false,
+                glib::ParamFlags::READWRITE,
+            )]
+            .into_iter()
+            .chain(publisher_properties().iter().cloned())
+            .collect()
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(
+        &self,
+        pad: &Self::Type,
+        _id: usize,
+        value: &glib::Value,
+        pspec: &glib::ParamSpec,
+    ) {
+        let (element, stream_type, index) = match OpenTokSinkPad::publisher(pad) {
+            Some(publisher) => publisher,
+            None => {
+                gst_warning!(CAT, obj: pad, "Pad not added to opentoksink, ignoring {}", pspec.name());
+                return;
+            }
+        };
+        let sink = OpenTokSink::from_instance(&element);
+        match pspec.name() {
+            "muted" => {
+                let muted = value.get::<bool>().expect("expected a boolean");
+                sink.set_muted(&element, stream_type, index, muted);
+            }
+            name => sink.set_group_property(&element, Some(index), name, value),
+        }
+    }
+
+    fn property(&self, pad: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match (OpenTokSinkPad::publisher(pad), pspec.name()) {
+            (Some((element, stream_type, index)), "muted") => OpenTokSink::from_instance(&element)
+                .muted(stream_type, index)
+                .to_value(),
+            (Some((element, _, index)), name) => {
+                OpenTokSink::from_instance(&element).group_property(index, name)
+            }
+            (None, "muted") => false.to_value(),
+            (None, name) => publisher_property(&PublisherSettings::default(), name),
+        }
+    }
+}
+
+impl GstObjectImpl for OpenTokSinkPad {}
+
+impl PadImpl for OpenTokSinkPad {}
+
+impl ProxyPadImpl for OpenTokSinkPad {}
+
+impl GhostPadImpl for OpenTokSinkPad {}
@@ -23,6 +23,14 @@ glib::wrapper! {
 unsafe impl Send for OpenTokSink {}
 unsafe impl Sync for OpenTokSink {}
 
+// Sink pads of the element, holding the settings of their publisher.
+glib::wrapper! {
+    pub struct OpenTokSinkPad(ObjectSubclass<imp::OpenTokSinkPad>) @extends gst::GhostPad, gst::ProxyPad, gst::Pad, gst::Object;
+}
+
+unsafe impl Send for OpenTokSinkPad {}
+unsafe impl Sync for OpenTokSinkPad {}
+
 // Registers the type for our element, and then registers in GStreamer under
 // the name "opentoksink" for being able to instantiate it via e.g.
 // gst::ElementFactory::make().
@@ -7,7 +7,11 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::common::{caps, Credentials, Error, IpcMessage, StreamMessage, StreamMessageData};
+use crate::common::secret::redact_location;
+use crate::common::{
+    received_caps, Credentials, Error, IpcMessage, IpcPeers, OpenTokUri, RemoteDirection,
+    RemoteSettings, StreamMessage, StreamMessageData,
+};
 
 use glib::subclass::prelude::*;
 use glib::{clone, ToValue};
@@ -15,16 +19,15 @@ use gst::prelude::*;
 use gst::subclass::prelude::*;
 use gst::{gst_debug, gst_error, gst_trace};
 use gst_app::prelude::BaseTransformExt;
-use ipc_channel::ipc::{IpcOneShotServer, IpcReceiver};
+use ipc_channel::ipc::IpcOneShotServer;
 use once_cell::sync::{Lazy, OnceCell};
 use signal_child::Signalable;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Child;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use url::Url;
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     gst::DebugCategory::new(
@@ -56,28 +59,32 @@ pub struct OpenTokSrcRemote {
     /// Boolean flag to indicate whether the auxiliary threads should
     /// be running or not.
     aux_threads_running: Arc<AtomicBool>,
+    /// Last location set, for the options that are not credentials.
+    uri: Mutex<OpenTokUri>,
 }
 
 impl OpenTokSrcRemote {
+    /// Location of the wrapped element, with the given scheme.
+    fn session_uri(&self, credentials: &Credentials, remote: bool) -> Option<OpenTokUri> {
+        let base = OpenTokUri {
+            remote,
+            stream_id: self.stream_id.get().cloned(),
+            ..self.uri.lock().unwrap().clone()
+        };
+        credentials.to_uri(&base)
+    }
+
     fn location(&self) -> Option<String> {
-        self.credentials
-            .lock()
-            .unwrap()
-            .session_id()
-            .map(|id| format!("opentok://{}", id))
+        let credentials = self.credentials.lock().unwrap();
+        self.session_uri(&credentials, true).map(|uri| uri.to_uri())
     }
 
     fn set_location(&self, location: &str) -> Result<(), glib::BoolError> {
-        gst_debug!(CAT, "Setting location to {}", location);
-        let url = Url::parse(location).map_err(|err| {
-            glib::BoolError::new(
-                format!("Malformed url {:?}", err),
-                file!(),
-                "set_location",
-                line!(),
-            )
+        gst_debug!(CAT, "Setting location to {}", redact_location(location));
+        let uri = location.parse::<OpenTokUri>().map_err(|err| {
+            glib::BoolError::new(err.to_string(), file!(), "set_location", line!())
         })?;
-        let credentials: Credentials = url.into();
+        let credentials = Credentials::from(&uri);
         gst_debug!(CAT, "Credentials {:?}", credentials);
         if let Some(ref stream_id) = credentials.stream_id() {
             if !stream_id.is_empty() {
@@ -86,6 +93,7 @@ impl OpenTokSrcRemote {
         }
 
         *self.credentials.lock().unwrap() = credentials;
+        *self.uri.lock().unwrap() = uri;
         Ok(())
     }
 
@@ -101,38 +109,12 @@ impl OpenTokSrcRemote {
         })
     }
 
-    fn launch_child_process(
-        &self,
-        ipc_server_name: &str,
-    ) -> Result<(), Error> {
+    fn launch_child_process(&self, ipc_server_name: &str) -> Result<(), Error> {
         gst_debug!(CAT, "Spawning child process");
+        // Everything else, credentials included, goes through IPC.
         let mut command = std::process::Command::new("gst-opentok-helper");
+        command.arg("--ipc-server").arg(ipc_server_name);
 
-        command.arg("--direction")
-                .arg("src")
-                .arg("--ipc-server")
-                .arg(ipc_server_name);
-
-        let credentials = self.credentials.lock().unwrap();
-        if credentials.api_key().is_some() {
-            command
-                .arg("--api-key")
-                .arg(credentials.api_key().unwrap())
-                .arg("--session-id")
-                .arg(credentials.session_id().unwrap())
-                .arg("--token")
-                .arg(credentials.token().unwrap());
-        } else {
-            command
-                .arg("--room-uri")
-                .arg(credentials.room_uri().unwrap().as_str());
-
-        }
-        drop(credentials);
-
-        if let Some(stream_id) = self.stream_id.get() {
-            command.arg("--stream-id").arg(stream_id);
-        }
         *self.child_process.lock().unwrap() = Some(
             command
                 .spawn()
@@ -141,6 +123,25 @@ impl OpenTokSrcRemote {
         Ok(())
     }
 
+    fn remote_settings(&self) -> Result<RemoteSettings, Error> {
+        let credentials = self.credentials.lock().unwrap();
+        let missing = |what: &'static str| Error::Init(format!("Missing {}", what));
+        let uri = self
+            .session_uri(&credentials, false)
+            .ok_or_else(|| missing("session ID"))?;
+        if uri.api_key.is_none() {
+            return Err(missing("API key"));
+        }
+        if uri.token.is_none() {
+            return Err(missing("token"));
+        }
+        Ok(RemoteSettings {
+            direction: RemoteDirection::Source,
+            uri,
+            properties: vec![],
+        })
+    }
+
     fn init_stream_pipeline(
         element: &gst::Element,
         stream_type: Stream,
@@ -269,13 +270,11 @@ impl OpenTokSrcRemote {
             .unwrap();
     }
 
-    fn init(
-        &self,
-        element: &gst::Element,
-    ) -> Result<(), Error> {
+    fn init(&self, element: &gst::Element) -> Result<(), Error> {
         // Spawn the child process and the auxiliary threads and hand over the
         // ipc server name.
-        let (ipc_server, ipc_server_name): (IpcOneShotServer<IpcReceiver<IpcMessage>>, String) =
+        let settings = self.remote_settings()?;
+        let (ipc_server, ipc_server_name): (IpcOneShotServer<IpcPeers>, String) =
             IpcOneShotServer::new().map_err(|_| Error::OpenTokRemoteLaunchFailed)?;
 
         self.launch_child_process(&ipc_server_name)?;
@@ -299,8 +298,12 @@ impl OpenTokSrcRemote {
             @weak aux_threads_running,
         => move || {
             gst_debug!(CAT, obj: &element, "Control thread running");
-            let (_, ipc_receiver) = ipc_server.accept().unwrap();
-            gst_debug!(CAT, obj: &element, "Got IPC receiver");
+            let (_, (ipc_sender, ipc_receiver)) = ipc_server.accept().unwrap();
+            gst_debug!(CAT, obj: &element, "Got IPC peers");
+            if let Err(err) = ipc_sender.send(IpcMessage::Settings(settings)) {
+                OpenTokSrcRemote::critical_error(&err.to_string(), &element, &child_process);
+                return;
+            }
             loop {
                 if !aux_threads_running.load(Ordering::Relaxed) {
                     break;
@@ -441,7 +444,16 @@ impl OpenTokSrcRemote {
     }
 
     fn maybe_init(&self, element: &gst::Element) -> Result<(), Error> {
-        let credentials = self.credentials.lock().unwrap();
+        let mut credentials = self.credentials.lock().unwrap();
+        // Demo rooms are resolved here as well, the helper only ever gets
+        // ready to use credentials.
+        async_std::task::block_on(credentials.load())
+            .map_err(|err| Error::Init(err.to_string()))?;
+        if let Some(stream_id) = credentials.stream_id().cloned() {
+            if !stream_id.is_empty() && self.stream_id.get().is_none() {
+                let _ = self.set_stream_id(stream_id);
+            }
+        }
         if credentials.is_complete() {
             drop(credentials);
             return self.init(element);
@@ -476,6 +488,7 @@ impl ObjectSubclass for OpenTokSrcRemote {
             video_src_pad_template,
             audio_src_pad_template,
             aux_threads_running: Default::default(),
+            uri: Default::default(),
         }
     }
 }
@@ -491,10 +504,17 @@ impl ObjectImpl for OpenTokSrcRemote {
     fn properties() -> &'static [glib::ParamSpec] {
         static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
             vec![
+                glib::ParamSpecString::new(
+                    "credentials-file",
+                    "CredentialsFile",
+                    "YAML or JSON file holding the apiKey, sessionId, token and optional streamId of the session",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecString::new(
                     "location",
                     "Location",
-                    "OpenTok session location (i.e. opentok-remote://<session id>/key=<api key>&token=<token>)",
+                    "OpenTok session location (i.e. opentok-remote://<session id>[/<stream id>]?key=<api key>&token=<token>[&name=<stream name>][&audio-only=true|&video-only=true][&resolution=<width>x<height>])",
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
@@ -521,6 +541,17 @@ impl ObjectImpl for OpenTokSrcRemote {
     ) {
         gst_trace!(CAT, obj: obj, "Setting property {:?}", pspec.name());
         match pspec.name() {
+            "credentials-file" => {
+                let path = value.get::<Option<String>>().expect("expected a string");
+                if let Err(e) = self
+                    .credentials
+                    .lock()
+                    .unwrap()
+                    .set_credentials_file(path.map(PathBuf::from))
+                {
+                    gst_error!(CAT, obj: obj, "Failed to set credentials file: {:?}", e)
+                }
+            }
             "location" => {
                 let location = value.get::<String>().expect("expected a string");
                 if let Err(e) = self.set_location(&location) {
@@ -533,6 +564,13 @@ impl ObjectImpl for OpenTokSrcRemote {
 
     fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
         match pspec.name() {
+            "credentials-file" => self
+                .credentials
+                .lock()
+                .unwrap()
+                .credentials_file()
+                .map(|path| path.to_string_lossy().into_owned())
+                .to_value(),
             "location" => self.location().to_value(),
             "is-live" => true.to_value(),
             _ => unimplemented!(),
@@ -558,7 +596,7 @@ impl ElementImpl for OpenTokSrcRemote {
 
     fn pad_templates() -> &'static [gst::PadTemplate] {
         static PAD_TEMPLATES: Lazy<Vec<gst::PadTemplate>> = Lazy::new(|| {
-            let (video_caps, audio_caps) = caps();
+            let (video_caps, audio_caps) = received_caps();
 
             let video_src_pad_template = gst::PadTemplate::new(
                 "video_stream_%u",
@@ -7,25 +7,43 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::common::{caps, gst_from_otc_format, pipe_opentok_to_gst_log, Credentials, Error, init};
+use crate::common::audio::{
+    audio_device_caps, audio_properties, audio_property, audio_settings_mutable, is_audio_property,
+    set_audio_property, AudioSettings,
+};
+use crate::common::backend::{
+    backend, AudioSamples, SessionBackend, SessionEvent, SessionEventHandler, Signal, StreamInfo,
+    SubscriberBackend, SubscriberEvent, SubscriberEventHandler, SubscriberSettings, VideoFrameData,
+};
+use crate::common::messages::{with_session_messages, with_subscriber_messages};
+use crate::common::reconnect::{reconnect_properties, reconnect_property, set_reconnect_property};
+use crate::common::room::{room_info_properties, room_info_property, set_room_info_property};
+use crate::common::secret::redact_location;
+use crate::common::session::{obtain_session, SessionHandle, SharedSession};
+use crate::common::signaling::{
+    send_signal_signal, signal_received, signal_received_signal, signal_to_json, SIGNAL_CAPS,
+};
+use crate::common::startup::{timeout_property, Startup};
+use crate::common::state::{
+    session_state_property, update_session_state, SessionInput, SessionStateMachine,
+};
+use crate::common::timestamps::{FrameTimestamps, FrameTiming};
+use crate::common::token::{
+    request_token, request_token_signal, token_is_expired, watch_token_expiry, TokenRequestReason,
+};
+use crate::common::{received_caps, Credentials, Error, OpenTokUri, ReconnectPolicy};
 
-use anyhow::anyhow;
 use byte_slice_cast::*;
 use glib::subclass::prelude::*;
 use glib::{clone, ToValue};
 use gst::prelude::*;
 use gst::subclass::prelude::*;
-use gst::{gst_debug, gst_error, gst_fixme, gst_trace};
+use gst::{gst_debug, gst_error, gst_fixme, gst_trace, gst_warning};
 use once_cell::sync::Lazy;
-use opentok::audio_device::{AudioDevice, AudioSample};
-use opentok::log::{self, LogLevel};
-use opentok::session::{Session, SessionCallbacks};
-use opentok::subscriber::{Subscriber as OpenTokSubscriber, SubscriberCallbacks};
-use opentok::video_frame::VideoFrame;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use url::Url;
+use std::thread;
 
 static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     gst::DebugCategory::new(
@@ -35,11 +53,32 @@ static CAT: Lazy<gst::DebugCategory> = Lazy::new(|| {
     )
 });
 
+/// Interval of the GAP events pushed while the session reconnects.
+const GAP_INTERVAL: gst::ClockTime = gst::ClockTime::from_mseconds(100);
+/// Default of the `latency` property, like `rtpbin`.
+const DEFAULT_LATENCY: gst::ClockTime = gst::ClockTime::from_mseconds(200);
+
+/// How the received audio and video are timestamped.
+#[derive(Clone, Copy, Debug)]
+struct SyncSettings {
+    /// How late buffers may be pushed after their timestamp.
+    latency: gst::ClockTime,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        Self {
+            latency: DEFAULT_LATENCY,
+        }
+    }
+}
+
 #[allow(dead_code)]
 struct Subscriber {
-    subscriber: OpenTokSubscriber,
+    subscriber: Arc<dyn SubscriberBackend>,
     video_appsrc: gst::Element,
     video_pad: gst::GhostPad,
+    holder: Arc<Mutex<AppSrcStateHolder>>,
 }
 
 struct State {
@@ -49,30 +88,61 @@ struct State {
     /// Every Vonage Video API video chat occurs within a session.
     /// You can think of a session as a “room” where clients can interact
     /// with one another in real-time.
-    session: Option<Session>,
+    session: Option<Arc<SessionHandle>>,
+    /// Whether to subscribe to the streams published by the sinks sharing
+    /// the session.
+    own_streams: bool,
     /// OpenTok Stream identifier.
     /// We will be connecting to this stream only.
     stream_id: Option<String>,
+    /// Last location set, for the stream name filter, the audio or video
+    /// only flags and the preferred resolution.
+    uri: OpenTokUri,
     /// List of OpenTok subscriber instances.
     /// We create a new subscriber per each stream that is created in the
     /// session, unless `stream_id` is set, in which case, a single subscriber
     /// for that specific stream is created.
     subscribers: HashMap<String, Subscriber>,
     flow_combiner: gst_base::UniqueFlowCombiner,
+    /// Fires `request-token` ahead of the expiry of the session token.
+    token_watch: Option<gst::SingleShotClockId>,
+    /// Set when the session refused the token, the next `token` property
+    /// change connects again.
+    awaiting_token: bool,
+    reconnect_policy: ReconnectPolicy,
+    /// Pushes GAP events while the session reconnects.
+    gaps: Option<gst::PeriodicClockId>,
+    /// Whether to expose the received signals on the `signal_stream` pad.
+    signal_stream: bool,
+    /// Format of the audio rendered by the SDK.
+    audio_settings: AudioSettings,
+    /// Shared with the appsrc state holders.
+    sync: Arc<Mutex<SyncSettings>>,
 }
 
 #[derive(Clone)]
 pub struct OpenTokSrc {
     state: Arc<Mutex<State>>,
+    /// Session shared with the other elements of the pipeline, as given by
+    /// the `opentok.session` context.
+    shared_session: Arc<Mutex<Option<Arc<SharedSession>>>>,
+    /// Tracks the asynchronous READY→PAUSED transition.
+    startup: Arc<Mutex<Startup>>,
+    session_state: Arc<Mutex<SessionStateMachine>>,
     /// Pad template for the video stream.
     video_src_pad_template: gst::PadTemplate,
     /// Pad template for the audio stream.
     audio_src_pad_template: gst::PadTemplate,
+    /// Pad template for the received signals.
+    signal_src_pad_template: gst::PadTemplate,
 }
 
 struct AppSrcStateHolder {
     allocator: Option<gst::Allocator>,
     query_allocator: bool,
+    /// Maps the capture timestamps of the video frames to running time.
+    timestamps: FrameTimestamps,
+    sync: Arc<Mutex<SyncSettings>>,
 }
 
 impl AppSrcStateHolder {
@@ -81,6 +151,7 @@ impl AppSrcStateHolder {
         caps: &gst::Caps,
         src: &gst::Element,
         data: &[u8],
+        timing: Option<FrameTiming>,
     ) {
         let query_allocator = holder.lock().unwrap().query_allocator;
         if query_allocator && holder.lock().unwrap().allocator.is_none() {
@@ -123,10 +194,22 @@ impl AppSrcStateHolder {
             None => gst::Buffer::from_slice(data.to_vec()),
         };
 
-        if let Some(timestamp) = src.current_running_time() {
+        // Without a better estimate, buffers are stamped when they arrive.
+        let timing = timing.or_else(|| {
+            src.current_running_time().map(|pts| FrameTiming {
+                pts,
+                duration: None,
+                discont: false,
+            })
+        });
+        if let Some(timing) = timing {
             let buffer = buffer.get_mut().unwrap();
-            buffer.set_pts(Some(timestamp));
-            buffer.set_dts(Some(timestamp));
+            buffer.set_pts(Some(timing.pts));
+            buffer.set_dts(Some(timing.pts));
+            buffer.set_duration(timing.duration);
+            if timing.discont {
+                buffer.set_flags(gst::BufferFlags::DISCONT);
+            }
         }
 
         let sample = gst::Sample::builder().caps(caps).buffer(&buffer).build();
@@ -147,24 +230,20 @@ impl State {
         location: &str,
     ) -> Result<(), glib::BoolError> {
         if self.credentials.room_uri().is_some() {
-                return Err(glib::BoolError::new(
-                    format!("Credential already set {:?}", self.credentials),
-                    file!(),
-                    "set_location",
-                    line!(),
-                ));
-        }
-
-        gst_debug!(CAT, obj: element, "Setting location to {}", location);
-        let url = Url::parse(location).map_err(|err| {
-            glib::BoolError::new(
-                format!("Malformed url {:?}", err),
+            return Err(glib::BoolError::new(
+                format!("Credential already set {:?}", self.credentials),
                 file!(),
                 "set_location",
                 line!(),
-            )
+            ));
+        }
+
+        gst_debug!(CAT, obj: element, "Setting location to {}", redact_location(location));
+        let uri = location.parse::<OpenTokUri>().map_err(|err| {
+            glib::BoolError::new(err.to_string(), file!(), "set_location", line!())
         })?;
-        let credentials: Credentials = url.into();
+        let mut credentials = Credentials::from(&uri);
+        credentials.set_room_info(self.credentials.room_info().clone());
         gst_debug!(CAT, obj: element, "Credentials {:?}", credentials);
         if let Some(ref stream_id) = credentials.stream_id() {
             if !stream_id.is_empty() {
@@ -173,6 +252,7 @@ impl State {
         }
 
         self.credentials = credentials;
+        self.uri = uri;
         Ok(())
     }
 
@@ -181,14 +261,19 @@ impl State {
         self.stream_id = Some(id);
     }
 
+    /// Subscribes to a stream, returning its video appsrc and pad, unless
+    /// the stream was subscribed to already before the session reconnected.
     fn stream_received(
         &mut self,
         element: &super::OpenTokSrc,
         video_src_pad_template: &gst::PadTemplate,
-        session: &opentok::session::Session,
-        stream: opentok::stream::Stream,
-    ) -> Result<(gst::Element, gst::GhostPad), Error> {
-        let stream_id = stream.id();
+        stream: StreamInfo,
+    ) -> Result<Option<(gst::Element, gst::GhostPad)>, Error> {
+        let session = self
+            .session
+            .clone()
+            .ok_or(Error::InvalidState("Stream received without session"))?;
+        let stream_id = stream.id;
         gst_debug!(CAT, obj: element, "Stream received {}", stream_id);
 
         if let Some(ref stream_id_to_subscribe) = self.stream_id {
@@ -208,6 +293,35 @@ impl State {
             gst_debug!(CAT, obj: element, "We want to subscribe to all streams");
         }
 
+        if let Some(ref name) = self.uri.stream_name {
+            if *name != stream.name {
+                return Err(Error::InvalidStream(
+                    stream_id,
+                    format!("streams named {}", name),
+                ));
+            }
+        }
+
+        let settings = SubscriberSettings {
+            subscribe_to_audio: !self.uri.video_only,
+            subscribe_to_video: !self.uri.audio_only,
+            preferred_resolution: self.uri.resolution,
+        };
+
+        // The connection was restored, the pads stay in place.
+        if let Some(subscriber) = self.subscribers.get_mut(&stream_id) {
+            gst_debug!(CAT, obj: element, "Subscribing again to stream {}", stream_id);
+            let handler = subscriber_handler(
+                element,
+                &stream_id,
+                &subscriber.holder,
+                &subscriber.video_pad,
+                &subscriber.video_appsrc,
+            );
+            subscriber.subscriber = session.subscribe(&stream_id, &settings, handler)?;
+            return Ok(None);
+        }
+
         // The stream may grow a video feed at some point during its life time and we
         // won't get any other notification about it, so we make sure to setup the video
         // pipeline even if the stream has no video enabled at this point.
@@ -222,6 +336,7 @@ impl State {
             .map_err(|_| Error::MissingElement("appsrc"))?;
         video_appsrc.set_property("is-live", &true);
         video_appsrc.set_property("format", &gst::Format::Time);
+        set_appsrc_latency(&video_appsrc, self.sync.lock().unwrap().latency);
 
         bin.add(&video_appsrc)
             .map_err(|_| Error::AddElement("appsrc"))?;
@@ -246,77 +361,75 @@ impl State {
             .build_with_target(&appsrc_src_pad)
             .unwrap();
 
-        self.flow_combiner.add_pad(&video_pad);
-
         let appsrc_state_holder = AppSrcStateHolder {
             allocator: None,
             query_allocator: true,
+            timestamps: Default::default(),
+            sync: self.sync.clone(),
         };
         let holder = Arc::new(Mutex::new(appsrc_state_holder));
 
-        let subscriber_callbacks = SubscriberCallbacks::builder()
-            .on_render_frame(clone!(
-                @weak element,
-                @weak video_appsrc,
-            => move |_, frame| {
-                OpenTokSrc::push_video_frame(&holder, &video_appsrc, frame)
-            }))
-            .on_error(clone!(
-                @weak element
-            => move |_, error, _| {
-                gst_error!(CAT, obj: &element, "Error notified from subscriber: {:?}", error);
-            }))
-            .on_audio_enabled(clone!(
-                @weak element
-            => move |_| {
-                gst_debug!(CAT, obj: &element, "Audio enabled");
-            }))
-            .on_audio_disabled(clone!(
-                @weak element
-            => move |_| {
-                gst_debug!(CAT, obj: &element, "Audio disabled");
-            }))
-            .on_video_enabled(clone!(@weak video_pad,
-                                     @weak element,
-                                     @weak video_appsrc => move |_, _| {
-                OpenTokSrc::enable_video(&element, &video_pad, &video_appsrc);
-            }))
-            .on_video_disabled(clone!(@weak video_pad,
-                                      @weak element,
-                                      @weak video_appsrc => move |_, _| {
-                OpenTokSrc::disable_video(&element, &video_pad, &video_appsrc);
-            }))
-            .build();
-
-        let subscriber = OpenTokSubscriber::new(subscriber_callbacks);
-
-        subscriber
-            .set_stream(stream)
-            .map_err(|e| Error::SubscriberStreamSetupFailed(format!("{}", e)))?;
-
-        if let Err(err) = session.subscribe(&subscriber) {
-            gst_error!(
-                CAT,
-                obj: element,
-                "Failed to subscribe to stream {:?}: {:?}",
-                stream_id,
+        let handler = subscriber_handler(element, &stream_id, &holder, &video_pad, &video_appsrc);
+        let subscriber = session
+            .subscribe(&stream_id, &settings, handler)
+            .map_err(|err| {
+                gst_error!(
+                    CAT,
+                    obj: element,
+                    "Failed to subscribe to stream {:?}: {:?}",
+                    stream_id,
+                    err
+                );
+                let _ = bin.remove(&video_appsrc);
                 err
-            );
-        }
+            })?;
 
+        self.flow_combiner.add_pad(&video_pad);
         self.subscribers.insert(
             stream_id,
             Subscriber {
                 subscriber,
                 video_appsrc: video_appsrc.clone(),
                 video_pad: video_pad.clone(),
+                holder,
             },
         );
-        Ok((video_appsrc, video_pad))
+        Ok(Some((video_appsrc, video_pad)))
+    }
+
+    /// Keeps the downstream elements going while the session reconnects,
+    /// with a GAP event on each pad every [`GAP_INTERVAL`].
+    fn start_gaps(&mut self, element: &super::OpenTokSrc) {
+        if self.gaps.is_some() {
+            return;
+        }
+
+        let clock = gst::SystemClock::obtain();
+        let start = match clock.time() {
+            Some(now) => now + GAP_INTERVAL,
+            None => return,
+        };
+        let id = clock.new_periodic_id(start, GAP_INTERVAL);
+        let element_weak = element.downgrade();
+        if let Err(err) = id.wait_async(move |_, _, _| {
+            if let Some(element) = element_weak.upgrade() {
+                OpenTokSrc::push_gaps(&element);
+            }
+        }) {
+            gst_warning!(CAT, obj: element, "Cannot schedule GAP events: {:?}", err);
+            return;
+        }
+        self.gaps = Some(id);
     }
 
-    fn stream_dropped(&mut self, element: &super::OpenTokSrc, stream: opentok::stream::Stream) {
-        let stream_id = stream.id();
+    fn stop_gaps(&mut self) {
+        if let Some(id) = self.gaps.take() {
+            id.unschedule();
+        }
+    }
+
+    fn stream_dropped(&mut self, element: &super::OpenTokSrc, stream: StreamInfo) {
+        let stream_id = stream.id;
         gst_debug!(CAT, obj: element, "Stream dropped {}", stream_id);
 
         let subscriber = match self.subscribers.remove(&stream_id) {
@@ -345,13 +458,16 @@ impl State {
         bin.remove(&subscriber.video_appsrc).unwrap();
         let _ = bin.remove_pad(&subscriber.video_pad);
 
-        if self.subscribers.is_empty() {
+        // There is no audio pad in video only mode.
+        if let Some(audio_pad) = element
+            .static_pad("audio_stream")
+            .filter(|_| self.subscribers.is_empty())
+        {
             gst_debug!(
                 CAT,
                 obj: element,
                 "All subscribers gone. Releasing audio pad"
             );
-            let audio_pad = element.static_pad("audio_stream").unwrap();
             audio_pad.set_active(false).unwrap();
             let ghost_pad = audio_pad.downcast_ref::<gst::GhostPad>().unwrap();
             let appsrc_pad = ghost_pad.target().unwrap();
@@ -365,70 +481,206 @@ impl State {
     }
 }
 
+/// Makes `appsrc` answer latency queries with `latency`, the buffers being
+/// pushed up to that late.
+fn set_appsrc_latency(appsrc: &gst::Element, latency: gst::ClockTime) {
+    appsrc.set_property("min-latency", latency.nseconds() as i64);
+}
+
+fn subscriber_handler(
+    element: &super::OpenTokSrc,
+    stream_id: &str,
+    holder: &Arc<Mutex<AppSrcStateHolder>>,
+    video_pad: &gst::GhostPad,
+    video_appsrc: &gst::Element,
+) -> SubscriberEventHandler {
+    let holder = holder.clone();
+    let handler: SubscriberEventHandler = Arc::new(clone!(
+        @weak element,
+        @weak video_pad,
+        @weak video_appsrc,
+    => move |event| match event {
+        SubscriberEvent::VideoFrame(frame) => {
+            OpenTokSrc::push_video_frame(&holder, &video_appsrc, frame)
+        }
+        SubscriberEvent::Error(error) => {
+            gst_error!(CAT, obj: &element, "Error notified from subscriber: {:?}", error);
+        }
+        SubscriberEvent::AudioEnabled => {
+            gst_debug!(CAT, obj: &element, "Audio enabled");
+        }
+        SubscriberEvent::AudioDisabled => {
+            gst_debug!(CAT, obj: &element, "Audio disabled");
+        }
+        SubscriberEvent::VideoEnabled => {
+            OpenTokSrc::enable_video(&element, &video_pad, &video_appsrc);
+        }
+        SubscriberEvent::VideoDisabled => {
+            OpenTokSrc::disable_video(&element, &video_pad, &video_appsrc);
+        }
+        _ => {}
+    }));
+    with_subscriber_messages(element.upcast_ref(), stream_id, handler)
+}
+
 fn generate_video_pad_name(subscribers: &HashMap<String, Subscriber>) -> std::string::String {
     let mut id = 0;
     for s in subscribers.values() {
-        if let Some(stream) = s.subscriber.get_stream() {
-            if stream.has_video() {
-                id += 1;
-            }
+        if s.subscriber.stream().has_video {
+            id += 1;
         }
     }
     format!("video_stream_{}", id)
 }
 
 impl OpenTokSrc {
-    fn start(&self, element: &super::OpenTokSrc) -> Result<(), anyhow::Error> {
-        gst_error!(CAT, obj: element, "OpenTokSrc initialization");
+    /// Loads the credentials in the background, then connects the session.
+    fn start(&self, element: &super::OpenTokSrc) {
+        gst_debug!(CAT, obj: element, "OpenTokSrc initialization");
+
+        let mut credentials = self.state.lock().unwrap().credentials.clone();
+        let epoch = {
+            let mut startup = self.startup.lock().unwrap();
+            startup.arm(element.upcast_ref(), |element| {
+                OpenTokSrc::connection_failed(element, "Timed out connecting the session");
+            });
+            startup.epoch()
+        };
+        update_session_state(
+            element.upcast_ref(),
+            &self.session_state,
+            SessionInput::Start,
+        );
 
-        async_std::task::block_on(
-            self.state.lock().unwrap().credentials.load(Duration::from_secs(5))
-        )?;
+        let element_weak = element.downgrade();
+        thread::spawn(move || {
+            let result = async_std::task::block_on(credentials.load());
+            let element = match element_weak.upgrade() {
+                Some(element) => element,
+                None => return,
+            };
+            let src = OpenTokSrc::from_instance(&element);
+            if src.startup.lock().unwrap().epoch() != epoch {
+                gst_debug!(CAT, obj: &element, "Stopped while loading the credentials");
+                return;
+            }
+            if let Err(err) = result {
+                OpenTokSrc::connection_failed(
+                    element.upcast_ref(),
+                    &format!("Cannot load credentials: {}", err),
+                );
+                return;
+            }
 
-        log::enable_log(LogLevel::Error);
+            {
+                let mut state = src.state.lock().unwrap();
+                // The credentials file may name the stream to subscribe to.
+                if state.stream_id.is_none() {
+                    if let Some(stream_id) = credentials.stream_id().cloned() {
+                        if !stream_id.is_empty() {
+                            state.set_stream_id(&element, stream_id);
+                        }
+                    }
+                }
+                state.credentials = credentials;
+            }
+
+            if let Err(err) = src.maybe_init_session(&element) {
+                OpenTokSrc::connection_failed(element.upcast_ref(), &err.to_string());
+            }
+        });
+    }
+
+    fn from_element(element: &gst::Element) -> &Self {
+        OpenTokSrc::from_instance(element.downcast_ref::<super::OpenTokSrc>().unwrap())
+    }
 
-        pipe_opentok_to_gst_log(*CAT);
+    /// Stops the connection timeout. Being a live source, the element does
+    /// not wait for the session to change state, its pads only get data once
+    /// connected.
+    fn connected(element: &gst::Element) {
+        let src = OpenTokSrc::from_element(element);
+        src.startup.lock().unwrap().connected();
+    }
 
-        self.maybe_init_session(element).map_err(|error| {anyhow!(error)})
+    /// Posts an error message, READY→PAUSED then fails until the session
+    /// connects.
+    fn connection_failed(element: &gst::Element, error: &str) {
+        gst::element_error!(element, gst::ResourceError::OpenRead, ["{}", error]);
+        let src = OpenTokSrc::from_element(element);
+        update_session_state(element, &src.session_state, SessionInput::Failed);
+        src.startup.lock().unwrap().failed();
     }
 
     fn stop(&self, element: &super::OpenTokSrc) -> Result<(), gst::StateChangeError> {
         let bin = element.upcast_ref::<gst::Bin>();
-        if let Some(audio_appsrc) = bin.by_name("audio_appsrc") {
-            bin.set_locked_state(true);
-            audio_appsrc.set_state(gst::State::Null)?;
-            let _ = audio_appsrc.state(None);
-            bin.remove(&audio_appsrc).unwrap();
-            let audio_pad = element.static_pad("audio_stream").unwrap();
-            bin.set_locked_state(false);
-            element.remove_pad(&audio_pad).map_err(|error| {
-                gst_error!(CAT, obj: element, "Unable to remove audio pad: {:?}", error,);
-                gst::StateChangeError
-            })?;
+        for (appsrc_name, pad_name) in [
+            ("audio_appsrc", "audio_stream"),
+            ("signal_appsrc", "signal_stream"),
+        ] {
+            if let Some(appsrc) = bin.by_name(appsrc_name) {
+                bin.set_locked_state(true);
+                appsrc.set_state(gst::State::Null)?;
+                let _ = appsrc.state(None);
+                bin.remove(&appsrc).unwrap();
+                let pad = element.static_pad(pad_name).unwrap();
+                bin.set_locked_state(false);
+                element.remove_pad(&pad).map_err(|error| {
+                    gst_error!(CAT, obj: element, "Unable to remove {} pad: {:?}", pad_name, error);
+                    gst::StateChangeError
+                })?;
+            }
         }
 
-        for (_name, subscriber) in self.state.lock().unwrap().subscribers.drain() {
+        let (subscribers, session) = {
+            let mut state = self.state.lock().unwrap();
+            let subscribers: Vec<Subscriber> = state.subscribers.drain().map(|(_, s)| s).collect();
+            if let Some(id) = state.token_watch.take() {
+                id.unschedule();
+            }
+            state.stop_gaps();
+            state.awaiting_token = false;
+            (subscribers, state.session.take())
+        };
+
+        for subscriber in subscribers {
             bin.set_locked_state(true);
             subscriber.video_appsrc.set_state(gst::State::Null)?;
             let _ = subscriber.video_appsrc.state(None);
             bin.remove(&subscriber.video_appsrc).unwrap();
             bin.set_locked_state(false);
-            element.remove_pad(&subscriber.video_pad).map_err(|error| {
-                gst_error!(CAT, obj: element, "Unable to remove video pad: {:?}", error,);
-                gst::StateChangeError
-            })?;
+            if subscriber.video_pad.parent().is_some() {
+                element.remove_pad(&subscriber.video_pad).map_err(|error| {
+                    gst_error!(CAT, obj: element, "Unable to remove video pad: {:?}", error,);
+                    gst::StateChangeError
+                })?;
+            }
+        }
+
+        // Leave the session, it disconnects once no other element uses it.
+        // A new handle is created when going back to PAUSED.
+        if let Some(session) = session {
+            if let Err(err) = session.disconnect() {
+                gst_error!(CAT, obj: element, "Session disconnect error {}", err);
+            }
         }
+        backend().set_audio_samples_handler(None);
+        update_session_state(
+            element.upcast_ref(),
+            &self.session_state,
+            SessionInput::Stop,
+        );
 
         Ok(())
     }
 
     fn location(&self) -> Option<String> {
-        self.state
-            .lock()
-            .unwrap()
-            .credentials
-            .session_id()
-            .map(|id| format!("opentok://{}", id))
+        let state = self.state.lock().unwrap();
+        let base = OpenTokUri {
+            stream_id: state.stream_id.clone(),
+            ..state.uri.clone()
+        };
+        state.credentials.to_uri(&base).map(|uri| uri.to_uri())
     }
 
     fn proxy_pad_chain(
@@ -445,16 +697,28 @@ impl OpenTokSrc {
             .update_pad_flow(pad, res)
     }
 
-    fn init_audio(&self, element: &super::OpenTokSrc) -> Result<(), Error> {
+    /// Adds an appsrc named `name` producing `caps` to the bin, along with a
+    /// pad of `templ` exposing it. The pad answers caps queries with `caps`
+    /// as soon as it is added.
+    fn add_appsrc_pad(
+        &self,
+        element: &super::OpenTokSrc,
+        name: &'static str,
+        templ: &gst::PadTemplate,
+        caps: &gst::Caps,
+    ) -> Result<gst::Element, Error> {
         let bin = element
             .upcast_ref::<gst::Element>()
             .clone()
             .downcast::<gst::Bin>()
             .unwrap();
-        let appsrc = gst::ElementFactory::make("appsrc", Some("audio_appsrc"))
+        let appsrc = gst::ElementFactory::make("appsrc", Some(name))
             .map_err(|_| Error::MissingElement("appsrc"))?;
         appsrc.set_property("is-live", &true);
         appsrc.set_property("format", &gst::Format::Time);
+        appsrc.set_property("caps", caps);
+        let latency = self.state.lock().unwrap().sync.lock().unwrap().latency;
+        set_appsrc_latency(&appsrc, latency);
 
         bin.add(&appsrc).map_err(|_| Error::AddElement("appsrc"))?;
 
@@ -462,8 +726,7 @@ impl OpenTokSrc {
             .static_pad("src")
             .ok_or(Error::ElementPad("appsrc.src"))?;
 
-        let templ = &self.audio_src_pad_template;
-        let audio_pad = gst::GhostPad::builder_with_template(templ, Some(&templ.name()))
+        let pad = gst::GhostPad::builder_with_template(templ, Some(&templ.name()))
             .proxy_pad_chain_function({
                 let element_weak = element.downgrade();
                 move |pad, _parent, buffer| {
@@ -477,39 +740,90 @@ impl OpenTokSrc {
                 }
             })
             .build_with_target(&appsrc_src_pad)
-            .map_err(|_| Error::PadConstruction("audio", "ghost pad".into()))?;
+            .map_err(|_| Error::PadConstruction(name, "ghost pad".into()))?;
 
-        self.state.lock().unwrap().flow_combiner.add_pad(&audio_pad);
+        self.state.lock().unwrap().flow_combiner.add_pad(&pad);
 
-        audio_pad
-            .set_active(true)
-            .map_err(|_| Error::PadActivation("audio ghost pad"))?;
+        pad.set_active(true)
+            .map_err(|_| Error::PadActivation(name))?;
         appsrc_src_pad.sticky_events_foreach(|event| {
             use std::ops::ControlFlow;
-            audio_pad.push_event(event.clone());
+            pad.push_event(event.clone());
             ControlFlow::Continue(gst::EventForeachAction::Keep)
         });
 
-        if let Err(err) = bin.add_pad(&audio_pad) {
-            gst_error!(CAT, obj: element, "Failed to add audio pad {:?}", err)
+        if let Err(err) = bin.add_pad(&pad) {
+            gst_error!(CAT, obj: element, "Failed to add {} pad {:?}", templ.name(), err)
         }
         appsrc.sync_state_with_parent().unwrap();
 
+        Ok(appsrc)
+    }
+
+    fn init_audio(&self, element: &super::OpenTokSrc) -> Result<(), Error> {
+        let settings = self.state.lock().unwrap().audio_settings;
+        let appsrc = self.add_appsrc_pad(
+            element,
+            "audio_appsrc",
+            &self.audio_src_pad_template,
+            &audio_device_caps(&settings),
+        )?;
+        backend().set_audio_render_settings(&settings);
+
         let appsrc_state_holder = Arc::new(Mutex::new(AppSrcStateHolder {
             allocator: None,
             query_allocator: true,
+            timestamps: Default::default(),
+            sync: self.state.lock().unwrap().sync.clone(),
         }));
-        let audio_device = AudioDevice::get_instance();
-        audio_device
-            .lock()
-            .unwrap()
-            .set_on_audio_sample_callback(Box::new(move |sample| {
-                OpenTokSrc::push_audio_sample(&appsrc_state_holder, &appsrc, sample);
-            }));
+        backend().set_audio_samples_handler(Some(Arc::new(move |samples| {
+            OpenTokSrc::push_audio_sample(&appsrc_state_holder, &appsrc, samples);
+        })));
 
         Ok(())
     }
 
+    /// Applies `settings` to the audio device and to the caps of the audio
+    /// pad, if any. The SDK only reads them when the audio device starts.
+    fn set_audio_settings(&self, element: &super::OpenTokSrc, settings: AudioSettings) {
+        self.state.lock().unwrap().audio_settings = settings;
+        if let Some(appsrc) = element.by_name("audio_appsrc") {
+            gst_debug!(CAT, obj: element, "Rendering audio as {:?}", settings);
+            appsrc.set_property("caps", &audio_device_caps(&settings));
+            backend().set_audio_render_settings(&settings);
+        }
+    }
+
+    /// Applies `latency` to the appsrcs, and has the pipeline compute its
+    /// latency again.
+    fn set_latency(&self, element: &super::OpenTokSrc, latency: gst::ClockTime) {
+        let appsrcs = {
+            let state = self.state.lock().unwrap();
+            state.sync.lock().unwrap().latency = latency;
+            state
+                .subscribers
+                .values()
+                .map(|subscriber| subscriber.video_appsrc.clone())
+                .chain(element.by_name("audio_appsrc"))
+                .chain(element.by_name("signal_appsrc"))
+                .collect::<Vec<_>>()
+        };
+        for appsrc in appsrcs {
+            set_appsrc_latency(&appsrc, latency);
+        }
+        let _ = element.post_message(gst::message::Latency::builder().src(element).build());
+    }
+
+    fn init_signals(&self, element: &super::OpenTokSrc) -> Result<(), Error> {
+        self.add_appsrc_pad(
+            element,
+            "signal_appsrc",
+            &self.signal_src_pad_template,
+            &gst::Caps::new_simple(SIGNAL_CAPS, &[]),
+        )?;
+        Ok(())
+    }
+
     fn init_session(
         &self,
         element: &super::OpenTokSrc,
@@ -521,55 +835,141 @@ impl OpenTokSrc {
             return Ok(());
         }
 
-        self.init_audio(element)?;
+        // The audio pad survives sessions refused because of their token.
+        let video_only = self.state.lock().unwrap().uri.video_only;
+        if !video_only && element.by_name("audio_appsrc").is_none() {
+            self.init_audio(element)?;
+        }
+        let signal_stream = self.state.lock().unwrap().signal_stream;
+        if signal_stream && element.by_name("signal_appsrc").is_none() {
+            self.init_signals(element)?;
+        }
 
         let video_src_pad_template = &self.video_src_pad_template;
         let state = self.state.clone();
-        let session_callbacks = SessionCallbacks::builder()
-            .on_stream_received(clone!(
-                @weak element,
-                @weak state,
-                @weak video_src_pad_template,
-                => move |session, stream| {
-                    let has_video = stream.has_video();
+        let session_state = &self.session_state;
+        let handler: SessionEventHandler = Arc::new(clone!(
+            @weak element,
+            @weak state,
+            @weak session_state,
+            @weak video_src_pad_template,
+        => move |event| match event {
+            SessionEvent::StreamReceived(stream) => {
+                let has_video = stream.has_video;
                 let (video_appsrc, video_pad) =
-                    match state.lock().unwrap().stream_received(&element, &video_src_pad_template, session, stream) {
-                        Ok((appsrc, pad)) => (appsrc, pad),
+                    match state.lock().unwrap().stream_received(&element, &video_src_pad_template, stream) {
+                        Ok(Some((appsrc, pad))) => (appsrc, pad),
+                        Ok(None) => return,
                         Err(err) => {
                             gst_error!(CAT, obj: &element, "{}", err);
                             return;
                         }
                     };
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Subscribed);
 
-                if has_video {
+                let audio_only = state.lock().unwrap().uri.audio_only;
+                if has_video && !audio_only {
                     OpenTokSrc::enable_video(&element, &video_pad, &video_appsrc);
                 }
-            }))
-            .on_stream_dropped(clone!(
-                @weak element,
-                @weak state,
-            => move |_, stream| {
-                state.lock().unwrap().stream_dropped(&element, stream);
-            }))
-            .on_error(clone!(
-                @weak element
-            => move |_, error, _| {
-                gst::element_error!(&element, gst::ResourceError::Read, [error]);
-            }))
-            .build();
-        match Session::new(api_key, session_id, session_callbacks) {
-            Ok(session) => {
-                let result = session
-                    .connect(token)
-                    .map_err(|e| Error::Init(format!("Connection error {:?}", e)));
-                self.state.lock().unwrap().session = Some(session);
-                result
-            }
-            Err(err) => Err(Error::Init(format!(
-                "Failed to create OpenTok session `{:?}",
-                err
-            ))),
+            }
+            SessionEvent::StreamDropped(stream) => {
+                let idle = {
+                    let mut state = state.lock().unwrap();
+                    state.stream_dropped(&element, stream);
+                    state.subscribers.is_empty()
+                };
+                if idle {
+                    update_session_state(element.upcast_ref(), &session_state, SessionInput::Inactive);
+                }
+            }
+            SessionEvent::AuthenticationFailed(error) => {
+                gst_warning!(CAT, obj: &element, "Session authentication failed: {}", error);
+                let (session, token) = {
+                    let mut state = state.lock().unwrap();
+                    state.awaiting_token = true;
+                    (state.session.take(), state.credentials.token().cloned())
+                };
+                drop(session);
+                OpenTokSrc::from_element(element.upcast_ref()).startup.lock().unwrap().disconnected();
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Disconnected);
+                request_token(
+                    element.upcast_ref::<gst::Element>(),
+                    TokenRequestReason::AuthenticationFailed,
+                    token.as_ref().map(|token| token.expose()),
+                );
+            }
+            SessionEvent::Connected => {
+                gst_debug!(CAT, obj: &element, "Session connected");
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Connected);
+                OpenTokSrc::connected(element.upcast_ref());
+            }
+            // Errors not affecting the connection, such as refused signals.
+            SessionEvent::Error(error) if session_state.lock().unwrap().is_connected() => {
+                gst::element_warning!(element, gst::ResourceError::Read, ["{}", error]);
+            }
+            SessionEvent::Error(error) => {
+                OpenTokSrc::connection_failed(element.upcast_ref(), &error);
+            }
+            SessionEvent::Reconnecting { attempt, .. } => {
+                gst_warning!(CAT, obj: &element, "Session reconnecting, attempt {}", attempt);
+                state.lock().unwrap().start_gaps(&element);
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Reconnecting);
+            }
+            SessionEvent::Reconnected => {
+                gst_debug!(CAT, obj: &element, "Session reconnected");
+                state.lock().unwrap().stop_gaps();
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Reconnected);
+            }
+            SessionEvent::Disconnected => {
+                gst_debug!(CAT, obj: &element, "Session disconnected");
+                state.lock().unwrap().stop_gaps();
+                update_session_state(element.upcast_ref(), &session_state, SessionInput::Disconnected);
+            }
+            SessionEvent::SignalReceived(signal) => {
+                signal_received(element.upcast_ref(), &signal);
+                OpenTokSrc::push_signal(&element, &signal);
+            }
+            _ => {}
+        }));
+        let handler = with_session_messages(element.upcast_ref(), handler);
+
+        let shared = obtain_session(
+            element.upcast_ref(),
+            &self.shared_session,
+            api_key,
+            session_id,
+        )?;
+        let (own_streams, reconnect_policy) = {
+            let state = self.state.lock().unwrap();
+            (state.own_streams, state.reconnect_policy)
+        };
+        let session = shared.handle(handler, own_streams, reconnect_policy);
+        self.state.lock().unwrap().session = Some(session.clone());
+        update_session_state(
+            element.upcast_ref(),
+            &self.session_state,
+            SessionInput::Connect,
+        );
+        if let Err(err) = session.connect(token) {
+            self.state.lock().unwrap().session.take();
+            if token_is_expired(token) {
+                self.state.lock().unwrap().awaiting_token = true;
+                request_token(
+                    element.upcast_ref::<gst::Element>(),
+                    TokenRequestReason::AuthenticationFailed,
+                    Some(token),
+                );
+            }
+            return Err(err);
+        }
+
+        let token_watch = watch_token_expiry(element.upcast_ref::<gst::Element>(), token);
+        let mut state = self.state.lock().unwrap();
+        state.awaiting_token = false;
+        if let Some(id) = std::mem::replace(&mut state.token_watch, token_watch) {
+            id.unschedule();
         }
+        Ok(())
     }
 
     fn maybe_init_session(&self, element: &super::OpenTokSrc) -> Result<(), Error> {
@@ -577,7 +977,7 @@ impl OpenTokSrc {
         if let Some(ref api_key) = credentials.api_key() {
             if let Some(ref session_id) = credentials.session_id() {
                 if let Some(ref token) = credentials.token() {
-                    return self.init_session(element, api_key, session_id, token);
+                    return self.init_session(element, api_key, session_id, token.expose());
                 }
             }
         }
@@ -626,32 +1026,87 @@ impl OpenTokSrc {
     fn push_audio_sample(
         appsrc_state_holder: &Arc<Mutex<AppSrcStateHolder>>,
         appsrc: &gst::Element,
-        sample: AudioSample,
+        sample: AudioSamples,
     ) {
-        let gst_format = "S16LE";
-        let caps = gst::Caps::builder("audio/x-raw")
-            .field("format", gst_format.to_string())
-            .field("layout", "interleaved")
-            .field("rate", sample.sampling_rate)
-            .field("channels", sample.number_of_channels)
-            .build();
+        // The SDK may render another format than the configured one.
+        let caps = audio_device_caps(&AudioSettings {
+            rate: sample.rate,
+            channels: sample.channels,
+        });
+        let frames = sample.data.len() as u64 / sample.channels.max(1) as u64;
+        let duration = gst::ClockTime::SECOND.mul_div_floor(frames, sample.rate.max(1) as u64);
+        // The SDK mixes the audio of all the streams without any timing
+        // information, it can only be stamped when it arrives.
+        let timing = appsrc.current_running_time().map(|arrival| FrameTiming {
+            pts: arrival,
+            duration,
+            discont: false,
+        });
         AppSrcStateHolder::push_sample(
             appsrc_state_holder,
             &caps,
             appsrc,
-            sample.data.0.as_byte_slice(),
+            sample.data.as_byte_slice(),
+            timing,
         );
     }
 
+    /// Pushes `signal` as JSON on the `signal_stream` pad, if any.
+    fn push_signal(element: &super::OpenTokSrc, signal: &Signal) {
+        let appsrc = match element.by_name("signal_appsrc") {
+            Some(appsrc) => appsrc.downcast::<gst_app::AppSrc>().unwrap(),
+            None => return,
+        };
+
+        let mut buffer = gst::Buffer::from_slice(signal_to_json(signal).into_bytes());
+        if let Some(timestamp) = appsrc.current_running_time() {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(Some(timestamp));
+            buffer.set_dts(Some(timestamp));
+        }
+        if let Err(err) = appsrc.push_buffer(buffer) {
+            gst_error!(CAT, obj: &appsrc, "Failed to push signal: {:?}", err);
+        }
+    }
+
+    fn push_gaps(element: &super::OpenTokSrc) {
+        let appsrcs = {
+            let imp = OpenTokSrc::from_instance(element);
+            let state = imp.state.lock().unwrap();
+            state
+                .subscribers
+                .values()
+                .map(|subscriber| subscriber.video_appsrc.clone())
+                .chain(element.by_name("audio_appsrc"))
+                .collect::<Vec<_>>()
+        };
+
+        for appsrc in appsrcs {
+            let pad = appsrc.static_pad("src").unwrap();
+            // Nothing to fill before the stream started.
+            if pad.sticky_event(gst::EventType::Segment, 0).is_none() {
+                continue;
+            }
+            if let Some(running_time) = appsrc.current_running_time() {
+                gst_trace!(CAT, obj: &pad, "Pushing GAP at {}", running_time);
+                pad.push_event(
+                    gst::event::Gap::builder(running_time)
+                        .duration(GAP_INTERVAL)
+                        .build(),
+                );
+            }
+        }
+    }
+
     fn push_video_frame(
         appsrc_state_holder: &Arc<Mutex<AppSrcStateHolder>>,
         appsrc: &gst::Element,
-        frame: VideoFrame,
+        frame: VideoFrameData,
     ) {
-        let data = frame.get_buffer().unwrap();
-        let format = frame.get_format().unwrap();
-        let width = frame.get_width().unwrap() as i32;
-        let height = frame.get_height().unwrap() as i32;
+        let data = &frame.data;
+        let format = frame.format;
+        let width = frame.width as i32;
+        let height = frame.height as i32;
         gst_trace!(
             CAT,
             obj: appsrc,
@@ -660,14 +1115,33 @@ impl OpenTokSrc {
             height
         );
 
-        let caps = gst::Caps::builder("video/x-raw")
+        // MJPEG frames are described as encoded video.
+        let builder = match format {
+            gst_video::VideoFormat::Encoded => gst::Caps::builder("image/jpeg"),
+            _ => gst::Caps::builder("video/x-raw").field("format", format.to_str()),
+        };
+        let timing = appsrc.current_running_time().map(|arrival| {
+            let mut holder = appsrc_state_holder.lock().unwrap();
+            let latency = holder.sync.lock().unwrap().latency;
+            holder.timestamps.map(frame.timestamp, arrival, latency)
+        });
+        // The SDK adapts the frame rate to the network conditions, only its
+        // highest measured value is known.
+        let mut builder = builder
             .field("width", width)
             .field("height", height)
-            .field("framerate", gst::Fraction::new(30, 1))
-            .field("format", format!("{}", gst_from_otc_format(format)))
-            .build();
+            .field("framerate", gst::Fraction::new(0, 1));
+        if let Some(max_framerate) = appsrc_state_holder
+            .lock()
+            .unwrap()
+            .timestamps
+            .max_framerate()
+        {
+            builder = builder.field("max-framerate", max_framerate);
+        }
+        let caps = builder.build();
 
-        AppSrcStateHolder::push_sample(appsrc_state_holder, &caps, appsrc, data);
+        AppSrcStateHolder::push_sample(appsrc_state_holder, &caps, appsrc, data, timing);
     }
 }
 
@@ -681,17 +1155,31 @@ impl ObjectSubclass for OpenTokSrc {
     fn with_class(klass: &Self::Class) -> Self {
         let video_src_pad_template = klass.pad_template("video_stream_%u").unwrap();
         let audio_src_pad_template = klass.pad_template("audio_stream").unwrap();
+        let signal_src_pad_template = klass.pad_template("signal_stream").unwrap();
         let state = State {
             credentials: Default::default(),
             session: Default::default(),
+            own_streams: false,
             stream_id: Default::default(),
+            uri: Default::default(),
             subscribers: Default::default(),
             flow_combiner: gst_base::UniqueFlowCombiner::new(),
+            token_watch: None,
+            awaiting_token: false,
+            reconnect_policy: Default::default(),
+            gaps: None,
+            signal_stream: false,
+            audio_settings: Default::default(),
+            sync: Default::default(),
         };
         Self {
             state: Arc::new(Mutex::new(state)),
+            shared_session: Default::default(),
+            startup: Default::default(),
+            session_state: Default::default(),
             video_src_pad_template,
             audio_src_pad_template,
+            signal_src_pad_template,
         }
     }
 }
@@ -728,6 +1216,45 @@ impl ObjectImpl for OpenTokSrc {
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecString::new(
+                    "api-secret",
+                    "ApiSecret",
+                    "OpenTok API secret, used to generate a token when none is set",
+                    None,
+                    glib::ParamFlags::WRITABLE,
+                ),
+                glib::ParamSpecString::new(
+                    "role",
+                    "Role",
+                    "Role of generated tokens (subscriber, publisher or moderator)",
+                    Some("publisher"),
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt64::new(
+                    "expire-time",
+                    "ExpireTime",
+                    "Expiry time of generated tokens, in seconds since the Unix epoch (0 = 24 hours after generation)",
+                    0,
+                    u64::MAX,
+                    0,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecString::new(
+                    "connection-data",
+                    "ConnectionData",
+                    "Connection data of generated tokens",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecUInt64::new(
+                    "token-expire-time",
+                    "TokenExpireTime",
+                    "Expiry time of the session token, in seconds since the Unix epoch (0 if unknown)",
+                    0,
+                    u64::MAX,
+                    0,
+                    glib::ParamFlags::READABLE,
+                ),
                 glib::ParamSpecString::new(
                     "stream-id",
                     "StreamId",
@@ -735,10 +1262,17 @@ impl ObjectImpl for OpenTokSrc {
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecString::new(
+                    "credentials-file",
+                    "CredentialsFile",
+                    "YAML or JSON file holding the apiKey, sessionId, token and optional streamId of the session",
+                    None,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecString::new(
                     "location",
                     "Location",
-                    "OpenTok session location (i.e. opentok://<session id>/key=<api key>&token=<token>)",
+                    "OpenTok session location (i.e. opentok://<session id>[/<stream id>]?key=<api key>&token=<token>[&name=<stream name>][&audio-only=true|&video-only=true][&resolution=<width>x<height>])",
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
@@ -749,6 +1283,20 @@ impl ObjectImpl for OpenTokSrc {
                     None,
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecBoolean::new(
+                    "subscribe-to-own-streams",
+                    "Subscribe to own streams",
+                    "Also subscribe to the streams published by the opentoksink elements sharing the session",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
+                glib::ParamSpecBoolean::new(
+                    "signal-stream",
+                    "Signal stream",
+                    "Expose the received signals as application/x-json buffers on a signal_stream pad",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
                 glib::ParamSpecBoolean::new(
                     "is-live",
                     "Is Live",
@@ -756,7 +1304,23 @@ impl ObjectImpl for OpenTokSrc {
                     true,
                     glib::ParamFlags::READABLE,
                 ),
+                glib::ParamSpecUInt::new(
+                    "latency",
+                    "Latency",
+                    "Time allowed to buffer the received streams, in milliseconds",
+                    0,
+                    u32::MAX,
+                    DEFAULT_LATENCY.mseconds() as u32,
+                    glib::ParamFlags::READWRITE,
+                ),
+                timeout_property(),
+                session_state_property(),
             ]
+            .into_iter()
+            .chain(room_info_properties().iter().cloned())
+            .chain(reconnect_properties().iter().cloned())
+            .chain(audio_properties().iter().cloned())
+            .collect()
         });
 
         PROPERTIES.as_ref()
@@ -786,6 +1350,25 @@ impl ObjectImpl for OpenTokSrc {
                     log_if_err_fn(state.credentials.set_session_id(session_id));
                 }
             }
+            "api-secret" => {
+                if let Ok(api_secret) = value.get::<String>() {
+                    log_if_err_fn(state.credentials.set_api_secret(api_secret));
+                }
+            }
+            "role" => {
+                let role = value.get::<String>().expect("expected a string");
+                log_if_err_fn(state.credentials.set_role(&role));
+            }
+            "expire-time" => {
+                state
+                    .credentials
+                    .set_expire_time(value.get::<u64>().expect("expected a u64"));
+            }
+            "connection-data" => {
+                state
+                    .credentials
+                    .set_connection_data(value.get::<Option<String>>().expect("expected a string"));
+            }
             "stream-id" => {
                 if let Ok(stream_id) = value.get::<String>() {
                     state.set_stream_id(obj, stream_id);
@@ -793,7 +1376,22 @@ impl ObjectImpl for OpenTokSrc {
             }
             "token" => {
                 if let Ok(token) = value.get::<String>() {
-                    log_if_err_fn(state.credentials.set_token(token));
+                    log_if_err_fn(state.credentials.set_token(token.clone()));
+                    if let Some(session) = state.session.clone() {
+                        gst_debug!(CAT, obj: obj, "Updating the session token");
+                        session.update_token(&token);
+                        let token_watch =
+                            watch_token_expiry(obj.upcast_ref::<gst::Element>(), &token);
+                        if let Some(id) = std::mem::replace(&mut state.token_watch, token_watch) {
+                            id.unschedule();
+                        }
+                    }
+                }
+                if state.awaiting_token {
+                    drop(state);
+                    if let Err(err) = self.maybe_init_session(obj) {
+                        gst_error!(CAT, obj: obj, "Failed to initialize OpenTok session: {:?}", err);
+                    }
                 }
             }
             "location" => {
@@ -802,8 +1400,52 @@ impl ObjectImpl for OpenTokSrc {
                     gst_error!(CAT, obj: obj, "Failed to set location: {:?}", e)
                 }
             }
+            "credentials-file" => {
+                let path = value.get::<Option<String>>().expect("expected a string");
+                log_if_err_fn(
+                    state
+                        .credentials
+                        .set_credentials_file(path.map(PathBuf::from)),
+                );
+            }
             "demo-room-uri" => {
-                log_if_err_fn(state.credentials.set_room_uri(value.get::<String>().expect("expected a string")));
+                log_if_err_fn(
+                    state
+                        .credentials
+                        .set_room_uri(value.get::<String>().expect("expected a string")),
+                );
+            }
+            name if name.starts_with("room-info-") => {
+                set_room_info_property(state.credentials.room_info_mut(), name, value);
+            }
+            "subscribe-to-own-streams" => {
+                state.own_streams = value.get::<bool>().expect("expected a boolean");
+            }
+            "signal-stream" => {
+                state.signal_stream = value.get::<bool>().expect("expected a boolean");
+            }
+            "latency" => {
+                let latency = value.get::<u32>().expect("expected a u32");
+                drop(state);
+                self.set_latency(obj, gst::ClockTime::from_mseconds(latency as u64));
+            }
+            name if name.starts_with("reconnect-") => {
+                set_reconnect_property(&mut state.reconnect_policy, name, value);
+            }
+            "timeout" => {
+                self.startup
+                    .lock()
+                    .unwrap()
+                    .set_timeout(value.get::<u32>().expect("expected a u32"));
+            }
+            name if is_audio_property(name) && !audio_settings_mutable(obj.upcast_ref()) => {
+                gst_warning!(CAT, obj: obj, "Cannot change {} once the audio device started", name);
+            }
+            name if is_audio_property(name) => {
+                let mut settings = state.audio_settings;
+                set_audio_property(&mut settings, name, value);
+                drop(state);
+                self.set_audio_settings(obj, settings);
             }
             _ => unimplemented!(),
         }
@@ -829,23 +1471,95 @@ impl ObjectImpl for OpenTokSrc {
             }
             "token" => {
                 let state = self.state.lock().unwrap();
-                state.credentials.token().to_value()
+                state
+                    .credentials
+                    .token()
+                    .map(|token| token.expose())
+                    .to_value()
+            }
+            "token-expire-time" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .credentials
+                    .token_expire_time()
+                    .unwrap_or(0)
+                    .to_value()
+            }
+            "role" => {
+                let state = self.state.lock().unwrap();
+                state.credentials.role().as_str().to_value()
+            }
+            "expire-time" => {
+                let state = self.state.lock().unwrap();
+                state.credentials.expire_time().to_value()
+            }
+            "connection-data" => {
+                let state = self.state.lock().unwrap();
+                state.credentials.connection_data().to_value()
+            }
+            "credentials-file" => {
+                let state = self.state.lock().unwrap();
+                state
+                    .credentials
+                    .credentials_file()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .to_value()
             }
             "location" => self.location().to_value(),
-            "demo-room-uri" => {
-                self.state.lock().unwrap().credentials.room_uri().map(|url| url.as_str()).to_value()
+            "demo-room-uri" => self
+                .state
+                .lock()
+                .unwrap()
+                .credentials
+                .room_uri()
+                .map(|url| url.as_str())
+                .to_value(),
+            "subscribe-to-own-streams" => self.state.lock().unwrap().own_streams.to_value(),
+            "signal-stream" => self.state.lock().unwrap().signal_stream.to_value(),
+            "latency" => {
+                let latency = self.state.lock().unwrap().sync.lock().unwrap().latency;
+                (latency.mseconds() as u32).to_value()
+            }
+            name if name.starts_with("reconnect-") => {
+                reconnect_property(&self.state.lock().unwrap().reconnect_policy, name)
             }
             "is-live" => true.to_value(),
+            "timeout" => self.startup.lock().unwrap().timeout().to_value(),
+            "session-state" => self.session_state.lock().unwrap().state().to_value(),
+            name if name.starts_with("room-info-") => {
+                room_info_property(self.state.lock().unwrap().credentials.room_info(), name)
+            }
+            name if is_audio_property(name) => {
+                audio_property(&self.state.lock().unwrap().audio_settings, name)
+            }
             _ => unimplemented!(),
         }
     }
+
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: Lazy<Vec<glib::subclass::Signal>> = Lazy::new(|| {
+            vec![
+                request_token_signal(),
+                send_signal_signal(|element| {
+                    OpenTokSrc::from_element(element)
+                        .state
+                        .lock()
+                        .unwrap()
+                        .session
+                        .clone()
+                }),
+                signal_received_signal(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
 }
 
 impl GstObjectImpl for OpenTokSrc {}
 
 impl ElementImpl for OpenTokSrc {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
-        init();
         static ELEMENT_METADATA: Lazy<gst::subclass::ElementMetadata> = Lazy::new(|| {
             gst::subclass::ElementMetadata::new(
                 "OpenTok Source",
@@ -860,7 +1574,7 @@ impl ElementImpl for OpenTokSrc {
 
     fn pad_templates() -> &'static [gst::PadTemplate] {
         static PAD_TEMPLATES: Lazy<Vec<gst::PadTemplate>> = Lazy::new(|| {
-            let (video_caps, audio_caps) = caps();
+            let (video_caps, audio_caps) = received_caps();
 
             let video_src_pad_template = gst::PadTemplate::new(
                 "video_stream_%u",
@@ -880,11 +1594,31 @@ impl ElementImpl for OpenTokSrc {
             )
             .unwrap();
 
-            vec![video_src_pad_template, audio_src_pad_template]
+            let signal_src_pad_template = gst::PadTemplate::new(
+                "signal_stream",
+                gst::PadDirection::Src,
+                gst::PadPresence::Sometimes,
+                &gst::Caps::new_simple(SIGNAL_CAPS, &[]),
+            )
+            .unwrap();
+
+            vec![
+                video_src_pad_template,
+                audio_src_pad_template,
+                signal_src_pad_template,
+            ]
         });
         PAD_TEMPLATES.as_ref()
     }
 
+    fn set_context(&self, element: &Self::Type, context: &gst::Context) {
+        if let Some(session) = SharedSession::from_context(context) {
+            gst_debug!(CAT, obj: element, "Got shared session {}", session.session_id());
+            *self.shared_session.lock().unwrap() = Some(session);
+        }
+        self.parent_set_context(element, context);
+    }
+
     fn change_state(
         &self,
         element: &Self::Type,
@@ -893,10 +1627,23 @@ impl ElementImpl for OpenTokSrc {
         gst_debug!(CAT, obj: element, "Changing state {:?}", transition);
 
         if transition == gst::StateChange::NullToReady {
-            self.start(element).map_err(|error| {
-                gst_error!(CAT, "Error changing state: {:?}", error);
+            self.start(element);
+        }
 
-                gst::StateChangeError
+        if transition == gst::StateChange::ReadyToPaused {
+            // The session is dropped when going back to READY.
+            if let Err(error) = self.maybe_init_session(element) {
+                OpenTokSrc::connection_failed(element.upcast_ref(), &error.to_string());
+            }
+            // Live sources do not preroll, the session keeps connecting in
+            // the background until the timeout.
+            let mut startup = self.startup.lock().unwrap();
+            startup.arm(element.upcast_ref(), |element| {
+                OpenTokSrc::connection_failed(element, "Timed out connecting the session");
+            });
+            startup.begin_live().map_err(|err| {
+                gst_error!(CAT, obj: element, "The session failed to connect");
+                err
             })?;
         }
 
@@ -907,9 +1654,19 @@ impl ElementImpl for OpenTokSrc {
         }
 
         if transition == gst::StateChange::PausedToReady {
+            self.startup.lock().unwrap().reset();
             self.stop(element)?;
         }
 
+        if transition == gst::StateChange::ReadyToNull {
+            self.startup.lock().unwrap().reset();
+            update_session_state(
+                element.upcast_ref(),
+                &self.session_state,
+                SessionInput::Stop,
+            );
+        }
+
         Ok(success)
     }
 }
@@ -0,0 +1,219 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+// Not every test crate uses every helper.
+#![allow(dead_code)]
+
+use gst::prelude::*;
+use gstopentok::common::backend::{set_backend, BACKEND_ENV_VAR};
+use gstopentok::common::mock::MockBackend;
+use once_cell::sync::Lazy;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const VIDEO_CAPS: &str = "video/x-raw,format=I420,width=320,height=240,framerate=30/1";
+
+/// Backend shared by all the elements created in the test process.
+pub static MOCK: Lazy<Arc<MockBackend>> = Lazy::new(|| Arc::new(MockBackend::new()));
+
+pub fn init() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+
+        // The remote elements spawn gst-opentok-helper from $PATH, and the
+        // helper picks its backend from the environment.
+        std::env::set_var(BACKEND_ENV_VAR, "mock");
+        let helper_dir = Path::new(env!("CARGO_BIN_EXE_gst-opentok-helper"))
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let mut paths = vec![helper_dir];
+        if let Some(path) = std::env::var_os("PATH") {
+            paths.extend(std::env::split_paths(&path));
+        }
+        std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+
+        set_backend(MOCK.clone());
+        gstopentok::plugin_register_static().unwrap();
+    });
+}
+
+/// Each test gets its own room, so that tests can run in parallel.
+pub fn new_session_id() -> String {
+    format!("session-{}", Uuid::new_v4())
+}
+
+pub fn location(scheme: &str, session_id: &str) -> String {
+    format!("{}://{}?key=mock-key&token=mock-token", scheme, session_id)
+}
+
+/// Polls `condition` until it holds or `TIMEOUT` expires.
+pub fn wait_until<F: Fn() -> bool>(condition: F) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+/// `videotestsrc ! capsfilter ! <sink>`, with `sink` already configured.
+pub fn publisher_pipeline(sink: &gst::Element) -> gst::Pipeline {
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("videotestsrc", None).unwrap();
+    src.set_property("is-live", true);
+    let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+    capsfilter.set_property("caps", &gst::Caps::from_str(VIDEO_CAPS).unwrap());
+    pipeline.add_many(&[&src, &capsfilter, sink]).unwrap();
+    gst::Element::link_many(&[&src, &capsfilter, sink]).unwrap();
+    pipeline
+}
+
+pub fn assert_no_error(pipeline: &gst::Pipeline) {
+    let bus = pipeline.bus().unwrap();
+    if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+        panic!("Unexpected error message {:?}", msg);
+    }
+}
+
+pub fn set_state_and_wait(pipeline: &gst::Pipeline, state: gst::State) {
+    pipeline.set_state(state).unwrap();
+    let (res, current, _) = pipeline.state(gst::ClockTime::from_seconds(TIMEOUT.as_secs()));
+    res.unwrap();
+    assert_eq!(current, state);
+}
+
+/// A request received by a [`RoomServer`]: its path and lowercased headers.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Local HTTP server standing in for a room info service. It answers every
+/// request with `body`, except the first `failures` ones which get a 503.
+pub struct RoomServer {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl RoomServer {
+    pub fn start(body: impl Into<String>, failures: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::<Request>::new()));
+        let body = body.into();
+
+        std::thread::spawn({
+            let requests = requests.clone();
+            move || {
+                for mut stream in listener.incoming().flatten() {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    let _ = reader.read_line(&mut line);
+                    let path = line
+                        .split_whitespace()
+                        .nth(1)
+                        .unwrap_or_default()
+                        .to_string();
+                    let mut headers = vec![];
+                    loop {
+                        line.clear();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                        }
+                    }
+
+                    let mut requests = requests.lock().unwrap();
+                    requests.push(Request { path, headers });
+                    let (status, body) = if requests.len() <= failures {
+                        ("503 Service Unavailable", "")
+                    } else {
+                        ("200 OK", body.as_str())
+                    };
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                }
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Base URL, without trailing slash.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Waits for an error message on the bus of `pipeline` and returns it.
+pub fn wait_for_error(pipeline: &gst::Pipeline) -> glib::Error {
+    let bus = pipeline.bus().unwrap();
+    let msg = bus
+        .timed_pop_filtered(
+            gst::ClockTime::from_seconds(TIMEOUT.as_secs()),
+            &[gst::MessageType::Error],
+        )
+        .expect("No error message");
+    match msg.view() {
+        gst::MessageView::Error(err) => err.error(),
+        _ => unreachable!(),
+    }
+}
+
+/// Waits for the element message named `name` on the bus of `pipeline`,
+/// skipping the other ones.
+pub fn wait_for_message(pipeline: &gst::Pipeline, name: &str) -> gst::Structure {
+    let bus = pipeline.bus().unwrap();
+    loop {
+        let msg = bus
+            .timed_pop_filtered(
+                gst::ClockTime::from_seconds(TIMEOUT.as_secs()),
+                &[gst::MessageType::Element],
+            )
+            .unwrap_or_else(|| panic!("No {} message", name));
+        if let Some(structure) = msg.structure() {
+            if structure.name() == name {
+                return structure.to_owned();
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::{Credentials, API_KEY_ENV_VAR, SESSION_ID_ENV_VAR, TOKEN_ENV_VAR};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+mod common;
+use common::*;
+
+/// Writes `contents` to a new temporary file with the given extension.
+fn credentials_file(extension: &str, contents: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "opentok-credentials-{}.{}",
+        Uuid::new_v4(),
+        extension
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn json_and_yaml_files() {
+    let json = credentials_file(
+        "json",
+        r#"{"apiKey": "json-key", "sessionId": "json-session", "token": "json-token", "streamId": "json-stream"}"#,
+    );
+    let mut credentials = Credentials::default();
+    credentials
+        .set_credentials_file(Some(json.clone()))
+        .unwrap();
+    credentials.resolve().unwrap();
+    assert!(credentials.is_complete());
+    assert_eq!(credentials.api_key().unwrap(), "json-key");
+    assert_eq!(credentials.session_id().unwrap(), "json-session");
+    assert_eq!(credentials.token().unwrap().expose(), "json-token");
+    assert_eq!(credentials.stream_id().unwrap(), "json-stream");
+
+    let yaml = credentials_file(
+        "yaml",
+        "apiKey: yaml-key\nsessionId: yaml-session\ntoken: yaml-token\n",
+    );
+    let mut credentials = Credentials::default();
+    // Explicitly set fields take precedence over the file.
+    credentials.set_api_key("explicit-key".into()).unwrap();
+    credentials
+        .set_credentials_file(Some(yaml.clone()))
+        .unwrap();
+    credentials.resolve().unwrap();
+    assert_eq!(credentials.api_key().unwrap(), "explicit-key");
+    assert_eq!(credentials.session_id().unwrap(), "yaml-session");
+    assert_eq!(credentials.token().unwrap().expose(), "yaml-token");
+    assert!(credentials.stream_id().is_none());
+
+    let mut credentials = Credentials::default();
+    credentials
+        .set_credentials_file(Some("/nonexistent/credentials.yaml".into()))
+        .unwrap();
+    assert!(credentials.resolve().is_err());
+
+    let _ = std::fs::remove_file(json);
+    let _ = std::fs::remove_file(yaml);
+}
+
+#[test]
+fn environment_fallback() {
+    std::env::set_var(API_KEY_ENV_VAR, "env-key");
+    std::env::set_var(SESSION_ID_ENV_VAR, "env-session");
+    std::env::set_var(TOKEN_ENV_VAR, "env-token");
+
+    let mut credentials = Credentials::default();
+    credentials
+        .set_session_id("explicit-session".into())
+        .unwrap();
+    assert!(!credentials.is_complete());
+    credentials.resolve().unwrap();
+    assert!(credentials.is_complete());
+    assert_eq!(credentials.api_key().unwrap(), "env-key");
+    assert_eq!(credentials.session_id().unwrap(), "explicit-session");
+    assert_eq!(credentials.token().unwrap().expose(), "env-token");
+
+    std::env::remove_var(API_KEY_ENV_VAR);
+    std::env::remove_var(SESSION_ID_ENV_VAR);
+    std::env::remove_var(TOKEN_ENV_VAR);
+}
+
+#[test]
+fn sink_credentials_file() {
+    init();
+
+    let session_id = new_session_id();
+    let path = credentials_file(
+        "json",
+        &format!(
+            r#"{{"apiKey": "mock-key", "sessionId": "{}", "token": "mock-token"}}"#,
+            session_id
+        ),
+    );
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("credentials-file", path.to_str().unwrap());
+    assert_eq!(
+        sink.property::<Option<String>>("credentials-file")
+            .as_deref(),
+        path.to_str()
+    );
+
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert_no_error(&pipeline);
+    let _ = std::fs::remove_file(path);
+}
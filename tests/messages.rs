@@ -0,0 +1,79 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn room_activity_messages() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+    src.connect_pad_added(|src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    wait_for_message(&pipeline, "opentok/connected");
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    let created = wait_for_message(&publisher, "opentok/stream-created");
+    let stream_id = created.get::<String>("stream-id").unwrap();
+    let connection_id = created.get::<String>("connection-id").unwrap();
+
+    let connection = wait_for_message(&pipeline, "opentok/connection-created");
+    assert_eq!(
+        connection.get::<String>("connection-id").unwrap(),
+        connection_id
+    );
+    assert!(connection.get::<i64>("creation-time").unwrap() > 0);
+    let received = wait_for_message(&pipeline, "opentok/stream-received");
+    assert_eq!(received.get::<String>("stream-id").unwrap(), stream_id);
+    assert!(received.get::<bool>("has-video").unwrap());
+    let subscriber = wait_for_message(&pipeline, "opentok/subscriber-connected");
+    assert_eq!(subscriber.get::<String>("stream-id").unwrap(), stream_id);
+
+    let archive_id = MOCK.start_archive(&session_id, "archive");
+    let started = wait_for_message(&pipeline, "opentok/archive-started");
+    assert_eq!(started.get::<String>("archive-id").unwrap(), archive_id);
+    assert_eq!(started.get::<String>("name").unwrap(), "archive");
+    MOCK.stop_archive(&session_id, &archive_id);
+    let stopped = wait_for_message(&pipeline, "opentok/archive-stopped");
+    assert_eq!(stopped.get::<String>("archive-id").unwrap(), archive_id);
+
+    set_state_and_wait(&publisher, gst::State::Null);
+    let dropped = wait_for_message(&pipeline, "opentok/stream-dropped");
+    assert_eq!(dropped.get::<String>("stream-id").unwrap(), stream_id);
+    let dropped = wait_for_message(&pipeline, "opentok/connection-dropped");
+    assert_eq!(
+        dropped.get::<String>("connection-id").unwrap(),
+        connection_id
+    );
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
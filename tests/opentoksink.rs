@@ -0,0 +1,636 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::publisher::{VideoContentHint, VideoType};
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+#[test]
+fn request_and_release_pads() {
+    init();
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+
+    let video_pad = sink.request_pad_simple("video_sink").unwrap();
+    assert_eq!(video_pad.name(), "video_sink");
+    let audio_pad = sink.request_pad_simple("audio_sink").unwrap();
+    assert_eq!(audio_pad.name(), "audio_sink");
+    assert_eq!(sink.num_sink_pads(), 2);
+
+    // The first publisher has a single pad per media type.
+    assert!(sink.request_pad_simple("video_sink").is_none());
+    assert!(sink.request_pad_simple("audio_sink").is_none());
+    assert!(sink.request_pad_simple("video_sink_0").is_none());
+
+    // More video pads go to new publishers, but the audio device is shared.
+    let second_video_pad = sink.request_pad_simple("video_sink_%u").unwrap();
+    assert_eq!(second_video_pad.name(), "video_sink_1");
+    let third_video_pad = sink.request_pad_simple("video_sink_3").unwrap();
+    assert_eq!(third_video_pad.name(), "video_sink_3");
+    assert!(sink.request_pad_simple("audio_sink_%u").is_none());
+    assert_eq!(sink.num_sink_pads(), 4);
+
+    sink.release_request_pad(&video_pad);
+    sink.release_request_pad(&audio_pad);
+    sink.release_request_pad(&second_video_pad);
+    sink.release_request_pad(&third_video_pad);
+    assert_eq!(sink.num_sink_pads(), 0);
+
+    // Released pads can be requested again.
+    let video_pad = sink.request_pad_simple("video_sink").unwrap();
+    sink.release_request_pad(&video_pad);
+    assert_eq!(sink.num_sink_pads(), 0);
+}
+
+#[test]
+fn published_stream_signal() {
+    init();
+
+    let session_id = new_session_id();
+    let mut h = gst_check::Harness::with_padnames("opentoksink", Some("video_sink"), None);
+    h.use_systemclock();
+
+    let sink = h.element().unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    sink.connect("published-stream", false, move |args| {
+        let stream_id = args[1].get::<String>().unwrap();
+        let url = args[2].get::<String>().unwrap();
+        let _ = sender.lock().unwrap().send((stream_id, url));
+        None
+    });
+
+    h.set_src_caps_str(VIDEO_CAPS);
+    h.play();
+    let buffer = gst::Buffer::with_size(320 * 240 * 3 / 2).unwrap();
+    h.push(buffer).unwrap();
+
+    let (stream_id, url) = receiver.recv_timeout(TIMEOUT).unwrap();
+    let streams = MOCK.streams(&session_id);
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].id, stream_id);
+    assert!(url.starts_with(&format!("opentok://{}/{}?", session_id, stream_id)));
+    assert_eq!(sink.property::<String>("stream-id"), stream_id);
+}
+
+#[test]
+fn multiple_publishers() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    sink.connect("published-stream", false, move |args| {
+        let stream_id = args[1].get::<String>().unwrap();
+        let group = args[3].get::<u32>().unwrap();
+        let _ = sender.lock().unwrap().send((stream_id, group));
+        None
+    });
+
+    let pipeline = publisher_pipeline(&sink);
+    let src = gst::ElementFactory::make("videotestsrc", None).unwrap();
+    src.set_property("is-live", true);
+    let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+    capsfilter.set_property("caps", &gst::Caps::from_str(VIDEO_CAPS).unwrap());
+    pipeline.add_many(&[&src, &capsfilter]).unwrap();
+    src.link(&capsfilter).unwrap();
+    let pad = sink.request_pad_simple("video_sink_%u").unwrap();
+    assert_eq!(pad.name(), "video_sink_1");
+    capsfilter.static_pad("src").unwrap().link(&pad).unwrap();
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let mut published = vec![
+        receiver.recv_timeout(TIMEOUT).unwrap(),
+        receiver.recv_timeout(TIMEOUT).unwrap(),
+    ];
+    published.sort_by_key(|(_, group)| *group);
+    assert_eq!(published[0].1, 0);
+    assert_eq!(published[1].1, 1);
+    assert_ne!(published[0].0, published[1].0);
+
+    let streams = MOCK.streams(&session_id);
+    assert_eq!(streams.len(), 2);
+    assert!(streams
+        .iter()
+        .all(|stream| stream.has_video && !stream.has_audio));
+    assert!(streams.iter().any(|stream| stream.name == "opentoksink"));
+    assert!(streams.iter().any(|stream| stream.name == "opentoksink-1"));
+    assert_eq!(sink.property::<String>("stream-id"), published[0].0);
+
+    // Releasing the pads of a publisher unpublishes its stream only.
+    for element in [&src, &capsfilter] {
+        element.set_state(gst::State::Null).unwrap();
+        pipeline.remove(element).unwrap();
+    }
+    sink.release_request_pad(&pad);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    assert_eq!(MOCK.streams(&session_id)[0].id, published[0].0);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn publisher_settings() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    sink.set_property("publisher-name", "camera");
+    sink.set_property("max-video-bitrate", 500_000u32);
+    sink.set_property("preferred-framerate", gst::Fraction::new(15, 1));
+    sink.set_property("publish-audio", false);
+
+    let pipeline = publisher_pipeline(&sink);
+    let audio_src = gst::ElementFactory::make("audiotestsrc", None).unwrap();
+    audio_src.set_property("is-live", true);
+    pipeline.add(&audio_src).unwrap();
+    audio_src.link(&sink).unwrap();
+
+    // Pad properties default to the element ones, and take precedence.
+    let pad = sink.static_pad("video_sink_0").unwrap();
+    assert_eq!(pad.property::<u32>("max-video-bitrate"), 500_000);
+    pad.set_property("video-content-hint", VideoContentHint::Text);
+    assert_eq!(
+        sink.property::<VideoContentHint>("video-content-hint"),
+        VideoContentHint::None
+    );
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    let stream = MOCK.streams(&session_id).remove(0);
+    assert_eq!(stream.name, "camera");
+    assert!(stream.has_video);
+    assert!(!stream.has_audio);
+
+    let (settings, capturer) = MOCK.publisher_settings(&session_id, &stream.id).unwrap();
+    assert_eq!(settings.max_video_bitrate, 500_000);
+    assert_eq!(settings.content_hint, VideoContentHint::Text);
+    assert_eq!(settings.video_type, VideoType::Camera);
+    assert_eq!(
+        (capturer.width, capturer.height, capturer.fps),
+        (320, 240, gst::Fraction::new(15, 1))
+    );
+
+    // Some settings also apply while publishing.
+    sink.set_property("video-type", VideoType::Screen);
+    sink.set_property("video-content-hint", VideoContentHint::Motion);
+    let (settings, _) = MOCK.publisher_settings(&session_id, &stream.id).unwrap();
+    assert_eq!(settings.video_type, VideoType::Screen);
+    assert_eq!(settings.content_hint, VideoContentHint::Text);
+    assert_eq!(pad.property::<VideoType>("video-type"), VideoType::Screen);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn video_caps_renegotiation() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    let stream_id = MOCK.streams(&session_id).remove(0).id;
+
+    // Upstream switches to another resolution and to a fractional frame
+    // rate, the capturer follows and the stream stays published.
+    let capsfilter = sink
+        .static_pad("video_sink_0")
+        .unwrap()
+        .peer()
+        .unwrap()
+        .parent_element()
+        .unwrap();
+    capsfilter.set_property(
+        "caps",
+        &gst::Caps::from_str("video/x-raw,format=I420,width=640,height=360,framerate=30000/1001")
+            .unwrap(),
+    );
+    assert!(wait_until(|| {
+        let (_, capturer) = MOCK.publisher_settings(&session_id, &stream_id).unwrap();
+        (capturer.width, capturer.height) == (640, 360)
+    }));
+    let (_, capturer) = MOCK.publisher_settings(&session_id, &stream_id).unwrap();
+    assert_eq!(capturer.fps, gst::Fraction::new(30000, 1001));
+    let streams = MOCK.streams(&session_id);
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].id, stream_id);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn framerate_change() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    let stream_id = MOCK.streams(&session_id).remove(0).id;
+
+    // Only the frame rate changes, the stream is not published again.
+    let capsfilter = sink
+        .static_pad("video_sink_0")
+        .unwrap()
+        .peer()
+        .unwrap()
+        .parent_element()
+        .unwrap();
+    capsfilter.set_property(
+        "caps",
+        &gst::Caps::from_str("video/x-raw,format=I420,width=320,height=240,framerate=15/1")
+            .unwrap(),
+    );
+    assert!(wait_until(|| {
+        let (_, capturer) = MOCK.publisher_settings(&session_id, &stream_id).unwrap();
+        capturer.fps == gst::Fraction::new(15, 1)
+    }));
+    let (_, capturer) = MOCK.publisher_settings(&session_id, &stream_id).unwrap();
+    assert_eq!((capturer.width, capturer.height), (320, 240));
+    let streams = MOCK.streams(&session_id);
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].id, stream_id);
+    for message in pipeline.bus().unwrap().iter() {
+        assert_ne!(message.type_(), gst::MessageType::Error, "{:?}", message);
+        assert!(message
+            .structure()
+            .map_or(true, |s| s.name() != "opentok/stream-destroyed"));
+    }
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+/// Subscribes to the streams of `session_id`, sending the content of the
+/// received buffers.
+fn frame_receiver(session_id: &str) -> (gst::Pipeline, mpsc::Receiver<Vec<u8>>) {
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", session_id));
+    pipeline.add(&src).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added(move |src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+        let sender = Mutex::new(sender.lock().unwrap().clone());
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                let map = buffer.map_readable().unwrap();
+                let _ = sender.lock().unwrap().send(map.to_vec());
+            }
+            gst::PadProbeReturn::Ok
+        });
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    (pipeline, receiver)
+}
+
+#[test]
+fn padded_video_frames() {
+    init();
+
+    let session_id = new_session_id();
+    let (pipeline, receiver) = frame_receiver(&session_id);
+
+    // Rows of 322 pixels are padded to 324 bytes for the luma plane, and to
+    // 164 bytes for the chroma ones.
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = gst::Pipeline::new(None);
+    let video_src = gst::ElementFactory::make("videotestsrc", None).unwrap();
+    video_src.set_property("is-live", true);
+    video_src.set_property_from_str("pattern", "white");
+    let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+    capsfilter.set_property(
+        "caps",
+        &gst::Caps::from_str("video/x-raw,format=I420,width=322,height=240,framerate=30/1")
+            .unwrap(),
+    );
+    publisher
+        .add_many(&[&video_src, &capsfilter, &sink])
+        .unwrap();
+    gst::Element::link_many(&[&video_src, &capsfilter, &sink]).unwrap();
+
+    // Upstream is told that the sink handles video metas, whatever the
+    // result of the query downstream.
+    let caps = capsfilter.property::<gst::Caps>("caps");
+    let mut query = gst::query::Allocation::new(&caps, true);
+    sink.static_pad("video_sink_0").unwrap().query(&mut query);
+    assert!(query
+        .find_allocation_meta::<gst_video::VideoMeta>()
+        .is_some());
+
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    // The published frames have no padding.
+    let data = receiver.recv_timeout(TIMEOUT).unwrap();
+    let luma_size = 322 * 240;
+    assert_eq!(data.len(), luma_size + 2 * 161 * 120);
+    assert!(data[..luma_size].iter().all(|y| *y == 235));
+    assert!(data[luma_size..].iter().all(|c| *c == 128));
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn padded_video_meta() {
+    init();
+
+    let session_id = new_session_id();
+    let (pipeline, receiver) = frame_receiver(&session_id);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = gst::Pipeline::new(None);
+    let appsrc = gst::ElementFactory::make("appsrc", None).unwrap();
+    appsrc.set_property(
+        "caps",
+        &gst::Caps::from_str("video/x-raw,format=I420,width=322,height=240,framerate=30/1")
+            .unwrap(),
+    );
+    appsrc.set_property("is-live", true);
+    appsrc.set_property("do-timestamp", true);
+    appsrc.set_property_from_str("format", "time");
+    publisher.add_many(&[&appsrc, &sink]).unwrap();
+    appsrc.link(&sink).unwrap();
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    // Rows padded well beyond the alignment of the default layout, with
+    // distinct luma and chroma values and garbage in the padding.
+    let (width, height) = (322, 240);
+    let (chroma_width, chroma_height) = (161, 120);
+    let strides = [400, 256, 256];
+    let offsets = [0, 400 * height, 400 * height + 256 * chroma_height];
+    let values = [200u8, 90, 240];
+    let size = offsets[2] + 256 * chroma_height;
+    let frame = || {
+        let mut buffer = gst::Buffer::with_size(size).unwrap();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            {
+                let mut map = buffer.map_writable().unwrap();
+                map.fill(0xff);
+                for plane in 0..3 {
+                    let (plane_width, plane_height) = match plane {
+                        0 => (width, height),
+                        _ => (chroma_width, chroma_height),
+                    };
+                    for row in 0..plane_height {
+                        let offset = offsets[plane] + row * strides[plane] as usize;
+                        map[offset..offset + plane_width].fill(values[plane]);
+                    }
+                }
+            }
+            gst_video::VideoMeta::add_full(
+                buffer,
+                gst_video::VideoFrameFlags::empty(),
+                gst_video::VideoFormat::I420,
+                width as u32,
+                height as u32,
+                &offsets,
+                &strides,
+            )
+            .unwrap();
+        }
+        buffer
+    };
+
+    // Frames are only published once the publisher is.
+    let appsrc = appsrc.downcast::<gst_app::AppSrc>().unwrap();
+    let start = Instant::now();
+    let data = loop {
+        appsrc.push_buffer(frame()).unwrap();
+        if let Ok(data) = receiver.recv_timeout(Duration::from_millis(33)) {
+            break data;
+        }
+        assert!(start.elapsed() < TIMEOUT);
+    };
+
+    // The published frames have no padding.
+    let luma_size = width * height;
+    let chroma_size = chroma_width * chroma_height;
+    assert_eq!(data.len(), luma_size + 2 * chroma_size);
+    assert!(data[..luma_size].iter().all(|y| *y == values[0]));
+    assert!(data[luma_size..luma_size + chroma_size]
+        .iter()
+        .all(|u| *u == values[1]));
+    assert!(data[luma_size + chroma_size..]
+        .iter()
+        .all(|v| *v == values[2]));
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn any_audio_format() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    sink.set_property("publish-stereo", true);
+    let pipeline = publisher_pipeline(&sink);
+    let audio_src = gst::ElementFactory::make("audiotestsrc", None).unwrap();
+    audio_src.set_property("is-live", true);
+    let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+    capsfilter.set_property(
+        "caps",
+        &gst::Caps::from_str("audio/x-raw,format=F32LE,rate=48000,channels=2").unwrap(),
+    );
+    pipeline.add_many(&[&audio_src, &capsfilter]).unwrap();
+    gst::Element::link_many(&[&audio_src, &capsfilter, &sink]).unwrap();
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    assert!(MOCK.streams(&session_id)[0].has_audio);
+
+    // The audio is converted to the format of the audio device.
+    let appsink_pad = sink
+        .downcast_ref::<gst::Bin>()
+        .unwrap()
+        .by_name("audio-sink-0-appsink")
+        .unwrap()
+        .static_pad("sink")
+        .unwrap();
+    assert!(wait_until(|| appsink_pad.current_caps().is_some()));
+    let caps = appsink_pad.current_caps().unwrap();
+    let s = caps.structure(0).unwrap();
+    assert_eq!(
+        s.get::<&str>("format").unwrap(),
+        gst_audio::AUDIO_FORMAT_S16.to_str()
+    );
+    assert_eq!(s.get::<i32>("rate").unwrap(), 44100);
+    assert_eq!(s.get::<i32>("channels").unwrap(), 2);
+
+    // The audio device is running, its format can no longer change.
+    sink.set_property("publish-stereo", false);
+    sink.set_property("audio-rate", 16000u32);
+    assert!(sink.property::<bool>("publish-stereo"));
+    assert_eq!(sink.property::<u32>("audio-rate"), 44100);
+    let caps = appsink_pad.current_caps().unwrap();
+    let s = caps.structure(0).unwrap();
+    assert_eq!(s.get::<i32>("rate").unwrap(), 44100);
+    assert_eq!(s.get::<i32>("channels").unwrap(), 2);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn mute_and_unmute() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let pipeline = publisher_pipeline(&sink);
+    let audio_src = gst::ElementFactory::make("audiotestsrc", None).unwrap();
+    audio_src.set_property("is-live", true);
+    pipeline.add(&audio_src).unwrap();
+    audio_src.link(&sink).unwrap();
+    let video_pad = sink.static_pad("video_sink_0").unwrap();
+    let audio_pad = sink.static_pad("audio_sink_0").unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    audio_pad.connect_notify(Some("publish-audio"), move |pad, _| {
+        let _ = sender
+            .lock()
+            .unwrap()
+            .send(pad.property::<bool>("publish-audio"));
+    });
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    let stream = wait_for_message(&pipeline, "opentok/stream-created");
+    assert!(stream.get::<bool>("has-audio").unwrap());
+    assert!(stream.get::<bool>("has-video").unwrap());
+    let stream_id = stream.get::<String>("stream-id").unwrap();
+    let has = |media: &str| {
+        let stream = MOCK
+            .streams(&session_id)
+            .into_iter()
+            .find(|s| s.id == stream_id)
+            .unwrap();
+        match media {
+            "audio" => stream.has_audio,
+            _ => stream.has_video,
+        }
+    };
+
+    sink.set_property("publish-audio", false);
+    assert!(!receiver.recv_timeout(TIMEOUT).unwrap());
+    assert!(!has("audio"));
+    let message = wait_for_message(&pipeline, "opentok/stream-property-changed");
+    assert_eq!(message.get::<String>("stream-id").unwrap(), stream_id);
+    assert_eq!(message.get::<String>("property").unwrap(), "has-audio");
+    assert!(!message.get::<bool>("has-audio").unwrap());
+
+    sink.set_property("publish-audio", true);
+    assert!(receiver.recv_timeout(TIMEOUT).unwrap());
+    assert!(has("audio"));
+    let message = wait_for_message(&pipeline, "opentok/stream-property-changed");
+    assert!(message.get::<bool>("has-audio").unwrap());
+
+    video_pad.set_property("muted", true);
+    assert!(!has("video"));
+    assert!(has("audio"));
+    let message = wait_for_message(&pipeline, "opentok/stream-property-changed");
+    assert_eq!(message.get::<String>("property").unwrap(), "has-video");
+    assert!(!message.get::<bool>("has-video").unwrap());
+
+    video_pad.set_property("muted", false);
+    assert!(has("video"));
+    assert!(!video_pad.property::<bool>("muted"));
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn state_cycles() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let pipeline = publisher_pipeline(&sink);
+
+    for _ in 0..3 {
+        set_state_and_wait(&pipeline, gst::State::Playing);
+        assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+
+        set_state_and_wait(&pipeline, gst::State::Null);
+        assert!(wait_until(|| MOCK.streams(&session_id).is_empty()));
+        assert!(wait_until(|| MOCK.connection_count(&session_id) == 0));
+        assert_no_error(&pipeline);
+    }
+}
+
+#[test]
+fn async_start_and_timeout() {
+    init();
+
+    // The room info service accepts connections, but never answers.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property(
+        "demo-room-uri",
+        format!("http://{}/room/test", listener.local_addr().unwrap()),
+    );
+    assert_eq!(sink.property::<u32>("timeout"), 30);
+    sink.set_property("timeout", 1u32);
+    let pipeline = gst::Pipeline::new(None);
+    pipeline.add(&sink).unwrap();
+
+    let start = Instant::now();
+    assert_eq!(
+        pipeline.set_state(gst::State::Paused),
+        Ok(gst::StateChangeSuccess::Async)
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let error = wait_for_error(&pipeline);
+    assert!(error.matches(gst::ResourceError::OpenWrite));
+    assert!(error.to_string().contains("Timed out"), "{}", error);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
@@ -0,0 +1,542 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+mod common;
+use common::*;
+
+#[test]
+fn video_pads_follow_remote_streams() {
+    init();
+
+    let session_id = new_session_id();
+
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+
+    let buffers = Arc::new(AtomicUsize::new(0));
+    let (added_sender, added_receiver) = mpsc::channel();
+    let added_sender = Mutex::new(added_sender);
+    src.connect_pad_added({
+        let pipeline = pipeline.downgrade();
+        let buffers = buffers.clone();
+        move |_, pad| {
+            let pipeline = match pipeline.upgrade() {
+                Some(pipeline) => pipeline,
+                None => return,
+            };
+            let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+            sink.set_property("sync", false);
+            sink.set_property("async", false);
+            pipeline.add(&sink).unwrap();
+            sink.sync_state_with_parent().unwrap();
+            pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+
+            let buffers = buffers.clone();
+            pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                buffers.fetch_add(1, Ordering::SeqCst);
+                gst::PadProbeReturn::Ok
+            });
+            let _ = added_sender.lock().unwrap().send(pad.name().to_string());
+        }
+    });
+
+    let (removed_sender, removed_receiver) = mpsc::channel();
+    let removed_sender = Mutex::new(removed_sender);
+    src.connect_pad_removed(move |_, pad| {
+        let _ = removed_sender.lock().unwrap().send(pad.name().to_string());
+    });
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    let pad_name = added_receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(pad_name, "video_stream_0");
+    assert!(wait_until(|| buffers.load(Ordering::SeqCst) > 0));
+
+    set_state_and_wait(&publisher, gst::State::Null);
+    let pad_name = removed_receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(pad_name, "video_stream_0");
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn state_cycles() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+
+    for _ in 0..3 {
+        set_state_and_wait(&pipeline, gst::State::Playing);
+        assert!(wait_until(|| MOCK.connection_count(&session_id) == 1));
+
+        set_state_and_wait(&pipeline, gst::State::Null);
+        assert!(wait_until(|| MOCK.connection_count(&session_id) == 0));
+        assert_no_error(&pipeline);
+    }
+}
+
+#[test]
+fn room_info_error() {
+    init();
+
+    let server = RoomServer::start("{}", usize::MAX);
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("demo-room-uri", format!("{}/room/test", server.url()));
+    pipeline.add(&src).unwrap();
+
+    // Depending on how fast the room info service answers, the state change
+    // either fails right away or completes asynchronously.
+    let _ = pipeline.set_state(gst::State::Playing);
+    let error = wait_for_error(&pipeline);
+    assert!(error.matches(gst::ResourceError::OpenRead));
+    assert!(
+        error.to_string().contains("Cannot load credentials"),
+        "{}",
+        error
+    );
+    assert_eq!(server.requests().len(), 1);
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn video_formats() {
+    init();
+
+    // Sources also produce the MJPEG frames of the SDK, sinks only take raw
+    // video.
+    let src_template = gst::ElementFactory::find("opentoksrc")
+        .unwrap()
+        .static_pad_templates()
+        .into_iter()
+        .find(|template| template.name_template() == "video_stream_%u")
+        .unwrap()
+        .caps();
+    assert!(src_template.can_intersect(&gst::Caps::builder("image/jpeg").build()));
+    let sink_template = gst::ElementFactory::find("opentoksink")
+        .unwrap()
+        .static_pad_templates()
+        .into_iter()
+        .find(|template| template.name_template() == "video_sink_%u")
+        .unwrap()
+        .caps();
+    assert!(!sink_template.can_intersect(&gst::Caps::builder("image/jpeg").build()));
+    for format in &["I420", "NV12", "BGR", "BGRA", "ARGB", "RGBA", "ABGR"] {
+        let caps = gst::Caps::builder("video/x-raw")
+            .field("format", format)
+            .build();
+        assert!(sink_template.can_intersect(&caps), "{}", format);
+        assert!(src_template.can_intersect(&caps), "{}", format);
+    }
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added(move |src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+        let sender = Mutex::new(sender.lock().unwrap().clone());
+        pad.connect_notify(Some("caps"), move |pad, _| {
+            if let Some(caps) = pad.current_caps() {
+                let _ = sender.lock().unwrap().send(caps);
+            }
+        });
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = gst::Pipeline::new(None);
+    let video_src = gst::ElementFactory::make("videotestsrc", None).unwrap();
+    video_src.set_property("is-live", true);
+    let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+    capsfilter.set_property(
+        "caps",
+        &gst::Caps::builder("video/x-raw")
+            .field("format", "BGRA")
+            .field("width", 320)
+            .field("height", 240)
+            .build(),
+    );
+    publisher
+        .add_many(&[&video_src, &capsfilter, &sink])
+        .unwrap();
+    gst::Element::link_many(&[&video_src, &capsfilter, &sink]).unwrap();
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    let caps = receiver.recv_timeout(TIMEOUT).unwrap();
+    let s = caps.structure(0).unwrap();
+    assert_eq!(s.name(), "video/x-raw");
+    assert_eq!(s.get::<&str>("format").unwrap(), "BGRA");
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn audio_device_settings() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    src.set_property("audio-rate", 48000u32);
+    src.set_property("audio-channels", 2u32);
+    pipeline.add(&src).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added(move |src, pad| {
+        if pad.name() == "audio_stream" {
+            let _ = sender.lock().unwrap().send(pad.query_caps(None));
+        }
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    // The audio pad advertises the format of the audio device, from the
+    // moment it is added.
+    let audio_pad = src.static_pad("audio_stream").unwrap();
+    let added_caps = receiver.recv_timeout(TIMEOUT).unwrap();
+    for caps in [added_caps, audio_pad.query_caps(None)] {
+        assert!(caps.is_fixed(), "{}", caps);
+        let s = caps.structure(0).unwrap();
+        assert_eq!(
+            s.get::<&str>("format").unwrap(),
+            gst_audio::AUDIO_FORMAT_S16.to_str()
+        );
+        assert_eq!(s.get::<i32>("rate").unwrap(), 48000);
+        assert_eq!(s.get::<i32>("channels").unwrap(), 2);
+    }
+
+    // The audio device is running, its format can no longer change.
+    src.set_property("audio-channels", 1u32);
+    assert_eq!(src.property::<u32>("audio-channels"), 2);
+    let caps = audio_pad.query_caps(None);
+    assert_eq!(
+        caps.structure(0).unwrap().get::<i32>("channels").unwrap(),
+        2
+    );
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn video_timestamps() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added(move |src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+        if !pad.name().starts_with("video_stream_") {
+            return;
+        }
+        let sender = Mutex::new(sender.lock().unwrap().clone());
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                let _ = sender
+                    .lock()
+                    .unwrap()
+                    .send((buffer.pts(), buffer.duration()));
+            }
+            gst::PadProbeReturn::Ok
+        });
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    // The buffers follow the 30 fps pace of the published frames.
+    let buffers = (0..20)
+        .map(|_| receiver.recv_timeout(TIMEOUT).unwrap())
+        .collect::<Vec<_>>();
+    for pair in buffers.windows(2) {
+        assert!(pair[0].0.unwrap() < pair[1].0.unwrap(), "{:?}", pair);
+    }
+    let duration = buffers.last().unwrap().1.unwrap();
+    assert!(
+        duration > gst::ClockTime::from_mseconds(30)
+            && duration < gst::ClockTime::from_mseconds(37),
+        "{}",
+        duration
+    );
+
+    // The frame rate is variable, up to the measured one.
+    let caps = src
+        .static_pad("video_stream_0")
+        .unwrap()
+        .current_caps()
+        .unwrap();
+    let s = caps.structure(0).unwrap();
+    assert_eq!(
+        s.get::<gst::Fraction>("framerate").unwrap(),
+        gst::Fraction::new(0, 1)
+    );
+    assert_eq!(
+        s.get::<gst::Fraction>("max-framerate").unwrap(),
+        gst::Fraction::new(30, 1)
+    );
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn latency() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    src.set_property("latency", 300u32);
+    pipeline.add(&src).unwrap();
+    src.connect_pad_added(move |src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+    assert!(wait_until(|| src.static_pad("video_stream_0").is_some()));
+
+    let query_latency = |name: &str| {
+        let mut query = gst::query::Latency::new();
+        assert!(src.static_pad(name).unwrap().query(&mut query));
+        query.result()
+    };
+    for name in &["audio_stream", "video_stream_0"] {
+        let (live, min, _) = query_latency(name);
+        assert!(live);
+        assert_eq!(min, gst::ClockTime::from_mseconds(300));
+    }
+
+    // Changing the latency has the pipeline compute its latency again.
+    src.set_property("latency", 100u32);
+    assert_eq!(src.property::<u32>("latency"), 100);
+    let bus = pipeline.bus().unwrap();
+    assert!(bus
+        .timed_pop_filtered(
+            gst::ClockTime::from_seconds(TIMEOUT.as_secs()),
+            &[gst::MessageType::Latency],
+        )
+        .is_some());
+    let (_, min, _) = query_latency("video_stream_0");
+    assert_eq!(min, gst::ClockTime::from_mseconds(100));
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn buffers_within_latency() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    src.set_property("latency", 200u32);
+    pipeline.add(&src).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added(move |src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+        let sender = Mutex::new(sender.lock().unwrap().clone());
+        let src = src.downgrade();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if let (Some(gst::PadProbeData::Buffer(ref buffer)), Some(src)) =
+                (&info.data, src.upgrade())
+            {
+                let now = src.current_running_time().unwrap();
+                let _ = sender.lock().unwrap().send((
+                    pad.name().to_string(),
+                    buffer.pts().unwrap(),
+                    now,
+                ));
+            }
+            gst::PadProbeReturn::Ok
+        });
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    let audio_src = gst::ElementFactory::make("audiotestsrc", None).unwrap();
+    audio_src.set_property("is-live", true);
+    publisher.add(&audio_src).unwrap();
+    audio_src.link(&sink).unwrap();
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    // The audio and video buffers are all pushed within the latency of
+    // their timestamps, give or take the scheduling of the test.
+    let slack = gst::ClockTime::from_mseconds(300);
+    let mut audio = 0;
+    let mut video = 0;
+    while audio < 50 || video < 30 {
+        let (name, pts, now) = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert!(pts <= now, "{} buffer at {} pushed at {}", name, pts, now);
+        assert!(
+            now - pts <= gst::ClockTime::from_mseconds(200) + slack,
+            "{} buffer at {} pushed at {}",
+            name,
+            pts,
+            now
+        );
+        match name.as_str() {
+            "audio_stream" => audio += 1,
+            _ => video += 1,
+        }
+    }
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn live_with_async_sinks() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+    let buffers = Arc::new(AtomicUsize::new(0));
+    src.connect_pad_added({
+        let buffers = buffers.clone();
+        move |src, pad| {
+            let pipeline = src
+                .parent()
+                .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+                .unwrap();
+            // Prerolling sinks, as in `opentoksrc ! autovideosink`.
+            let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+            pipeline.add(&sink).unwrap();
+            sink.sync_state_with_parent().unwrap();
+            pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+            if pad.name().starts_with("video_stream_") {
+                let buffers = buffers.clone();
+                sink.static_pad("sink").unwrap().add_probe(
+                    gst::PadProbeType::BUFFER,
+                    move |_, _| {
+                        buffers.fetch_add(1, Ordering::SeqCst);
+                        gst::PadProbeReturn::Ok
+                    },
+                );
+            }
+        }
+    });
+
+    // The source does not preroll, so PAUSED completes before anything is
+    // published.
+    assert_eq!(
+        pipeline.set_state(gst::State::Paused),
+        Ok(gst::StateChangeSuccess::NoPreroll)
+    );
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+    assert!(wait_until(|| buffers.load(Ordering::SeqCst) > 0));
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&publisher, gst::State::Null);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
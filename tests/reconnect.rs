@@ -0,0 +1,204 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use std::sync::{mpsc, Mutex};
+
+mod common;
+use common::*;
+
+fn quick_reconnect(element: &gst::Element, attempts: u32) {
+    element.set_property("reconnect-attempts", attempts);
+    element.set_property("reconnect-initial-delay", 10u32);
+    element.set_property("reconnect-max-delay", 50u32);
+}
+
+#[test]
+fn reconnect_properties() {
+    init();
+
+    for name in ["opentoksrc", "opentoksink"] {
+        let element = gst::ElementFactory::make(name, None).unwrap();
+        assert_eq!(element.property::<u32>("reconnect-attempts"), 5);
+        assert_eq!(element.property::<u32>("reconnect-initial-delay"), 500);
+        assert_eq!(element.property::<u32>("reconnect-max-delay"), 10000);
+    }
+}
+
+#[test]
+fn session_reconnects() {
+    init();
+
+    let session_id = new_session_id();
+    let location = location("opentok", &session_id);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location);
+    quick_reconnect(&sink, 5);
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location);
+    quick_reconnect(&src, 5);
+    pipeline.add(&src).unwrap();
+
+    let (removed_sender, removed_receiver) = mpsc::channel();
+    let removed_sender = Mutex::new(removed_sender);
+    src.connect_pad_removed(move |_, pad| {
+        let _ = removed_sender.lock().unwrap().send(pad.name().to_string());
+    });
+    src.connect_pad_added(|src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+    });
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| src.static_pad("video_stream_0").is_some()));
+
+    MOCK.refuse_connections(&session_id, 2);
+    MOCK.drop_connections(&session_id);
+
+    let reconnecting = wait_for_message(&pipeline, "opentok/reconnecting");
+    assert_eq!(reconnecting.get::<u32>("attempt").unwrap(), 1);
+    assert_eq!(reconnecting.get::<u32>("max-attempts").unwrap(), 5);
+    wait_for_message(&pipeline, "opentok/reconnected");
+    wait_for_message(&publisher, "opentok/reconnected");
+
+    // The pads stayed in place, and the republished stream replaces the
+    // one lost with the connection.
+    assert!(src.static_pad("audio_stream").is_some());
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    assert!(wait_until(|| src.static_pad("video_stream_1").is_some()));
+    assert_eq!(
+        removed_receiver.recv_timeout(TIMEOUT).unwrap(),
+        "video_stream_0"
+    );
+    assert_eq!(MOCK.connection_count(&session_id), 2);
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+    set_state_and_wait(&publisher, gst::State::Null);
+}
+
+#[test]
+fn reconnection_gives_up() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    quick_reconnect(&src, 2);
+    pipeline.add(&src).unwrap();
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.connection_count(&session_id) == 1));
+
+    MOCK.refuse_connections(&session_id, usize::MAX);
+    MOCK.drop_connections(&session_id);
+
+    for attempt in 1..=2 {
+        let reconnecting = wait_for_message(&pipeline, "opentok/reconnecting");
+        assert_eq!(reconnecting.get::<u32>("attempt").unwrap(), attempt);
+    }
+    wait_for_message(&pipeline, "opentok/disconnected");
+    let error = wait_for_error(&pipeline);
+    assert!(
+        error.to_string().contains("after 2 reconnection attempts"),
+        "{}",
+        error
+    );
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn errors_keep_the_connection() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    quick_reconnect(&src, 2);
+    pipeline.add(&src).unwrap();
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.connection_count(&session_id) == 1));
+
+    // An error unrelated to the connection is only a warning.
+    MOCK.session_error(&session_id, "Invalid signal type");
+    let bus = pipeline.bus().unwrap();
+    let warning = bus
+        .timed_pop_filtered(
+            gst::ClockTime::from_seconds(TIMEOUT.as_secs()),
+            &[gst::MessageType::Warning],
+        )
+        .unwrap();
+    assert!(format!("{:?}", warning).contains("Invalid signal type"));
+    for message in bus.iter() {
+        assert_ne!(message.type_(), gst::MessageType::Error, "{:?}", message);
+        assert!(message
+            .structure()
+            .map_or(true, |s| s.name() != "opentok/reconnecting"));
+    }
+
+    // Losing the connection still reconnects.
+    MOCK.drop_connections(&session_id);
+    wait_for_message(&pipeline, "opentok/reconnecting");
+    wait_for_message(&pipeline, "opentok/reconnected");
+    assert_eq!(MOCK.connection_count(&session_id), 1);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn reconnect_uses_new_token() {
+    init();
+
+    for name in ["opentoksrc", "opentoksink"] {
+        let session_id = new_session_id();
+        let element = gst::ElementFactory::make(name, None).unwrap();
+        element.set_property("location", &location("opentok", &session_id));
+        quick_reconnect(&element, 2);
+        let pipeline = if name == "opentoksink" {
+            publisher_pipeline(&element)
+        } else {
+            let pipeline = gst::Pipeline::new(None);
+            pipeline.add(&element).unwrap();
+            pipeline
+        };
+
+        set_state_and_wait(&pipeline, gst::State::Playing);
+        assert!(wait_until(|| MOCK.connection_count(&session_id) == 1));
+        assert_eq!(MOCK.connection_tokens(&session_id), vec!["mock-token"]);
+
+        // Renewed while connected, used once the connection is lost.
+        element.set_property("token", "renewed-token");
+        MOCK.drop_connections(&session_id);
+        wait_for_message(&pipeline, "opentok/reconnected");
+        assert_eq!(MOCK.connection_tokens(&session_id), vec!["renewed-token"]);
+
+        assert_no_error(&pipeline);
+        set_state_and_wait(&pipeline, gst::State::Null);
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! The remote elements run the session in a gst-opentok-helper process, which
+//! picks the mock backend from the environment. Rooms are not shared with the
+//! test process, so these tests only check what the elements report back.
+
+use gst::prelude::*;
+use std::sync::{mpsc, Mutex};
+
+mod common;
+use common::*;
+
+#[test]
+fn sink_request_and_release_pads() {
+    init();
+
+    let sink = gst::ElementFactory::make("opentoksink-remote", None).unwrap();
+
+    let video_pad = sink.request_pad_simple("video_sink").unwrap();
+    assert_eq!(video_pad.name(), "video_sink");
+    let audio_pad = sink.request_pad_simple("audio_sink").unwrap();
+    assert_eq!(audio_pad.name(), "audio_sink");
+    assert_eq!(sink.num_sink_pads(), 2);
+
+    sink.release_request_pad(&video_pad);
+    sink.release_request_pad(&audio_pad);
+    assert_eq!(sink.num_sink_pads(), 0);
+}
+
+#[test]
+fn sink_published_stream_signal() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::Element::make_from_uri(
+        gst::URIType::Sink,
+        &location("opentok-remote", &session_id),
+        None,
+    )
+    .unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    sink.connect("published-stream", false, move |args| {
+        let stream_id = args[1].get::<String>().unwrap();
+        let url = args[2].get::<String>().unwrap();
+        let _ = sender.lock().unwrap().send((stream_id, url));
+        None
+    });
+
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let (stream_id, url) = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert!(!stream_id.is_empty());
+    assert!(url.contains(&session_id));
+    assert!(url.contains(&stream_id));
+    assert!(wait_until(
+        || sink.property::<String>("stream-id") == stream_id
+    ));
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+}
+
+#[test]
+fn src_state_cycles() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc-remote", None).unwrap();
+    src.set_property("location", &location("opentok-remote", &session_id));
+    pipeline.add(&src).unwrap();
+
+    for _ in 0..3 {
+        set_state_and_wait(&pipeline, gst::State::Playing);
+        set_state_and_wait(&pipeline, gst::State::Null);
+        assert_no_error(&pipeline);
+    }
+}
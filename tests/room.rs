@@ -0,0 +1,198 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::room::RoomInfoFields;
+use gstopentok::common::{Credentials, RoomInfoProvider, Secret};
+use std::net::TcpListener;
+use std::time::Duration;
+
+mod common;
+use common::*;
+
+fn nested_provider() -> RoomInfoProvider {
+    RoomInfoProvider {
+        url: "{room}/credentials?client=gst".into(),
+        fields: RoomInfoFields {
+            api_key: "data.key".into(),
+            session_id: "data.session".into(),
+            token: "data.tokens.0".into(),
+            stream_id: Some("data.stream".into()),
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn parse() {
+    let info = RoomInfoProvider::default()
+        .parse(r#"{"apiKey": "key", "sessionId": "session", "token": "xyz"}"#)
+        .unwrap();
+    assert_eq!(info.api_key, "key");
+    assert_eq!(info.session_id, "session");
+    assert_eq!(info.token, Secret::from("xyz"));
+    assert_eq!(info.stream_id, None);
+
+    let provider = nested_provider();
+    let info = provider
+        .parse(r#"{"data": {"key": 123456, "session": "session", "tokens": ["xyz"], "stream": "stream"}}"#)
+        .unwrap();
+    assert_eq!(info.api_key, "123456");
+    assert_eq!(info.token.expose(), "xyz");
+    assert_eq!(info.stream_id.as_deref(), Some("stream"));
+
+    assert!(provider
+        .parse(r#"{"data": {"key": 1, "session": "s"}}"#)
+        .is_err());
+    assert!(provider.parse("not json").is_err());
+
+    let room = url::Url::parse("https://rooms.example.com/room/test/").unwrap();
+    assert_eq!(
+        provider.url(&room),
+        "https://rooms.example.com/room/test/credentials?client=gst"
+    );
+}
+
+#[test]
+fn retries_and_headers() {
+    let server = RoomServer::start(
+        r#"{"data": {"key": "key", "session": "session", "tokens": ["xyz"], "stream": "stream"}}"#,
+        2,
+    );
+
+    let mut credentials = Credentials::default();
+    credentials
+        .set_room_uri(format!("{}/room/test", server.url()))
+        .unwrap();
+    credentials.set_room_info(RoomInfoProvider {
+        headers: vec![("Authorization".into(), Secret::from("Bearer xyz"))],
+        retries: 1,
+        ..nested_provider()
+    });
+    assert!(async_std::task::block_on(credentials.load()).is_err());
+    assert_eq!(server.requests().len(), 2);
+
+    // Both failures are used up, the next request succeeds.
+    async_std::task::block_on(credentials.load()).unwrap();
+    assert_eq!(credentials.api_key().unwrap(), "key");
+    assert_eq!(credentials.session_id().unwrap(), "session");
+    assert_eq!(credentials.token().unwrap().expose(), "xyz");
+    assert_eq!(credentials.stream_id().unwrap(), "stream");
+    // The header value never shows up in the logs.
+    assert!(!format!("{:?}", credentials).contains("Bearer xyz"));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    for request in requests {
+        assert_eq!(request.path, "/room/test/credentials?client=gst");
+        assert_eq!(request.header("authorization"), Some("Bearer xyz"));
+    }
+}
+
+#[test]
+fn timeout() {
+    // Connections are accepted by the kernel, but never answered.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut credentials = Credentials::default();
+    credentials
+        .set_room_uri(format!(
+            "http://{}/room/test",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+    credentials.room_info_mut().timeout = Duration::from_secs(1);
+    assert!(async_std::task::block_on(credentials.load()).is_err());
+}
+
+#[test]
+fn element_properties() {
+    init();
+
+    for factory in ["opentoksrc", "opentoksink"] {
+        let element = gst::ElementFactory::make(factory, None).unwrap();
+        assert_eq!(element.property::<String>("room-info-url"), "{room}/info");
+        assert_eq!(element.property::<u32>("room-info-retries"), 0);
+        assert_eq!(element.property::<u32>("room-info-timeout"), 5);
+        assert!(element
+            .property::<Option<gst::Structure>>("room-info-headers")
+            .is_none());
+
+        let headers = gst::Structure::builder("headers")
+            .field("Authorization", "Bearer xyz")
+            .build();
+        let fields = gst::Structure::builder("fields")
+            .field("token", "data.token")
+            .build();
+        element.set_property("room-info-url", "{room}/credentials");
+        element.set_property("room-info-headers", &headers);
+        element.set_property("room-info-fields", &fields);
+        element.set_property("room-info-retries", 3u32);
+        element.set_property("room-info-timeout", 1u32);
+
+        assert_eq!(
+            element.property::<String>("room-info-url"),
+            "{room}/credentials"
+        );
+        assert_eq!(
+            element.property::<Option<gst::Structure>>("room-info-headers"),
+            Some(headers)
+        );
+        let fields = element.property::<gst::Structure>("room-info-fields");
+        assert_eq!(fields.get::<String>("token").unwrap(), "data.token");
+        assert_eq!(fields.get::<String>("api-key").unwrap(), "apiKey");
+        assert!(!fields.has_field("stream-id"));
+        assert_eq!(element.property::<u32>("room-info-retries"), 3);
+        assert_eq!(element.property::<u32>("room-info-timeout"), 1);
+    }
+}
+
+#[test]
+fn sink_publishes_with_room_info() {
+    init();
+
+    let session_id = new_session_id();
+    let server = RoomServer::start(
+        format!(
+            r#"{{"data": {{"key": "mock-key", "session": "{}", "token": "mock-token"}}}}"#,
+            session_id
+        ),
+        1,
+    );
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("demo-room-uri", format!("{}/room/test", server.url()));
+    sink.set_property("room-info-url", "{room}/credentials");
+    sink.set_property(
+        "room-info-headers",
+        &gst::Structure::builder("headers")
+            .field("Authorization", "Bearer xyz")
+            .build(),
+    );
+    sink.set_property(
+        "room-info-fields",
+        &gst::Structure::builder("fields")
+            .field("api-key", "data.key")
+            .field("session-id", "data.session")
+            .field("token", "data.token")
+            .build(),
+    );
+    sink.set_property("room-info-retries", 1u32);
+
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].path, "/room/test/credentials");
+    assert_eq!(requests[1].header("authorization"), Some("Bearer xyz"));
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert_no_error(&pipeline);
+}
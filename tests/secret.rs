@@ -0,0 +1,54 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gstopentok::common::secret::{redact_location, set_reveal_secrets};
+use gstopentok::common::{Credentials, Secret};
+
+const TOKEN: &str = "T1==c2VjcmV0LXRva2Vu";
+
+// The reveal switch is process wide, hence a single test.
+#[test]
+fn redaction() {
+    set_reveal_secrets(false);
+
+    let token = Secret::from(TOKEN);
+    assert_eq!(token.expose(), TOKEN);
+    assert_eq!(token.fingerprint().len(), 8);
+    assert_eq!(
+        token.to_string(),
+        format!("<redacted {}>", token.fingerprint())
+    );
+    assert!(!format!("{:?}", token).contains(TOKEN));
+    assert_ne!(
+        token.fingerprint(),
+        Secret::from("another-token").fingerprint()
+    );
+
+    let mut credentials = Credentials::default();
+    credentials.set_api_key("key".into()).unwrap();
+    credentials.set_session_id("session".into()).unwrap();
+    credentials.set_token(TOKEN).unwrap();
+    credentials.set_api_secret("api-secret").unwrap();
+    let debug = format!("{:?}", credentials);
+    assert!(!debug.contains(TOKEN));
+    assert!(!debug.contains("api-secret"));
+    assert!(debug.contains(&token.fingerprint()));
+
+    let location = format!("opentok://session/stream?key=key&token={}", TOKEN);
+    let redacted = redact_location(&location);
+    assert!(!redacted.contains(TOKEN));
+    assert!(redacted.starts_with("opentok://session/stream?key=key&token=<redacted "));
+    assert_eq!(redact_location("not a location?token=x"), "not a location");
+
+    set_reveal_secrets(true);
+    assert_eq!(token.to_string(), TOKEN);
+    assert!(format!("{:?}", credentials).contains(TOKEN));
+    assert_eq!(redact_location(&location), location);
+    set_reveal_secrets(false);
+}
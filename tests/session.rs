@@ -0,0 +1,127 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::session::CONTEXT_TYPE;
+use std::sync::{mpsc, Mutex};
+
+mod common;
+use common::*;
+
+/// Adds an `opentoksrc` to `pipeline`, its pads linked to fakesinks, and
+/// reports the names of the pads it adds.
+fn add_src(
+    pipeline: &gst::Pipeline,
+    location: &str,
+    own_streams: bool,
+) -> (gst::Element, mpsc::Receiver<String>) {
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", location);
+    src.set_property("subscribe-to-own-streams", own_streams);
+    pipeline.add(&src).unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added({
+        let pipeline = pipeline.downgrade();
+        move |_, pad| {
+            let pipeline = match pipeline.upgrade() {
+                Some(pipeline) => pipeline,
+                None => return,
+            };
+            let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+            sink.set_property("sync", false);
+            sink.set_property("async", false);
+            pipeline.add(&sink).unwrap();
+            sink.sync_state_with_parent().unwrap();
+            pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+            let _ = sender.lock().unwrap().send(pad.name().to_string());
+        }
+    });
+    (src, receiver)
+}
+
+fn video_pad_added(pads: &mpsc::Receiver<String>) -> bool {
+    std::iter::from_fn(|| pads.recv_timeout(TIMEOUT).ok()).any(|name| name.starts_with("video"))
+}
+
+#[test]
+fn one_connection_per_pipeline() {
+    init();
+
+    let session_id = new_session_id();
+    let location = location("opentok", &session_id);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location);
+    let pipeline = publisher_pipeline(&sink);
+    let (src, _) = add_src(&pipeline, &location, false);
+    let (own_streams_src, own_streams_pads) = add_src(&pipeline, &location, true);
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(pipeline.context(CONTEXT_TYPE).is_some());
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+    assert_eq!(MOCK.connection_count(&session_id), 1);
+
+    // Only the source asking for it gets the stream of the sink.
+    assert!(video_pad_added(&own_streams_pads));
+    assert!(src.static_pad("video_stream_0").is_none());
+
+    // Remote streams reach both sources.
+    let remote_sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    remote_sink.set_property("location", &location);
+    let remote = publisher_pipeline(&remote_sink);
+    set_state_and_wait(&remote, gst::State::Playing);
+    assert!(wait_until(|| src.static_pad("video_stream_0").is_some()));
+    assert!(wait_until(|| own_streams_src
+        .static_pad("video_stream_1")
+        .is_some()));
+    assert_eq!(MOCK.connection_count(&session_id), 2);
+
+    set_state_and_wait(&remote, gst::State::Null);
+    assert_no_error(&remote);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert!(wait_until(|| MOCK.connection_count(&session_id) == 0));
+}
+
+#[test]
+fn late_joiner_gets_existing_streams() {
+    init();
+
+    let session_id = new_session_id();
+    let location = location("opentok", &session_id);
+
+    let remote_sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    remote_sink.set_property("location", &location);
+    let remote = publisher_pipeline(&remote_sink);
+    set_state_and_wait(&remote, gst::State::Playing);
+
+    let pipeline = gst::Pipeline::new(None);
+    let (_, first_pads) = add_src(&pipeline, &location, false);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(video_pad_added(&first_pads));
+
+    // The session is connected already, the new source gets the stream
+    // without connecting again.
+    let (second, second_pads) = add_src(&pipeline, &location, false);
+    second.sync_state_with_parent().unwrap();
+    assert!(video_pad_added(&second_pads));
+    assert_eq!(MOCK.connection_count(&session_id), 2);
+
+    // The session stays connected until its last user leaves.
+    second.set_state(gst::State::Null).unwrap();
+    assert_eq!(MOCK.connection_count(&session_id), 2);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert!(wait_until(|| MOCK.connection_count(&session_id) == 1));
+    set_state_and_wait(&remote, gst::State::Null);
+    assert_no_error(&remote);
+}
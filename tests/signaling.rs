@@ -0,0 +1,142 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use std::sync::{mpsc, Mutex};
+
+mod common;
+use common::*;
+
+type Signal = (String, String, String);
+
+fn watch_signals(element: &gst::Element) -> mpsc::Receiver<Signal> {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    element.connect("signal-received", false, move |args| {
+        let signal = (
+            args[1].get::<String>().unwrap(),
+            args[2].get::<String>().unwrap(),
+            args[3].get::<String>().unwrap(),
+        );
+        let _ = sender.lock().unwrap().send(signal);
+        None
+    });
+    receiver
+}
+
+fn send_signal(element: &gst::Element, signal_type: &str, data: &str, to: Option<&str>) -> bool {
+    element.emit_by_name::<bool>("send-signal", &[&signal_type, &data, &to])
+}
+
+#[test]
+fn send_and_receive_signals() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let sink_signals = watch_signals(&sink);
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+    let sink_connection_id = wait_for_message(&publisher, "opentok/stream-created")
+        .get::<String>("connection-id")
+        .unwrap();
+
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    src.set_property("signal-stream", true);
+    pipeline.add(&src).unwrap();
+    let src_signals = watch_signals(&src);
+
+    let appsink = gst::ElementFactory::make("appsink", None).unwrap();
+    appsink.set_property("sync", false);
+    appsink.set_property("async", false);
+    pipeline.add(&appsink).unwrap();
+    let appsink_pad = appsink.static_pad("sink").unwrap();
+    src.connect_pad_added(move |src, pad| {
+        if pad.name() == "signal_stream" {
+            pad.link(&appsink_pad).unwrap();
+            return;
+        }
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+    });
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    wait_for_message(&pipeline, "opentok/connected");
+
+    // Broadcast signals reach every connection, the sender included.
+    assert!(send_signal(&sink, "chat", "hello", None));
+    let expected = (
+        "chat".to_string(),
+        "hello".to_string(),
+        sink_connection_id.clone(),
+    );
+    assert_eq!(src_signals.recv_timeout(TIMEOUT).unwrap(), expected);
+    assert_eq!(sink_signals.recv_timeout(TIMEOUT).unwrap(), expected);
+
+    let message = wait_for_message(&pipeline, "opentok/signal-received");
+    assert_eq!(message.get::<String>("type").unwrap(), "chat");
+    assert_eq!(message.get::<String>("data").unwrap(), "hello");
+    assert_eq!(
+        message.get::<String>("connection-id").unwrap(),
+        sink_connection_id
+    );
+
+    let sample = appsink
+        .downcast_ref::<gst_app::AppSink>()
+        .unwrap()
+        .try_pull_sample(gst::ClockTime::from_seconds(TIMEOUT.as_secs()))
+        .expect("No signal buffer");
+    assert_eq!(
+        sample.caps().unwrap().structure(0).unwrap().name(),
+        "application/x-json"
+    );
+    let buffer = sample.buffer().unwrap().map_readable().unwrap();
+    let signal = json::parse(std::str::from_utf8(&buffer).unwrap()).unwrap();
+    assert_eq!(signal["type"], "chat");
+    assert_eq!(signal["data"], "hello");
+    assert_eq!(signal["connectionId"], sink_connection_id.as_str());
+
+    // Targeted signals only reach their recipient.
+    assert!(send_signal(
+        &src,
+        "control",
+        "mute",
+        Some(&sink_connection_id)
+    ));
+    let (signal_type, data, _) = sink_signals.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!((signal_type.as_str(), data.as_str()), ("control", "mute"));
+    assert!(src_signals
+        .recv_timeout(std::time::Duration::from_millis(500))
+        .is_err());
+
+    assert!(!send_signal(&src, "control", "mute", Some("unknown")));
+
+    assert_no_error(&publisher);
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+    set_state_and_wait(&publisher, gst::State::Null);
+}
+
+#[test]
+fn signal_without_session() {
+    init();
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    assert!(!send_signal(&sink, "chat", "hello", None));
+}
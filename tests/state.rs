@@ -0,0 +1,162 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::SessionState;
+use std::sync::{mpsc, Mutex};
+
+mod common;
+use common::*;
+
+/// Reports the `session-state` of `element` on each notification.
+fn watch_states(element: &gst::Element) -> mpsc::Receiver<SessionState> {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    element.connect_notify(Some("session-state"), move |element, _| {
+        let state = element.property::<SessionState>("session-state");
+        let _ = sender.lock().unwrap().send(state);
+    });
+    receiver
+}
+
+/// Waits until `states` reports `expected`, returning the states seen
+/// before.
+fn wait_for_state(
+    states: &mpsc::Receiver<SessionState>,
+    expected: SessionState,
+) -> Vec<SessionState> {
+    let mut seen = vec![];
+    loop {
+        let state = states
+            .recv_timeout(TIMEOUT)
+            .unwrap_or_else(|_| panic!("No {:?} state, got {:?}", expected, seen));
+        if state == expected {
+            return seen;
+        }
+        seen.push(state);
+    }
+}
+
+#[test]
+fn sink_states() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    assert_eq!(
+        sink.property::<SessionState>("session-state"),
+        SessionState::Idle
+    );
+    let states = watch_states(&sink);
+    sink.set_property("reconnect-initial-delay", 10u32);
+    sink.set_property("location", &location("opentok", &session_id));
+
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    let seen = wait_for_state(&states, SessionState::Publishing);
+    assert!(
+        seen.ends_with(&[SessionState::Connecting, SessionState::Connected]),
+        "{:?}",
+        seen
+    );
+
+    // The state changes are posted on the bus as well.
+    let bus = pipeline.bus().unwrap();
+    let msg = bus
+        .iter_timed_filtered(
+            gst::ClockTime::from_seconds(TIMEOUT.as_secs()),
+            &[gst::MessageType::Element],
+        )
+        .find(|msg| {
+            msg.structure().map_or(false, |s| {
+                s.name() == "opentok/session-state-changed"
+                    && s.get::<SessionState>("new-state").ok() == Some(SessionState::Publishing)
+            })
+        })
+        .expect("No state change message");
+    assert_eq!(
+        msg.structure()
+            .unwrap()
+            .get::<SessionState>("old-state")
+            .unwrap(),
+        SessionState::Connected
+    );
+
+    MOCK.drop_connections(&session_id);
+    wait_for_state(&states, SessionState::Reconnecting);
+    wait_for_state(&states, SessionState::Connected);
+    wait_for_state(&states, SessionState::Publishing);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+    wait_for_state(&states, SessionState::Idle);
+}
+
+#[test]
+fn src_states() {
+    init();
+
+    let session_id = new_session_id();
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", &location("opentok", &session_id));
+    pipeline.add(&src).unwrap();
+    src.connect_pad_added(|src, pad| {
+        let pipeline = src
+            .parent()
+            .and_then(|p| p.downcast::<gst::Pipeline>().ok())
+            .unwrap();
+        let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+        sink.set_property("sync", false);
+        sink.set_property("async", false);
+        pipeline.add(&sink).unwrap();
+        sink.sync_state_with_parent().unwrap();
+        pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+    });
+    let states = watch_states(&src);
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    wait_for_state(&states, SessionState::Connected);
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", &location("opentok", &session_id));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+    wait_for_state(&states, SessionState::Subscribing);
+
+    set_state_and_wait(&publisher, gst::State::Null);
+    wait_for_state(&states, SessionState::Connected);
+
+    assert_no_error(&pipeline);
+    set_state_and_wait(&pipeline, gst::State::Null);
+    wait_for_state(&states, SessionState::Idle);
+}
+
+#[test]
+fn failed_state() {
+    init();
+
+    let server = RoomServer::start("{}", usize::MAX);
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("demo-room-uri", format!("{}/room/test", server.url()));
+    pipeline.add(&src).unwrap();
+    let states = watch_states(&src);
+
+    let _ = pipeline.set_state(gst::State::Playing);
+    let seen = wait_for_state(&states, SessionState::Failed);
+    assert_eq!(seen, vec![SessionState::LoadingCredentials]);
+    wait_for_error(&pipeline);
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert_eq!(
+        src.property::<SessionState>("session-state"),
+        SessionState::Idle
+    );
+}
@@ -0,0 +1,250 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::token::{
+    generate_token, token_expire_time, token_is_expired, Role, TokenOptions, TOKEN_REQUEST_MESSAGE,
+    TOKEN_SENTINEL,
+};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod common;
+use common::*;
+
+const API_KEY: &str = "123456";
+const API_SECRET: &str = "1234567890abcdef1234567890abcdef12345678";
+
+/// Splits a token into its partner id, signature and signed data.
+fn decode(token: &str) -> (String, String, String) {
+    let payload = token.strip_prefix(TOKEN_SENTINEL).unwrap();
+    let payload = String::from_utf8(base64::decode(payload).unwrap()).unwrap();
+    let (header, data) = payload.split_once(':').unwrap();
+    let header: HashMap<_, _> = url::form_urlencoded::parse(header.as_bytes())
+        .into_owned()
+        .collect();
+    (
+        header["partner_id"].clone(),
+        header["sig"].clone(),
+        data.into(),
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+fn signed_token() {
+    let expire_time = now() + 3600;
+    let options = TokenOptions {
+        role: Role::Subscriber,
+        expire_time: Some(expire_time),
+        connection_data: Some("name=Jane & Joe".into()),
+    };
+    let token = generate_token(API_KEY, API_SECRET, "1_session", &options).unwrap();
+
+    let (partner_id, signature, data) = decode(&token);
+    assert_eq!(partner_id, API_KEY);
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(API_SECRET.as_bytes()).unwrap();
+    mac.update(data.as_bytes());
+    let expected: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    assert_eq!(signature, expected);
+
+    let data: HashMap<_, _> = url::form_urlencoded::parse(data.as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!(data["session_id"], "1_session");
+    assert_eq!(data["role"], "subscriber");
+    assert_eq!(data["expire_time"], expire_time.to_string());
+    assert_eq!(data["connection_data"], "name=Jane & Joe");
+    assert!(data["create_time"].parse::<u64>().unwrap() <= now());
+    assert!(data.contains_key("nonce"));
+}
+
+#[test]
+fn default_options() {
+    let token = generate_token(API_KEY, API_SECRET, "1_session", &Default::default()).unwrap();
+    let (_, _, data) = decode(&token);
+    let data: HashMap<_, _> = url::form_urlencoded::parse(data.as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!(data["role"], "publisher");
+    let create_time = data["create_time"].parse::<u64>().unwrap();
+    let expire_time = data["expire_time"].parse::<u64>().unwrap();
+    assert_eq!(expire_time - create_time, 24 * 60 * 60);
+    assert!(!data.contains_key("connection_data"));
+}
+
+#[test]
+fn invalid_options() {
+    assert!(generate_token(API_KEY, "", "1_session", &Default::default()).is_err());
+
+    let past = TokenOptions {
+        expire_time: Some(now() - 1),
+        ..Default::default()
+    };
+    assert!(generate_token(API_KEY, API_SECRET, "1_session", &past).is_err());
+
+    let too_late = TokenOptions {
+        expire_time: Some(now() + 31 * 24 * 60 * 60),
+        ..Default::default()
+    };
+    assert!(generate_token(API_KEY, API_SECRET, "1_session", &too_late).is_err());
+
+    let too_long = TokenOptions {
+        connection_data: Some("x".repeat(1001)),
+        ..Default::default()
+    };
+    assert!(generate_token(API_KEY, API_SECRET, "1_session", &too_long).is_err());
+
+    assert!("admin".parse::<Role>().is_err());
+}
+
+#[test]
+fn sink_publishes_with_generated_token() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("api-key", API_KEY);
+    sink.set_property("session-id", &session_id);
+    sink.set_property("api-secret", API_SECRET);
+    sink.set_property("role", "moderator");
+    assert_eq!(sink.property::<String>("role"), "moderator");
+
+    let pipeline = publisher_pipeline(&sink);
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert_no_error(&pipeline);
+}
+
+/// A token that expired at the very beginning of the Unix epoch.
+fn expired_token(session_id: &str) -> String {
+    let data = format!(
+        "session_id={}&create_time=0&expire_time=1&role=publisher",
+        session_id
+    );
+    let payload = format!("partner_id={}&sig=0:{}", API_KEY, data);
+    format!("{}{}", TOKEN_SENTINEL, base64::encode(payload))
+}
+
+#[test]
+fn expire_time_decoding() {
+    let expire_time = now() + 3600;
+    let options = TokenOptions {
+        expire_time: Some(expire_time),
+        ..Default::default()
+    };
+    let token = generate_token(API_KEY, API_SECRET, "1_session", &options).unwrap();
+    assert_eq!(token_expire_time(&token), Some(expire_time));
+    assert!(!token_is_expired(&token));
+
+    assert_eq!(token_expire_time(&expired_token("1_session")), Some(1));
+    assert!(token_is_expired(&expired_token("1_session")));
+
+    assert_eq!(token_expire_time("mock-token"), None);
+    assert!(!token_is_expired("mock-token"));
+}
+
+#[test]
+fn request_token_on_auth_failure() {
+    init();
+
+    let session_id = new_session_id();
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    sink.connect("request-token", false, move |args| {
+        let reason = args[1].get::<String>().unwrap();
+        let expire_time = args[2].get::<u64>().unwrap();
+        let _ = sender.lock().unwrap().send((reason, expire_time));
+        None
+    });
+
+    // The sink connects as soon as its credentials are complete, make sure
+    // the request message ends up on the pipeline bus.
+    let pipeline = publisher_pipeline(&sink);
+    sink.set_property("api-key", API_KEY);
+    sink.set_property("session-id", &session_id);
+    sink.set_property("token", &expired_token(&session_id));
+    assert_eq!(sink.property::<u64>("token-expire-time"), 1);
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+
+    let (reason, expire_time) = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(reason, "auth-failure");
+    assert_eq!(expire_time, 1);
+
+    let bus = pipeline.bus().unwrap();
+    let msg = bus
+        .timed_pop_filtered(
+            gst::ClockTime::from_seconds(TIMEOUT.as_secs()),
+            &[gst::MessageType::Element],
+        )
+        .unwrap();
+    let s = msg.structure().unwrap();
+    assert_eq!(s.name(), TOKEN_REQUEST_MESSAGE);
+    assert_eq!(s.get::<String>("reason").unwrap(), "auth-failure");
+    assert!(MOCK.streams(&session_id).is_empty());
+
+    // The fresh token is used right away, without restarting the pipeline.
+    sink.set_property("token", "mock-token");
+    assert!(wait_until(|| MOCK.streams(&session_id).len() == 1));
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert_no_error(&pipeline);
+}
+
+#[test]
+fn request_token_ahead_of_expiry() {
+    init();
+
+    let session_id = new_session_id();
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("api-key", API_KEY);
+    src.set_property("session-id", &session_id);
+    src.set_property("api-secret", API_SECRET);
+    // Well within the warning margin.
+    src.set_property("expire-time", now() + 60);
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect("request-token", false, move |args| {
+        let reason = args[1].get::<String>().unwrap();
+        let _ = sender.lock().unwrap().send(reason);
+        None
+    });
+
+    let pipeline = gst::Pipeline::new(None);
+    pipeline.add(&src).unwrap();
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    assert!(wait_until(|| MOCK.connection_count(&session_id) == 1));
+    assert!(src.property::<u64>("token-expire-time") > 0);
+
+    assert_eq!(receiver.recv_timeout(TIMEOUT).unwrap(), "expiring");
+
+    set_state_and_wait(&pipeline, gst::State::Null);
+    assert_no_error(&pipeline);
+}
@@ -0,0 +1,196 @@
+// Copyright (C) 2021 Fernando Jimenez Moreno <fjimenez@igalia.com>
+// Copyright (C) 2021-2022 Philippe Normand <philn@igalia.com>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use gstopentok::common::{Credentials, OpenTokUri, Secret};
+use std::sync::{mpsc, Mutex};
+
+mod common;
+use common::*;
+
+const FULL: &str = "opentok://1_session/stream-1?key=123456&token=T1%3D%3Dxyz%2B%2F\
+                    &name=Jane+%26+Joe&audio-only=true&resolution=640x480";
+
+#[test]
+fn round_trip() {
+    let uri = FULL.parse::<OpenTokUri>().unwrap();
+    assert_eq!(
+        uri,
+        OpenTokUri {
+            remote: false,
+            session_id: "1_session".into(),
+            stream_id: Some("stream-1".into()),
+            api_key: Some("123456".into()),
+            token: Some(Secret::from("T1==xyz+/")),
+            stream_name: Some("Jane & Joe".into()),
+            audio_only: true,
+            video_only: false,
+            resolution: Some((640, 480)),
+        }
+    );
+    assert_eq!(uri.to_uri(), FULL);
+    assert_eq!(uri.to_uri().parse::<OpenTokUri>().unwrap(), uri);
+
+    let remote = "opentok-remote://1_session?key=123456&token=abc&video-only=true"
+        .parse::<OpenTokUri>()
+        .unwrap();
+    assert!(remote.remote);
+    assert!(remote.video_only);
+    assert_eq!(remote.stream_id, None);
+
+    let bare = "opentok://1_session".parse::<OpenTokUri>().unwrap();
+    assert_eq!(bare.to_uri(), "opentok://1_session");
+}
+
+#[test]
+fn redacted() {
+    let uri = FULL.parse::<OpenTokUri>().unwrap();
+    let redacted = uri.redacted();
+    assert!(!redacted.contains("xyz"));
+    assert!(redacted.contains("key=123456"));
+    assert!(redacted.contains("name=Jane+%26+Joe"));
+    assert_eq!(uri.to_string(), redacted);
+}
+
+#[test]
+fn invalid() {
+    for location in [
+        "http://1_session?key=123456",
+        "opentok://?key=123456",
+        "opentok://1_session/stream/extra",
+        "opentok://1_session?key=123456&secret=abc",
+        "opentok://1_session?audio-only=true&video-only=true",
+        "opentok://1_session?audio-only=maybe",
+        "opentok://1_session?resolution=640",
+        "opentok://1_session?resolution=0x480",
+    ] {
+        assert!(location.parse::<OpenTokUri>().is_err(), "{}", location);
+    }
+}
+
+#[test]
+fn credentials() {
+    let uri = FULL.parse::<OpenTokUri>().unwrap();
+    let mut credentials = Credentials::from(&uri);
+    assert_eq!(credentials.api_key().unwrap(), "123456");
+    assert_eq!(credentials.session_id().unwrap(), "1_session");
+    assert_eq!(credentials.token().unwrap().expose(), "T1==xyz+/");
+    assert_eq!(credentials.stream_id().unwrap(), "stream-1");
+    assert_eq!(credentials.to_uri(&uri).unwrap(), uri);
+
+    credentials.set_stream_id("stream-2".into()).unwrap();
+    assert_eq!(credentials.stream_id().unwrap(), "stream-2");
+    assert_eq!(credentials.api_key().unwrap(), "123456");
+}
+
+#[test]
+fn element_uri_round_trip() {
+    init();
+
+    for (factory, scheme) in [
+        ("opentoksrc", "opentok"),
+        ("opentoksink", "opentok"),
+        ("opentoksrc-remote", "opentok-remote"),
+        ("opentoksink-remote", "opentok-remote"),
+    ] {
+        let location = format!(
+            "{}://{}/stream-1?key=mock-key&token=mock-token&name=cam&video-only=true&resolution=320x240",
+            scheme,
+            new_session_id()
+        );
+        let element = gst::ElementFactory::make(factory, None).unwrap();
+        let handler = element.dynamic_cast_ref::<gst::URIHandler>().unwrap();
+        handler.set_uri(&location).unwrap();
+        assert_eq!(
+            handler.uri().as_deref(),
+            Some(location.as_str()),
+            "{}",
+            factory
+        );
+
+        let other = gst::ElementFactory::make(factory, None).unwrap();
+        other.set_property("location", element.property::<String>("location"));
+        assert_eq!(
+            other.property::<String>("location"),
+            location,
+            "{}",
+            factory
+        );
+    }
+}
+
+/// Plays an `opentoksrc` in a new pipeline, its pads linked to fakesinks,
+/// and reports the names of the pads it adds.
+fn play_src(location: &str) -> (gst::Pipeline, gst::Element, mpsc::Receiver<String>) {
+    let pipeline = gst::Pipeline::new(None);
+    let src = gst::ElementFactory::make("opentoksrc", None).unwrap();
+    src.set_property("location", location);
+    pipeline.add(&src).unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    src.connect_pad_added({
+        let pipeline = pipeline.downgrade();
+        move |_, pad| {
+            let pipeline = match pipeline.upgrade() {
+                Some(pipeline) => pipeline,
+                None => return,
+            };
+            let sink = gst::ElementFactory::make("fakesink", None).unwrap();
+            sink.set_property("sync", false);
+            sink.set_property("async", false);
+            pipeline.add(&sink).unwrap();
+            sink.sync_state_with_parent().unwrap();
+            pad.link(&sink.static_pad("sink").unwrap()).unwrap();
+            let _ = sender.lock().unwrap().send(pad.name().to_string());
+        }
+    });
+
+    set_state_and_wait(&pipeline, gst::State::Playing);
+    (pipeline, src, receiver)
+}
+
+#[test]
+fn subscriber_options() {
+    init();
+
+    let session_id = new_session_id();
+    let base = location("opentok", &session_id);
+
+    // The mock notifies connections in the order they joined, so by the time
+    // the last subscriber gets its video pad, the other ones have seen the
+    // stream as well.
+    let (other_name, other_name_src, _) = play_src(&format!("{}&name=screen", base));
+    let (audio_only, audio_only_src, _) = play_src(&format!("{}&audio-only=true", base));
+    let (video_only, video_only_src, _) = play_src(&format!("{}&video-only=true", base));
+    assert!(video_only_src.static_pad("audio_stream").is_none());
+    let (named, _, named_pads) = play_src(&format!("{}&name=camera", base));
+
+    let sink = gst::ElementFactory::make("opentoksink", None).unwrap();
+    sink.set_property("location", format!("{}&name=camera", base));
+    let publisher = publisher_pipeline(&sink);
+    set_state_and_wait(&publisher, gst::State::Playing);
+
+    assert!(std::iter::from_fn(|| named_pads.recv_timeout(TIMEOUT).ok())
+        .any(|name| name == "video_stream_0"));
+    assert_eq!(MOCK.streams(&session_id)[0].name, "camera");
+    assert!(other_name_src.static_pad("video_stream_0").is_none());
+    assert!(audio_only_src.static_pad("video_stream_0").is_none());
+    assert!(audio_only_src.static_pad("audio_stream").is_some());
+    assert!(wait_until(|| video_only_src
+        .static_pad("video_stream_0")
+        .is_some()));
+
+    set_state_and_wait(&publisher, gst::State::Null);
+    assert_no_error(&publisher);
+    for pipeline in [other_name, audio_only, video_only, named] {
+        assert_no_error(&pipeline);
+        set_state_and_wait(&pipeline, gst::State::Null);
+    }
+}